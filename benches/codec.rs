@@ -0,0 +1,139 @@
+use bytes::{Bytes, BytesMut};
+use criterion::{criterion_group, criterion_main, BenchmarkId, Criterion, Throughput};
+use serde_json::json;
+use tokio_codec::{Decoder, Encoder};
+use tower_lsp::LanguageServerCodec;
+
+/// Number of small frames packed into a single read buffer, as when a client sends a burst of
+/// `didChange` notifications while typing.
+const BURST_LEN: usize = 64;
+
+/// Returns message bodies resembling the frames typically exchanged with an editor.
+fn messages() -> Vec<(&'static str, String)> {
+    let small = json!({
+        "jsonrpc": "2.0",
+        "method": "textDocument/hover",
+        "params": {
+            "textDocument": { "uri": "file:///src/main.rs" },
+            "position": { "line": 12, "character": 8 },
+        },
+        "id": 1,
+    });
+
+    let items: Vec<_> = (0..40)
+        .map(|i| {
+            json!({
+                "label": format!("method_{}", i),
+                "kind": 2,
+                "detail": "fn(&self, value: usize) -> Option<String>",
+                "sortText": format!("{:04}", i),
+            })
+        })
+        .collect();
+    let medium = json!({
+        "jsonrpc": "2.0",
+        "result": { "isIncomplete": false, "items": items },
+        "id": 2,
+    });
+
+    let line = "    let value = compute(&input).unwrap_or_default(); // keep going\n";
+    let large = did_open(line.repeat(16 * 1024));
+
+    let line = "    let grüße = \"こんにちは, 世界\"; // 😀 multibyte\n";
+    let multibyte = did_open(line.repeat(1024));
+
+    vec![
+        ("small", small.to_string()),
+        ("medium", medium.to_string()),
+        ("large", large.to_string()),
+        ("multibyte", multibyte.to_string()),
+    ]
+}
+
+fn did_open(text: String) -> serde_json::Value {
+    json!({
+        "jsonrpc": "2.0",
+        "method": "textDocument/didOpen",
+        "params": {
+            "textDocument": {
+                "uri": "file:///src/main.rs",
+                "languageId": "rust",
+                "version": 1,
+                "text": text,
+            },
+        },
+    })
+}
+
+fn encode(message: &str) -> BytesMut {
+    let mut codec = LanguageServerCodec::<String>::default();
+    let mut buffer = BytesMut::new();
+    codec.encode(message.to_owned(), &mut buffer).unwrap();
+    buffer
+}
+
+fn bench_encode(c: &mut Criterion) {
+    let mut group = c.benchmark_group("encode");
+    for (name, message) in messages() {
+        group.throughput(Throughput::Bytes(message.len() as u64));
+        group.bench_with_input(BenchmarkId::from_parameter(name), &message, |b, message| {
+            let mut codec = LanguageServerCodec::<String>::default();
+            let mut buffer = BytesMut::with_capacity(message.len() + 64);
+            b.iter(|| {
+                buffer.clear();
+                codec.encode(message.clone(), &mut buffer).unwrap();
+            })
+        });
+    }
+    group.finish();
+}
+
+fn bench_decode(c: &mut Criterion) {
+    let mut group = c.benchmark_group("decode");
+    for (name, message) in messages() {
+        let frame = encode(&message).freeze();
+        group.throughput(Throughput::Bytes(frame.len() as u64));
+        group.bench_with_input(BenchmarkId::new("string", name), &frame, |b, frame| {
+            let mut codec = LanguageServerCodec::<String>::default();
+            b.iter(|| {
+                let mut buffer = BytesMut::from(frame.clone());
+                codec.decode(&mut buffer).unwrap().unwrap()
+            })
+        });
+        group.bench_with_input(BenchmarkId::new("bytes", name), &frame, |b, frame| {
+            let mut codec = LanguageServerCodec::<Bytes>::default();
+            b.iter(|| {
+                let mut buffer = BytesMut::from(frame.clone());
+                codec.decode(&mut buffer).unwrap().unwrap()
+            })
+        });
+    }
+    group.finish();
+}
+
+fn bench_decode_burst(c: &mut Criterion) {
+    let (_, message) = messages().swap_remove(0);
+    let mut burst = BytesMut::new();
+    for _ in 0..BURST_LEN {
+        burst.extend_from_slice(&encode(&message));
+    }
+    let burst = burst.freeze();
+
+    let mut group = c.benchmark_group("decode_burst");
+    group.throughput(Throughput::Bytes(burst.len() as u64));
+    group.bench_function("small", |b| {
+        let mut codec = LanguageServerCodec::<String>::default();
+        b.iter(|| {
+            let mut buffer = BytesMut::from(burst.clone());
+            let mut count = 0;
+            while codec.decode(&mut buffer).unwrap().is_some() {
+                count += 1;
+            }
+            assert_eq!(count, BURST_LEN);
+        })
+    });
+    group.finish();
+}
+
+criterion_group!(benches, bench_encode, bench_decode, bench_decode_burst);
+criterion_main!(benches);
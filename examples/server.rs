@@ -9,7 +9,7 @@ struct Backend;
 
 impl LanguageServer for Backend {
     type ShutdownFuture = BoxFuture<()>;
-    type SymbolFuture = BoxFuture<Option<Vec<SymbolInformation>>>;
+    type SymbolFuture = BoxFuture<Option<WorkspaceSymbolResponse>>;
     type ExecuteFuture = BoxFuture<Option<Value>>;
     type CompletionFuture = BoxFuture<Option<CompletionResponse>>;
     type HoverFuture = BoxFuture<Option<Hover>>;
@@ -19,36 +19,36 @@ impl LanguageServer for Backend {
         Ok(InitializeResult {
             capabilities: ServerCapabilities {
                 text_document_sync: Some(TextDocumentSyncCapability::Kind(
-                    TextDocumentSyncKind::Incremental,
+                    TextDocumentSyncKind::INCREMENTAL,
                 )),
-                hover_provider: Some(true),
+                hover_provider: Some(HoverProviderCapability::Simple(true)),
                 completion_provider: Some(CompletionOptions {
                     resolve_provider: Some(false),
                     trigger_characters: Some(vec![".".to_string()]),
+                    ..CompletionOptions::default()
                 }),
-                signature_help_provider: Some(SignatureHelpOptions {
-                    trigger_characters: None,
-                }),
-                document_highlight_provider: Some(true),
-                workspace_symbol_provider: Some(true),
+                signature_help_provider: Some(SignatureHelpOptions::default()),
+                document_highlight_provider: Some(OneOf::Left(true)),
+                workspace_symbol_provider: Some(OneOf::Left(true)),
                 execute_command_provider: Some(ExecuteCommandOptions {
                     commands: vec!["dummy.do_something".to_string()],
+                    ..ExecuteCommandOptions::default()
                 }),
-                workspace: Some(WorkspaceCapability {
-                    workspace_folders: Some(WorkspaceFolderCapability {
+                workspace: Some(WorkspaceServerCapabilities {
+                    workspace_folders: Some(WorkspaceFoldersServerCapabilities {
                         supported: Some(true),
-                        change_notifications: Some(
-                            WorkspaceFolderCapabilityChangeNotifications::Bool(true),
-                        ),
+                        change_notifications: Some(OneOf::Left(true)),
                     }),
+                    file_operations: None,
                 }),
                 ..ServerCapabilities::default()
             },
+            ..InitializeResult::default()
         })
     }
 
     fn initialized(&self, printer: &Printer, _: InitializedParams) {
-        printer.log_message(MessageType::Info, "server initialized!");
+        printer.log_message(MessageType::INFO, "server initialized!");
     }
 
     fn shutdown(&self) -> Self::ShutdownFuture {
@@ -60,48 +60,48 @@ impl LanguageServer for Backend {
     }
 
     fn did_change_workspace_folders(&self, printer: &Printer, _: DidChangeWorkspaceFoldersParams) {
-        printer.log_message(MessageType::Info, "workspace folders changed!");
+        printer.log_message(MessageType::INFO, "workspace folders changed!");
     }
 
     fn did_change_configuration(&self, printer: &Printer, _: DidChangeConfigurationParams) {
-        printer.log_message(MessageType::Info, "configuration changed!");
+        printer.log_message(MessageType::INFO, "configuration changed!");
     }
 
     fn did_change_watched_files(&self, printer: &Printer, _: DidChangeWatchedFilesParams) {
-        printer.log_message(MessageType::Info, "watched files have changed!");
+        printer.log_message(MessageType::INFO, "watched files have changed!");
     }
 
     fn execute_command(&self, printer: &Printer, _: ExecuteCommandParams) -> Self::ExecuteFuture {
-        printer.log_message(MessageType::Info, "command executed!");
+        printer.log_message(MessageType::INFO, "command executed!");
         printer.apply_edit(WorkspaceEdit::default());
         Box::new(future::ok(None))
     }
 
     fn did_open(&self, printer: &Printer, _: DidOpenTextDocumentParams) {
-        printer.log_message(MessageType::Info, "file opened!");
+        printer.log_message(MessageType::INFO, "file opened!");
     }
 
     fn did_change(&self, printer: &Printer, _: DidChangeTextDocumentParams) {
-        printer.log_message(MessageType::Info, "file changed!");
+        printer.log_message(MessageType::INFO, "file changed!");
     }
 
     fn did_save(&self, printer: &Printer, _: DidSaveTextDocumentParams) {
-        printer.log_message(MessageType::Info, "file saved!");
+        printer.log_message(MessageType::INFO, "file saved!");
     }
 
     fn did_close(&self, printer: &Printer, _: DidCloseTextDocumentParams) {
-        printer.log_message(MessageType::Info, "file closed!");
+        printer.log_message(MessageType::INFO, "file closed!");
     }
 
     fn completion(&self, _: CompletionParams) -> Self::CompletionFuture {
         Box::new(future::ok(None))
     }
 
-    fn hover(&self, _: TextDocumentPositionParams) -> Self::HoverFuture {
+    fn hover(&self, _: HoverParams) -> Self::HoverFuture {
         Box::new(future::ok(None))
     }
 
-    fn document_highlight(&self, _: TextDocumentPositionParams) -> Self::HighlightFuture {
+    fn document_highlight(&self, _: DocumentHighlightParams) -> Self::HighlightFuture {
         Box::new(future::ok(None))
     }
 }
@@ -112,7 +112,7 @@ fn main() {
     let stdin = tokio::io::stdin();
     let stdout = tokio::io::stdout();
 
-    let (service, messages) = LspService::new(Backend::default());
+    let (service, messages) = LspService::new(Backend);
     let handle = service.close_handle();
     let server = Server::new(stdin, stdout)
         .interleave(messages)
@@ -1,21 +1,34 @@
 //! Encoder and decoder for Language Server Protocol messages.
 
 use std::error::Error;
-use std::fmt::{Display, Formatter, Result as FmtResult};
-use std::io::{Error as IoError, Write};
+use std::fmt::{self, Debug, Display, Formatter, Result as FmtResult};
+use std::io::{self, Error as IoError, ErrorKind as IoErrorKind, Read, Write};
+use std::marker::PhantomData;
 use std::str::{self, Utf8Error};
+use std::sync::{Arc, Mutex};
+#[cfg(feature = "metrics")]
+use std::time::{Duration, Instant};
 
-use bytes::{BufMut, BytesMut};
-use nom::branch::alt;
+use bytes::{BufMut, Bytes, BytesMut};
+use jsonrpc_core::types::Id;
+use log::error;
+use lsp_types::TraceValue;
 use nom::bytes::streaming::{is_not, tag};
 use nom::character::streaming::{char, crlf, digit1, space0};
-use nom::combinator::{map, map_res, opt};
+use nom::combinator::{cut, map_res, opt, verify};
 use nom::error::ErrorKind;
-use nom::multi::length_data;
-use nom::sequence::{delimited, terminated, tuple};
-use nom::{Err, IResult, Needed};
+use nom::multi::many0;
+use nom::sequence::{delimited, preceded, separated_pair, terminated, tuple};
+use nom::{Err, IResult};
 use tokio_codec::{Decoder, Encoder};
 
+use super::delegate::Printer;
+
+const DEFAULT_MAX_HEADER_LINE_LEN: usize = 8 * 1024;
+
+/// Number of bytes dumped on either side of the position at which decoding failed.
+const ERROR_CONTEXT_LEN: usize = 32;
+
 /// Errors that can occur when processing an LSP request.
 #[derive(Debug)]
 pub enum ParseError {
@@ -25,6 +38,13 @@ pub enum ParseError {
     InvalidLength,
     /// The media type in the `Content-Type` header is invalid.
     InvalidType,
+    /// A single header line exceeds the maximum permitted length.
+    HeaderLineTooLong,
+    /// The message body exceeds the maximum permitted length and has been skipped.
+    ///
+    /// Contains the declared length of the body and the ID of the request, if the message was a
+    /// request and its ID could be recovered.
+    MessageTooLarge(usize, Option<Id>),
     /// Failed to encode the response.
     Encode(IoError),
     /// Request contains invalid UTF8.
@@ -37,6 +57,10 @@ impl Display for ParseError {
             ParseError::MissingHeader => write!(fmt, "missing required `Content-Length` header"),
             ParseError::InvalidLength => write!(fmt, "unable to parse content length"),
             ParseError::InvalidType => write!(fmt, "unable to parse content type"),
+            ParseError::HeaderLineTooLong => write!(fmt, "header line exceeds maximum length"),
+            ParseError::MessageTooLarge(len, _) => {
+                write!(fmt, "message of {} bytes exceeds maximum length", len)
+            }
             ParseError::Encode(ref e) => write!(fmt, "failed to encode response: {}", e),
             ParseError::Utf8(ref e) => write!(fmt, "request contains invalid UTF8: {}", e),
         }
@@ -65,77 +89,840 @@ impl From<Utf8Error> for ParseError {
     }
 }
 
+/// Shared destination for the raw bytes of every frame passing through a codec.
+#[derive(Clone)]
+pub(crate) struct WireLog(Arc<Mutex<dyn Write + Send>>);
+
+impl WireLog {
+    pub fn new<W: Write + Send + 'static>(writer: W) -> Self {
+        WireLog(Arc::new(Mutex::new(writer)))
+    }
+
+    fn record(&self, marker: &[u8], frame: &[u8]) {
+        let mut writer = self.0.lock().unwrap_or_else(|w| w.into_inner());
+        let result = writer
+            .write_all(marker)
+            .and_then(|_| writer.write_all(frame))
+            .and_then(|_| writer.write_all(b"\n"))
+            .and_then(|_| writer.flush());
+
+        if let Err(e) = result {
+            error!("failed to write to wire log: {}", e);
+        }
+    }
+}
+
+impl Debug for WireLog {
+    fn fmt(&self, fmt: &mut Formatter) -> fmt::Result {
+        fmt.debug_tuple("WireLog").finish()
+    }
+}
+
+/// Receives timing information about the messages processed by a [`LanguageServerCodec`].
+///
+/// [`LanguageServerCodec`]: ./struct.LanguageServerCodec.html
+#[cfg(feature = "metrics")]
+pub trait CodecMetrics: Send + Sync {
+    /// Records that a message body of `len` bytes was decoded, including UTF-8 validation.
+    fn record_decode(&self, len: usize, elapsed: Duration);
+
+    /// Records that a message body of `len` bytes was encoded.
+    fn record_encode(&self, len: usize, elapsed: Duration);
+}
+
+/// Shared hook receiving timing information from one or more codecs.
+#[cfg(feature = "metrics")]
+#[derive(Clone)]
+pub(crate) struct Metrics(pub Arc<dyn CodecMetrics>);
+
+#[cfg(feature = "metrics")]
+impl Debug for Metrics {
+    fn fmt(&self, fmt: &mut Formatter) -> fmt::Result {
+        fmt.debug_tuple("Metrics").finish()
+    }
+}
+
+/// Options controlling how a [`LanguageServerCodec`] parses and formats messages.
+///
+/// The default configuration matches the strict behavior of [`LanguageServerCodec::default`].
+/// Options are set through chained builder methods, so that more of them may be added over time:
+///
+/// ```rust
+/// # use tower_lsp::{CodecConfig, LanguageServerCodec};
+/// let config = CodecConfig::default()
+///     .max_message_len(Some(64 * 1024 * 1024))
+///     .skip_content_type(true);
+/// let codec: LanguageServerCodec = LanguageServerCodec::with_config(config);
+/// ```
+///
+/// [`LanguageServerCodec`]: ./struct.LanguageServerCodec.html
+/// [`LanguageServerCodec::default`]: ./struct.LanguageServerCodec.html#impl-Default
+#[derive(Clone, Debug, Eq, PartialEq)]
+pub struct CodecConfig {
+    max_header_line_len: usize,
+    max_message_len: Option<usize>,
+    skip_content_type: bool,
+    pretty_json: bool,
+}
+
+impl Default for CodecConfig {
+    fn default() -> Self {
+        CodecConfig {
+            max_header_line_len: DEFAULT_MAX_HEADER_LINE_LEN,
+            max_message_len: None,
+            skip_content_type: false,
+            pretty_json: false,
+        }
+    }
+}
+
+impl CodecConfig {
+    /// Sets the maximum permitted length of a single header line, in bytes.
+    ///
+    /// See [`LanguageServerCodec::set_max_header_line_len`] for details.
+    ///
+    /// [`LanguageServerCodec::set_max_header_line_len`]: ./struct.LanguageServerCodec.html#method.set_max_header_line_len
+    pub fn max_header_line_len(mut self, max_header_line_len: usize) -> Self {
+        self.max_header_line_len = max_header_line_len;
+        self
+    }
+
+    /// Sets the maximum permitted length of a message body, in bytes.
+    ///
+    /// See [`LanguageServerCodec::set_max_message_len`] for details.
+    ///
+    /// [`LanguageServerCodec::set_max_message_len`]: ./struct.LanguageServerCodec.html#method.set_max_message_len
+    pub fn max_message_len(mut self, max_message_len: Option<usize>) -> Self {
+        self.max_message_len = max_message_len;
+        self
+    }
+
+    /// Sets whether decoding assumes that no `Content-Type` header is ever sent.
+    ///
+    /// See [`LanguageServerCodec::set_skip_content_type`] for details.
+    ///
+    /// [`LanguageServerCodec::set_skip_content_type`]: ./struct.LanguageServerCodec.html#method.set_skip_content_type
+    pub fn skip_content_type(mut self, skip_content_type: bool) -> Self {
+        self.skip_content_type = skip_content_type;
+        self
+    }
+
+    /// Sets whether encoded JSON messages are pretty-printed.
+    ///
+    /// See [`LanguageServerCodec::set_pretty_json`] for details.
+    ///
+    /// [`LanguageServerCodec::set_pretty_json`]: ./struct.LanguageServerCodec.html#method.set_pretty_json
+    pub fn pretty_json(mut self, pretty_json: bool) -> Self {
+        self.pretty_json = pretty_json;
+        self
+    }
+}
+
 /// Encodes and decodes Language Server Protocol messages.
 ///
 /// # Encoding
 ///
 /// If the message length is zero, then the codec will skip encoding the message.
-#[derive(Clone, Debug, Default)]
-pub struct LanguageServerCodec {
+///
+/// As required by the specification, the `Content-Length` header always counts the bytes of the
+/// UTF-8 encoded body, not its characters, in both directions. Bodies containing multibyte
+/// characters, e.g. non-ASCII identifiers or emoji, are therefore framed correctly.
+///
+/// # Decoding
+///
+/// By default, message bodies are validated as UTF-8 and decoded into a `String`. For trusted
+/// transports, `LanguageServerCodec<Bytes>` skips this check and yields the raw body instead,
+/// deferring all validation to the downstream JSON parser.
+///
+/// Frames are decoded strictly in the order they are received, as befits a single stream. While a
+/// frame is only partially received, any later frame cannot be decoded yet. Its bytes stay in the
+/// buffer and are decoded as soon as the preceding frame is complete.
+///
+/// The `Content-Length` value must be written in plain ASCII digits. Any amount of spaces or tabs
+/// between the colon and the value is accepted, as are leading zeros, but a sign or trailing
+/// whitespace after the number yields `ParseError::InvalidLength`.
+///
+/// Header lines longer than [`max_header_line_len`](#method.max_header_line_len) bytes are
+/// rejected as soon as they are detected, even before their terminating newline is received.
+#[derive(Clone, Debug)]
+pub struct LanguageServerCodec<T = String> {
     remaining_msg_bytes: usize,
+    frame_header: Option<FrameHeader>,
+    config: CodecConfig,
+    skipping: Option<SkippedFrame>,
+    wire_log: Option<WireLog>,
+    trace: Option<Printer>,
+    #[cfg(feature = "metrics")]
+    metrics: Option<Metrics>,
+    _marker: PhantomData<T>,
+}
+
+impl<T> Default for LanguageServerCodec<T> {
+    fn default() -> Self {
+        LanguageServerCodec {
+            remaining_msg_bytes: 0,
+            frame_header: None,
+            config: CodecConfig::default(),
+            skipping: None,
+            wire_log: None,
+            trace: None,
+            #[cfg(feature = "metrics")]
+            metrics: None,
+            _marker: PhantomData,
+        }
+    }
+}
+
+impl<T> LanguageServerCodec<T> {
+    /// Creates a new `LanguageServerCodec` with the given parsing and formatting options.
+    pub fn with_config(config: CodecConfig) -> Self {
+        LanguageServerCodec {
+            config,
+            ..LanguageServerCodec::default()
+        }
+    }
+
+    /// Creates a new `LanguageServerCodec` which resumes decoding a partially received frame.
+    ///
+    /// This is useful when handing off a stream between codec instances mid-frame, using the
+    /// value previously obtained from [`remaining_msg_bytes`](#method.remaining_msg_bytes).
+    pub fn with_remaining_msg_bytes(remaining_msg_bytes: usize) -> Self {
+        LanguageServerCodec {
+            remaining_msg_bytes,
+            ..LanguageServerCodec::default()
+        }
+    }
+
+    /// Returns the minimum number of buffered bytes required before the next decode attempt.
+    ///
+    /// This is zero unless a frame has only been partially received so far. Since the headers of a
+    /// partial frame stay in the buffer until the whole frame is decoded, the count covers the
+    /// entire frame, i.e. both the headers and the body, rather than only its missing bytes.
+    pub fn remaining_msg_bytes(&self) -> usize {
+        self.remaining_msg_bytes
+    }
+
+    /// Sets the minimum number of buffered bytes required before the next decode attempt.
+    ///
+    /// The headers of a partially received frame are parsed again on the next decode attempt.
+    pub fn set_remaining_msg_bytes(&mut self, remaining_msg_bytes: usize) {
+        self.remaining_msg_bytes = remaining_msg_bytes;
+        self.frame_header = None;
+    }
+
+    /// Returns the parsing and formatting options of this codec.
+    pub fn config(&self) -> &CodecConfig {
+        &self.config
+    }
+
+    /// Returns the maximum permitted length of a single header line, in bytes.
+    pub fn max_header_line_len(&self) -> usize {
+        self.config.max_header_line_len
+    }
+
+    /// Sets the maximum permitted length of a single header line, in bytes.
+    ///
+    /// Defaults to 8 KiB.
+    pub fn set_max_header_line_len(&mut self, max_header_line_len: usize) {
+        self.config.max_header_line_len = max_header_line_len;
+    }
+
+    /// Returns the maximum permitted length of a message body, in bytes, if any.
+    pub fn max_message_len(&self) -> Option<usize> {
+        self.config.max_message_len
+    }
+
+    /// Sets the maximum permitted length of a message body, in bytes.
+    ///
+    /// Frames declaring a longer body are skipped without buffering them: their bytes are
+    /// discarded as they arrive, using the declared `Content-Length`. Once the entire frame has
+    /// been skipped, decoding fails with `ParseError::MessageTooLarge`, which carries the ID of
+    /// the request if it could be recovered along the way. Decoding may resume with the next
+    /// frame afterwards. Defaults to `None`, i.e. no limit.
+    pub fn set_max_message_len(&mut self, max_message_len: Option<usize>) {
+        self.config.max_message_len = max_message_len;
+    }
+
+    /// Returns whether decoding assumes that no `Content-Type` header is ever sent.
+    pub fn skip_content_type(&self) -> bool {
+        self.config.skip_content_type
+    }
+
+    /// Sets whether decoding assumes that no `Content-Type` header is ever sent.
+    ///
+    /// When enabled, the `Content-Length` header must be directly followed by the blank line
+    /// ending the headers, which saves parsing work for clients known to never send a
+    /// `Content-Type`. Frames including one are rejected with `ParseError::MissingHeader`.
+    /// Defaults to `false`.
+    pub fn set_skip_content_type(&mut self, skip_content_type: bool) {
+        self.config.skip_content_type = skip_content_type;
+    }
+
+    /// Returns whether encoded JSON messages are pretty-printed.
+    pub fn pretty_json(&self) -> bool {
+        self.config.pretty_json
+    }
+
+    /// Sets whether encoded JSON messages are pretty-printed, for human readability when debugging.
+    ///
+    /// The `Content-Length` header always matches the pretty-printed body. Pretty-printing
+    /// requires re-parsing encoded strings, which sorts the keys of objects, and strings which are
+    /// not valid JSON are sent as is. Defaults to `false`.
+    pub fn set_pretty_json(&mut self, pretty_json: bool) {
+        self.config.pretty_json = pretty_json;
+    }
+
+    /// Tees the raw bytes of every encoded and decoded frame into the given log.
+    ///
+    /// Received frames are prefixed with `<-- ` and sent frames are prefixed with `--> `.
+    pub(crate) fn set_wire_log(&mut self, wire_log: WireLog) {
+        self.wire_log = Some(wire_log);
+    }
+
+    /// Logs a hex dump of the bytes around the failure whenever decoding fails, as long as the
+    /// client has set the trace level of the given printer to verbose.
+    pub(crate) fn set_trace(&mut self, printer: Printer) {
+        self.trace = Some(printer);
+    }
+
+    /// Reports the time spent encoding and decoding each message to the given metrics hook.
+    ///
+    /// Only available with the `metrics` feature enabled.
+    #[cfg(feature = "metrics")]
+    pub fn set_metrics(&mut self, metrics: Arc<dyn CodecMetrics>) {
+        self.metrics = Some(Metrics(metrics));
+    }
+
+    /// Encodes `item` into `dst` like `Encoder::encode`, returning the number of bytes written.
+    ///
+    /// The count covers the entire frame, i.e. both the header and the body. Empty messages are
+    /// skipped, in which case zero is returned.
+    pub fn encode_counted(
+        &mut self,
+        item: String,
+        dst: &mut BytesMut,
+    ) -> Result<usize, ParseError> {
+        if item.is_empty() {
+            return Ok(0);
+        }
+
+        let item = if self.config.pretty_json {
+            prettify(item)
+        } else {
+            item
+        };
+
+        #[cfg(feature = "metrics")]
+        let started = Instant::now();
+
+        let start = dst.len();
+        dst.reserve(item.len() + 30);
+        let mut writer = dst.writer();
+        write!(writer, "Content-Length: {}\r\n\r\n{}", item.len(), item)?;
+        writer.flush()?;
+        debug_check_frame(&dst[start..]);
+        if let Some(ref wire_log) = self.wire_log {
+            wire_log.record(b"--> ", &dst[start..]);
+        }
+
+        #[cfg(feature = "metrics")]
+        {
+            if let Some(ref metrics) = self.metrics {
+                metrics.0.record_encode(item.len(), started.elapsed());
+            }
+        }
+
+        Ok(dst.len() - start)
+    }
+
+    /// Decodes the next complete frame, if any, and converts its body with `convert`.
+    fn decode_with<F, U>(&mut self, src: &mut BytesMut, convert: F) -> Result<Option<U>, ParseError>
+    where
+        F: FnOnce(BytesMut) -> Result<U, ParseError>,
+    {
+        #[cfg(feature = "metrics")]
+        let started = Instant::now();
+
+        let body = match self.decode_frame(src) {
+            Ok(Some(body)) => body,
+            Ok(None) => return Ok(None),
+            Err(e) => {
+                self.log_error_context(&e, src);
+                return Err(e);
+            }
+        };
+
+        #[cfg(feature = "metrics")]
+        let len = body.len();
+        let verbose_body = if self.is_verbose() {
+            Some(body.clone())
+        } else {
+            None
+        };
+        let item = match convert(body) {
+            Ok(item) => item,
+            Err(e) => {
+                if let Some(ref body) = verbose_body {
+                    self.log_error_context(&e, body);
+                }
+                return Err(e);
+            }
+        };
+
+        #[cfg(feature = "metrics")]
+        {
+            if let Some(ref metrics) = self.metrics {
+                metrics.0.record_decode(len, started.elapsed());
+            }
+        }
+
+        Ok(Some(item))
+    }
+
+    fn is_verbose(&self) -> bool {
+        self.trace
+            .as_ref()
+            .is_some_and(|printer| printer.trace_value() == TraceValue::Verbose)
+    }
+
+    /// Logs the bytes around the position in `src` at which decoding failed with `error`, if the
+    /// trace level is verbose.
+    fn log_error_context(&self, error: &ParseError, src: &[u8]) {
+        if self.is_verbose() {
+            if let Some(context) = error_context(error, src) {
+                error!("bytes around decoding error ({}):\n{}", error, context);
+            }
+        }
+    }
+
+    fn decode_frame(&mut self, src: &mut BytesMut) -> Result<Option<BytesMut>, ParseError> {
+        if let Some(ref mut skipped) = self.skipping {
+            let len = skipped.remaining.min(src.len());
+            skipped.scanner.scan(&src[..len]);
+            skipped.remaining -= len;
+            src.advance(len);
+            if skipped.remaining > 0 {
+                return Ok(None);
+            }
+
+            let skipped = self.skipping.take().unwrap();
+            return Err(ParseError::MessageTooLarge(
+                skipped.len,
+                skipped.scanner.request_id(),
+            ));
+        }
+
+        if self.remaining_msg_bytes > src.len() {
+            return Ok(None);
+        }
+
+        // The headers of a large frame are only parsed once, rather than on every attempt until
+        // its body has been received in full.
+        let FrameHeader {
+            header_len,
+            body_len,
+        } = match self.frame_header.take() {
+            Some(header) => header,
+            None => match self.parse_frame_header(src)? {
+                Some(header) => header,
+                None => return Ok(None),
+            },
+        };
+
+        if self
+            .config
+            .max_message_len
+            .is_some_and(|max| body_len > max)
+        {
+            src.advance(header_len);
+            self.remaining_msg_bytes = 0;
+            self.skipping = Some(SkippedFrame {
+                len: body_len,
+                remaining: body_len,
+                scanner: RequestIdScanner::default(),
+            });
+            return self.decode_frame(src);
+        }
+
+        // Wait until the entire frame, including the headers parsed above, has been received.
+        let frame_len = header_len + body_len;
+        if src.len() < frame_len {
+            self.remaining_msg_bytes = frame_len;
+            self.frame_header = Some(FrameHeader {
+                header_len,
+                body_len,
+            });
+            return Ok(None);
+        }
+
+        let mut frame = src.split_to(header_len + body_len);
+        if let Some(ref wire_log) = self.wire_log {
+            wire_log.record(b"<-- ", &frame);
+        }
+
+        frame.advance(header_len);
+        self.remaining_msg_bytes = 0;
+
+        Ok(Some(frame))
+    }
+
+    /// Parses the headers at the start of `src`, returning `None` if they are still incomplete.
+    fn parse_frame_header(&self, src: &[u8]) -> Result<Option<FrameHeader>, ParseError> {
+        check_header_lines(src, self.config.max_header_line_len)?;
+
+        let parse = if self.config.skip_content_type {
+            parse_length_header
+        } else {
+            parse_header
+        };
+
+        match parse(src) {
+            Ok((remaining, body_len)) => Ok(Some(FrameHeader {
+                header_len: src.len() - remaining.len(),
+                body_len,
+            })),
+            Err(Err::Incomplete(_)) => Ok(None),
+            Err(Err::Error((_, err))) | Err(Err::Failure((_, err))) => match err {
+                ErrorKind::Digit | ErrorKind::MapRes => Err(ParseError::InvalidLength),
+                ErrorKind::Char | ErrorKind::IsNot | ErrorKind::Verify => {
+                    Err(ParseError::InvalidType)
+                }
+                _ => Err(ParseError::MissingHeader),
+            },
+        }
+    }
 }
 
-impl Encoder for LanguageServerCodec {
+impl<T> Encoder for LanguageServerCodec<T> {
     type Item = String;
     type Error = ParseError;
 
     fn encode(&mut self, item: Self::Item, dst: &mut BytesMut) -> Result<(), Self::Error> {
-        if !item.is_empty() {
-            dst.reserve(item.len() + 30);
-            let mut writer = dst.writer();
-            write!(writer, "Content-Length: {}\r\n\r\n{}", item.len(), item)?;
-            writer.flush()?;
-        }
-
-        Ok(())
+        self.encode_counted(item, dst).map(|_| ())
     }
 }
 
-impl Decoder for LanguageServerCodec {
+impl Decoder for LanguageServerCodec<String> {
     type Item = String;
     type Error = ParseError;
 
     fn decode(&mut self, src: &mut BytesMut) -> Result<Option<Self::Item>, Self::Error> {
-        if self.remaining_msg_bytes > src.len() {
-            return Ok(None);
+        self.decode_with(src, |body| Ok(str::from_utf8(&body)?.to_string()))
+    }
+}
+
+impl Decoder for LanguageServerCodec<Bytes> {
+    type Item = Bytes;
+    type Error = ParseError;
+
+    fn decode(&mut self, src: &mut BytesMut) -> Result<Option<Self::Item>, Self::Error> {
+        self.decode_with(src, |body| Ok(body.freeze()))
+    }
+}
+
+/// Returns a hex dump of the bytes in `src` around the position at which decoding failed.
+///
+/// Header errors are located at the start of `src`, while UTF-8 errors are located at the first
+/// invalid byte of the message body. Returns `None` for errors which are not caused by the bytes
+/// in `src`.
+fn error_context(error: &ParseError, src: &[u8]) -> Option<String> {
+    let position = match *error {
+        ParseError::Utf8(ref e) => e.valid_up_to(),
+        ParseError::MissingHeader
+        | ParseError::InvalidLength
+        | ParseError::InvalidType
+        | ParseError::HeaderLineTooLong => 0,
+        ParseError::MessageTooLarge(..) | ParseError::Encode(_) => return None,
+    };
+
+    let start = position.saturating_sub(ERROR_CONTEXT_LEN);
+    let end = src.len().min(position + ERROR_CONTEXT_LEN);
+    Some(hex_dump(&src[start..end], start))
+}
+
+/// Formats `bytes` as a hex dump of 16 bytes per line, prefixed with their offset in the original
+/// buffer and followed by their printable ASCII characters.
+fn hex_dump(bytes: &[u8], offset: usize) -> String {
+    let mut dump = String::new();
+    for (i, line) in bytes.chunks(16).enumerate() {
+        if i > 0 {
+            dump.push('\n');
         }
 
-        let string = str::from_utf8(src)?;
-        let (message, len) = match parse_message(string) {
-            Ok((remaining, message)) => (message.to_string(), src.len() - remaining.len()),
-            Err(Err::Incomplete(Needed::Size(min))) => {
-                self.remaining_msg_bytes = min;
-                return Ok(None);
+        dump.push_str(&format!("{:08x} ", offset + i * 16));
+        for b in line {
+            dump.push_str(&format!(" {:02x}", b));
+        }
+
+        dump.push_str(&"   ".repeat(16 - line.len()));
+        dump.push_str("  |");
+        dump.extend(line.iter().map(|&b| match b {
+            b' '..=b'~' => b as char,
+            _ => '.',
+        }));
+        dump.push('|');
+    }
+    dump
+}
+
+/// Lengths of the headers and the body of a partially received frame, once its headers have been
+/// parsed.
+#[derive(Clone, Copy, Debug)]
+struct FrameHeader {
+    header_len: usize,
+    body_len: usize,
+}
+
+/// An oversized frame whose body is being discarded.
+#[derive(Clone, Debug)]
+struct SkippedFrame {
+    len: usize,
+    remaining: usize,
+    scanner: RequestIdScanner,
+}
+
+/// Recovers the ID of a request from its JSON body as it streams past, without buffering it.
+///
+/// Only the members of the top-level object are inspected. The ID is only reported if the object
+/// also contains a `method` member, since responses from the client must not be answered.
+#[derive(Clone, Debug, Default)]
+struct RequestIdScanner {
+    depth: usize,
+    in_string: bool,
+    escaped: bool,
+    in_key: bool,
+    after_colon: bool,
+    key: Vec<u8>,
+    value: Option<Vec<u8>>,
+    id: Option<Vec<u8>>,
+    has_method: bool,
+}
+
+impl RequestIdScanner {
+    /// IDs longer than this are not recovered.
+    const MAX_ID_LEN: usize = 128;
+
+    fn scan(&mut self, bytes: &[u8]) {
+        for &b in bytes {
+            if self.in_string {
+                if self.in_key {
+                    if !self.escaped && b == b'"' {
+                        self.in_key = false;
+                    } else if self.key.len() < 8 {
+                        self.key.push(b);
+                    }
+                } else {
+                    self.capture(b);
+                }
+
+                if self.escaped {
+                    self.escaped = false;
+                } else if b == b'\\' {
+                    self.escaped = true;
+                } else if b == b'"' {
+                    self.in_string = false;
+                }
+                continue;
             }
-            Err(Err::Incomplete(_)) => {
-                return Ok(None);
+
+            match b {
+                b'"' => {
+                    self.in_string = true;
+                    if self.depth == 1 && !self.after_colon {
+                        self.in_key = true;
+                        self.key.clear();
+                    } else {
+                        self.capture(b);
+                    }
+                }
+                b':' if self.depth == 1 => {
+                    self.after_colon = true;
+                    self.has_method |= self.key == b"method";
+                    if self.key == b"id" {
+                        self.value = Some(Vec::new());
+                    }
+                }
+                b',' if self.depth == 1 => {
+                    self.finish_value();
+                    self.after_colon = false;
+                }
+                b'{' | b'[' => {
+                    self.depth += 1;
+                    self.capture(b);
+                }
+                b'}' | b']' => {
+                    self.depth = self.depth.saturating_sub(1);
+                    if self.depth == 0 {
+                        self.finish_value();
+                    } else {
+                        self.capture(b);
+                    }
+                }
+                b' ' | b'\t' | b'\r' | b'\n' => {}
+                _ => self.capture(b),
             }
-            Err(Err::Error((_, err))) | Err(Err::Failure((_, err))) => match err {
-                ErrorKind::Digit | ErrorKind::MapRes => return Err(ParseError::InvalidLength),
-                ErrorKind::Char | ErrorKind::IsNot => return Err(ParseError::InvalidType),
-                _ => return Err(ParseError::MissingHeader),
-            },
-        };
+        }
+    }
 
-        src.advance(len);
-        self.remaining_msg_bytes = 0;
+    fn capture(&mut self, b: u8) {
+        if let Some(ref mut value) = self.value {
+            if value.len() < Self::MAX_ID_LEN {
+                value.push(b);
+            } else {
+                self.value = None;
+            }
+        }
+    }
+
+    fn finish_value(&mut self) {
+        if let Some(value) = self.value.take() {
+            self.id = Some(value);
+        }
+    }
+
+    /// Returns the ID of the request, if the scanned body was a request with a valid ID.
+    fn request_id(&self) -> Option<Id> {
+        if !self.has_method {
+            return None;
+        }
+
+        match self.id.as_ref().map(|id| serde_json::from_slice(id)) {
+            Some(Ok(Id::Null)) | Some(Err(_)) | None => None,
+            Some(Ok(id)) => Some(id),
+        }
+    }
+}
+
+/// Pretty-prints the given JSON string, or returns it unchanged if it is not valid JSON.
+fn prettify(json: String) -> String {
+    serde_json::from_str::<serde_json::Value>(&json)
+        .and_then(|value| serde_json::to_string_pretty(&value))
+        .unwrap_or(json)
+}
+
+/// Reads a single Language Server Protocol message from the given reader.
+///
+/// This is a blocking convenience wrapper around `LanguageServerCodec` for simple use cases, such
+/// as scripts and tests. Headers are read one byte at a time so that no bytes past the end of the
+/// message are consumed, so wrapping unbuffered readers in a `BufReader` is recommended.
+///
+/// Returns an error of kind `InvalidData` if the message is malformed and `UnexpectedEof` if the
+/// reader is exhausted before a complete message has been read.
+pub fn read_message<R: Read>(mut reader: R) -> io::Result<String> {
+    let mut codec = LanguageServerCodec::<String>::default();
+    let mut buffer = BytesMut::new();
+
+    loop {
+        // Once the headers are parsed, the codec knows how many bytes make up the full frame.
+        let len = codec
+            .remaining_msg_bytes()
+            .saturating_sub(buffer.len())
+            .max(1);
+        let start = buffer.len();
+        buffer.resize(start + len, 0);
+        reader.read_exact(&mut buffer[start..])?;
 
-        Ok(Some(message))
+        let decoded = codec
+            .decode(&mut buffer)
+            .map_err(|e| IoError::new(IoErrorKind::InvalidData, e))?;
+        if let Some(message) = decoded {
+            return Ok(message);
+        }
+    }
+}
+
+/// Writes a single Language Server Protocol message to the given writer and flushes it.
+///
+/// This is a blocking convenience wrapper around `LanguageServerCodec` for simple use cases, such
+/// as scripts and tests. Like the codec, it skips writing empty messages entirely.
+pub fn write_message<W: Write>(mut writer: W, message: &str) -> io::Result<()> {
+    let mut codec = LanguageServerCodec::<String>::default();
+    let mut buffer = BytesMut::new();
+    codec
+        .encode(message.to_owned(), &mut buffer)
+        .map_err(|e| match e {
+            ParseError::Encode(e) => e,
+            e => IoError::other(e),
+        })?;
+
+    writer.write_all(&buffer)?;
+    writer.flush()
+}
+
+/// Asserts that an encoded frame declares the exact byte length of its valid UTF-8 body.
+///
+/// This guards against the header and body diverging and compiles away in release builds.
+#[cfg(debug_assertions)]
+fn debug_check_frame(frame: &[u8]) {
+    match parse_header(frame) {
+        Ok((body, body_len)) => {
+            assert_eq!(body_len, body.len(), "encoded content length mismatch");
+            assert!(str::from_utf8(body).is_ok(), "encoded body is not UTF-8");
+        }
+        Err(e) => panic!("encoded invalid header: {:?}", e),
+    }
+}
+
+#[cfg(not(debug_assertions))]
+fn debug_check_frame(_: &[u8]) {}
+
+/// Ensures that no header line, including one that is still being received, exceeds `max_len`.
+fn check_header_lines(src: &[u8], max_len: usize) -> Result<(), ParseError> {
+    for line in src.split(|&b| b == b'\n') {
+        if line.len() > max_len {
+            return Err(ParseError::HeaderLineTooLong);
+        } else if line == b"\r" {
+            break;
+        }
     }
+
+    Ok(())
 }
 
-fn parse_message(input: &str) -> IResult<&str, String> {
-    let content_len = delimited(tag("Content-Length: "), digit1, crlf);
+/// Parses the message headers, returning the length of the message body which follows them.
+///
+/// The `Content-Length` header may be followed by an optional `Content-Type` header.
+fn parse_header(input: &[u8]) -> IResult<&[u8], usize> {
+    let header = terminated(terminated(content_length, opt(content_type)), crlf);
+    let header = map_res(header, str::from_utf8);
 
-    let utf8 = alt((tag("utf-8"), tag("utf8")));
-    let charset = tuple((char(';'), space0, tag("charset="), utf8));
-    let content_type = tuple((tag("Content-Type:"), is_not(";\r"), opt(charset), crlf));
+    map_res(header, |s: &str| s.parse::<usize>())(input)
+}
+
+/// Parses a `Content-Length` header directly followed by the blank line ending the headers.
+///
+/// This is a faster variant of `parse_header()` for clients which never send a `Content-Type`.
+fn parse_length_header(input: &[u8]) -> IResult<&[u8], usize> {
+    let header = terminated(content_length, crlf);
+    let header = map_res(header, str::from_utf8);
 
-    let header = terminated(terminated(content_len, opt(content_type)), crlf);
-    let length = map_res(header, |s: &str| s.parse::<usize>());
-    let message = length_data(length);
+    map_res(header, |s: &str| s.parse::<usize>())(input)
+}
+
+/// Parses a `Content-Length` header line, returning the digits of its value.
+///
+/// The value must consist of ASCII digits only, following any amount of spaces or tabs after the
+/// colon and directly followed by `\r\n`. Leading zeros are accepted, while signs and trailing
+/// whitespace are rejected as an invalid length.
+fn content_length(input: &[u8]) -> IResult<&[u8], &[u8]> {
+    let digits_end = |i| crlf(i).map_err(|e: Err<_>| e.map(|(i, _)| (i, ErrorKind::Digit)));
+    delimited(tuple((tag("Content-Length:"), space0)), digit1, digits_end)(input)
+}
+
+/// Parses a `Content-Type` header line.
+///
+/// Parameters other than `charset` are skipped, but an unsupported charset is a hard error.
+fn content_type(input: &[u8]) -> IResult<&[u8], ()> {
+    let param_value = separated_pair(is_not("=;\r"), char('='), is_not(";\r"));
+    let param = preceded(tuple((char(';'), space0)), param_value);
+    let params = cut(verify(many0(param), |params: &Vec<(&[u8], &[u8])>| {
+        params
+            .iter()
+            .all(|&(name, value)| name != b"charset" || value == b"utf-8" || value == b"utf8")
+    }));
 
-    map(message, |msg| msg.to_string())(input)
+    let (input, _) = tuple((tag("Content-Type:"), is_not(";\r"), params, crlf))(input)?;
+    Ok((input, ()))
 }
 
 #[cfg(test)]
@@ -149,7 +936,7 @@ mod tests {
         let decoded = r#"{"jsonrpc":"2.0","method":"exit"}"#.to_string();
         let encoded = format!("Content-Length: {}\r\n\r\n{}", decoded.len(), decoded);
 
-        let mut codec = LanguageServerCodec::default();
+        let mut codec = LanguageServerCodec::<String>::default();
         let mut buffer = BytesMut::new();
         codec.encode(decoded.clone(), &mut buffer).unwrap();
         assert_eq!(buffer, BytesMut::from(encoded.clone()));
@@ -159,9 +946,77 @@ mod tests {
         assert_eq!(message, Some(decoded));
     }
 
+    #[test]
+    fn encodes_length_in_bytes() {
+        let decoded = r#"{"text":"é😀"}"#.to_string();
+        let encoded = format!("Content-Length: 17\r\n\r\n{}", decoded);
+
+        let mut codec = LanguageServerCodec::<String>::default();
+        let mut buffer = BytesMut::new();
+        codec.encode(decoded, &mut buffer).unwrap();
+        assert_eq!(buffer, BytesMut::from(encoded));
+    }
+
+    #[test]
+    fn round_trips_multibyte_characters() {
+        let decoded =
+            r#"{"jsonrpc":"2.0","method":"ünïcødé","params":["变量","🦀🎉"]}"#.to_string();
+        assert!(decoded.chars().count() < decoded.len());
+
+        let mut codec = LanguageServerCodec::<String>::default();
+        let mut buffer = BytesMut::new();
+        codec.encode(decoded.clone(), &mut buffer).unwrap();
+        let header = format!("Content-Length: {}\r\n\r\n", decoded.len());
+        assert!(buffer.starts_with(header.as_bytes()));
+
+        // Feed the frame in two halves, splitting the body in the middle of a multibyte character.
+        let split = header.len() + decoded.find('🦀').unwrap() + 2;
+        let rest = buffer.split_off(split);
+        assert_eq!(codec.decode(&mut buffer).unwrap(), None);
+        buffer.unsplit(rest);
+        assert_eq!(codec.decode(&mut buffer).unwrap(), Some(decoded));
+        assert!(buffer.is_empty());
+    }
+
+    #[test]
+    fn counts_encoded_bytes() {
+        let mut codec = LanguageServerCodec::<String>::default();
+        let mut buffer = BytesMut::from("leftover");
+        let written = codec
+            .encode_counted(r#"{"text":"é😀"}"#.into(), &mut buffer)
+            .unwrap();
+        assert_eq!(written, "Content-Length: 17\r\n\r\n".len() + 17);
+        assert_eq!(buffer.len(), "leftover".len() + written);
+
+        let written = codec.encode_counted(String::new(), &mut buffer).unwrap();
+        assert_eq!(written, 0);
+    }
+
+    #[test]
+    fn pretty_prints_json() {
+        let mut codec = LanguageServerCodec::<String>::default();
+        codec.set_pretty_json(true);
+
+        let pretty = "{\n  \"id\": 1,\n  \"jsonrpc\": \"2.0\",\n  \"result\": \"é\"\n}";
+        let expected = BytesMut::from(format!(
+            "Content-Length: {}\r\n\r\n{}",
+            pretty.len(),
+            pretty
+        ));
+
+        let mut buffer = BytesMut::new();
+        let item = r#"{"jsonrpc":"2.0","result":"é","id":1}"#.to_string();
+        codec.encode(item, &mut buffer).unwrap();
+        assert_eq!(buffer, expected);
+
+        let mut buffer = BytesMut::new();
+        codec.encode("not json".to_string(), &mut buffer).unwrap();
+        assert_eq!(buffer, BytesMut::from("Content-Length: 8\r\n\r\nnot json"));
+    }
+
     #[test]
     fn skip_encoding_empty_message() {
-        let mut codec = LanguageServerCodec::default();
+        let mut codec = LanguageServerCodec::<String>::default();
         let mut buffer = BytesMut::new();
         codec.encode("".to_string(), &mut buffer).unwrap();
         assert_eq!(buffer, BytesMut::new());
@@ -174,9 +1029,477 @@ mod tests {
         let content_type = "Content-Type: application/vscode-jsonrpc; charset=utf-8".to_string();
         let encoded = format!("{}\r\n{}\r\n\r\n{}", content_len, content_type, decoded);
 
-        let mut codec = LanguageServerCodec::default();
+        let mut codec = LanguageServerCodec::<String>::default();
         let mut buffer = BytesMut::from(encoded);
         let message = codec.decode(&mut buffer).unwrap();
         assert_eq!(message, Some(decoded));
     }
+
+    #[test]
+    fn skips_oversized_frames() {
+        let large = r#"{"jsonrpc":"2.0","id":7,"method":"foo","params":{"id":9,"text":"xxxx"}}"#;
+        let small = r#"{"jsonrpc":"2.0","method":"exit"}"#;
+        let encoded: String = [large, small]
+            .iter()
+            .map(|message| format!("Content-Length: {}\r\n\r\n{}", message.len(), message))
+            .collect();
+
+        let mut codec = LanguageServerCodec::<String>::default();
+        codec.set_max_message_len(Some(small.len()));
+
+        // Feed the oversized frame in two chunks, which must be skipped without buffering.
+        let (head, tail) = encoded.split_at(40);
+        let mut buffer = BytesMut::from(head);
+        assert_eq!(codec.decode(&mut buffer).unwrap(), None);
+        assert!(buffer.is_empty());
+
+        buffer.extend_from_slice(tail.as_bytes());
+        match codec.decode(&mut buffer) {
+            Err(ParseError::MessageTooLarge(len, Some(Id::Num(7)))) => assert_eq!(len, large.len()),
+            other => panic!("expected message too large, got {:?}", other),
+        }
+        assert_eq!(codec.decode(&mut buffer).unwrap(), Some(small.to_string()));
+    }
+
+    #[test]
+    fn recovers_request_id_of_skipped_frames() {
+        let cases = [
+            (
+                r#"{"jsonrpc":"2.0","id":1,"method":"foo"}"#,
+                Some(Id::Num(1)),
+            ),
+            (
+                r#"{ "method" : "foo", "params" : { "id" : 2, "s" : "\"id\":3" }, "id" : "a,b" }"#,
+                Some(Id::Str("a,b".into())),
+            ),
+            (
+                r#"{"jsonrpc":"2.0","method":"foo","params":[{"id":2}]}"#,
+                None,
+            ),
+            (r#"{"jsonrpc":"2.0","id":null,"method":"foo"}"#, None),
+            (
+                r#"{"jsonrpc":"2.0","id":1,"result":{"method":"foo"}}"#,
+                None,
+            ),
+        ];
+
+        for &(body, ref expected) in &cases {
+            let mut scanner = RequestIdScanner::default();
+            for chunk in body.as_bytes().chunks(3) {
+                scanner.scan(chunk);
+            }
+            assert_eq!(scanner.request_id(), *expected, "{}", body);
+        }
+    }
+
+    #[test]
+    fn skips_content_type_parsing() {
+        let decoded = r#"{"jsonrpc":"2.0","method":"exit"}"#.to_string();
+        let mut codec = LanguageServerCodec::<String>::default();
+        codec.set_skip_content_type(true);
+
+        let encoded = format!("Content-Length: {}\r\n\r\n{}", decoded.len(), decoded);
+        let mut buffer = BytesMut::from(encoded);
+        let message = codec.decode(&mut buffer).unwrap();
+        assert_eq!(message, Some(decoded.clone()));
+
+        let encoded = format!(
+            "Content-Length: {}\r\nContent-Type: application/vscode-jsonrpc\r\n\r\n{}",
+            decoded.len(),
+            decoded
+        );
+        let mut buffer = BytesMut::from(encoded);
+        match codec.decode(&mut buffer) {
+            Err(ParseError::MissingHeader) => {}
+            other => panic!("expected missing header, got {:?}", other),
+        }
+    }
+
+    #[test]
+    fn applies_config() {
+        let codec = LanguageServerCodec::<String>::default();
+        assert_eq!(codec.config(), &CodecConfig::default());
+        assert_eq!(codec.max_header_line_len(), DEFAULT_MAX_HEADER_LINE_LEN);
+
+        let config = CodecConfig::default()
+            .max_header_line_len(64)
+            .max_message_len(Some(1024))
+            .skip_content_type(true)
+            .pretty_json(true);
+        let mut codec = LanguageServerCodec::<String>::with_config(config.clone());
+        assert_eq!(codec.config(), &config);
+        assert_eq!(codec.max_header_line_len(), 64);
+        assert_eq!(codec.max_message_len(), Some(1024));
+        assert!(codec.skip_content_type());
+        assert!(codec.pretty_json());
+
+        let mut buffer = BytesMut::from("Content-Length: 2\r\nContent-Type: foo\r\n\r\n{}");
+        match codec.decode(&mut buffer) {
+            Err(ParseError::MissingHeader) => {}
+            other => panic!("expected missing header, got {:?}", other),
+        }
+    }
+
+    #[test]
+    fn skips_unknown_content_type_params() {
+        let decoded = r#"{"jsonrpc":"2.0","method":"exit"}"#.to_string();
+        let content_types = [
+            "application/vscode-jsonrpc; version=2",
+            "application/vscode-jsonrpc; version=2;charset=utf8; foo=bar",
+        ];
+
+        for content_type in &content_types {
+            let encoded = format!(
+                "Content-Length: {}\r\nContent-Type: {}\r\n\r\n{}",
+                decoded.len(),
+                content_type,
+                decoded
+            );
+
+            let mut codec = LanguageServerCodec::<String>::default();
+            let mut buffer = BytesMut::from(encoded);
+            let message = codec.decode(&mut buffer).unwrap();
+            assert_eq!(message, Some(decoded.clone()));
+        }
+    }
+
+    #[test]
+    fn rejects_unsupported_charset() {
+        let encoded = "Content-Length: 2\r\nContent-Type: text/plain; charset=latin1\r\n\r\n{}";
+        let mut codec = LanguageServerCodec::<String>::default();
+        let mut buffer = BytesMut::from(encoded);
+        match codec.decode(&mut buffer) {
+            Err(ParseError::InvalidType) => {}
+            other => panic!("expected invalid type, got {:?}", other),
+        }
+    }
+
+    #[test]
+    fn resumes_partial_frame() {
+        let decoded = r#"{"jsonrpc":"2.0","method":"exit"}"#.to_string();
+        let encoded = format!("Content-Length: {}\r\n\r\n{}", decoded.len(), decoded);
+        let (head, tail) = encoded.split_at(encoded.len() - 5);
+
+        let mut codec = LanguageServerCodec::<String>::default();
+        let mut buffer = BytesMut::from(head);
+        assert_eq!(codec.decode(&mut buffer).unwrap(), None);
+        assert_ne!(codec.remaining_msg_bytes(), 0);
+
+        let remaining = codec.remaining_msg_bytes();
+        let mut codec = LanguageServerCodec::<String>::with_remaining_msg_bytes(remaining);
+        buffer.extend_from_slice(tail.as_bytes());
+        assert_eq!(codec.decode(&mut buffer).unwrap(), Some(decoded));
+        assert_eq!(codec.remaining_msg_bytes(), 0);
+    }
+
+    #[test]
+    fn decodes_frame_following_large_partial_frame() {
+        let large = format!(r#"{{"data":"{}"}}"#, "x".repeat(64 * 1024));
+        let small = r#"{"jsonrpc":"2.0","method":"exit"}"#.to_string();
+        let encoded = format!(
+            "Content-Length: {}\r\n\r\n{}Content-Length: {}\r\n\r\n{}",
+            large.len(),
+            large,
+            small.len(),
+            small
+        );
+
+        let mut codec = LanguageServerCodec::<String>::default();
+        let mut buffer = BytesMut::from(&encoded.as_bytes()[..1024]);
+        assert_eq!(codec.decode(&mut buffer).unwrap(), None);
+
+        buffer.extend_from_slice(&encoded.as_bytes()[1024..]);
+        assert_eq!(codec.decode(&mut buffer).unwrap(), Some(large));
+        assert_eq!(codec.decode(&mut buffer).unwrap(), Some(small));
+        assert!(buffer.is_empty());
+    }
+
+    #[test]
+    fn parses_headers_of_large_frame_once() {
+        let large = format!(r#"{{"data":"{}"}}"#, "x".repeat(4 * 1024 * 1024));
+        let encoded = format!("Content-Length: {}\r\n\r\n{}", large.len(), large);
+        let header_len = encoded.len() - large.len();
+
+        let mut codec = LanguageServerCodec::<String>::default();
+        let mut buffer = BytesMut::new();
+        let mut chunks = encoded.as_bytes().chunks(64 * 1024).peekable();
+        while let Some(chunk) = chunks.next() {
+            buffer.extend_from_slice(chunk);
+            if chunks.peek().is_some() {
+                assert_eq!(codec.decode(&mut buffer).unwrap(), None);
+                let header = codec.frame_header.expect("headers were not cached");
+                assert_eq!(
+                    (header.header_len, header.body_len),
+                    (header_len, large.len())
+                );
+            }
+        }
+
+        assert_eq!(codec.decode(&mut buffer).unwrap(), Some(large));
+        assert!(codec.frame_header.is_none());
+        assert_eq!(codec.remaining_msg_bytes(), 0);
+    }
+
+    #[test]
+    fn decodes_body_following_header_only_chunk() {
+        let decoded = r#"{"jsonrpc":"2.0","method":"exit"}"#.to_string();
+        let header = format!("Content-Length: {}\r\n\r\n", decoded.len());
+        let encoded_len = header.len() + decoded.len();
+
+        let mut codec = LanguageServerCodec::<String>::default();
+        let mut buffer = BytesMut::from(header.as_str());
+        assert_eq!(codec.decode(&mut buffer).unwrap(), None);
+        assert_eq!(codec.remaining_msg_bytes(), encoded_len);
+        assert_eq!(buffer.len(), header.len());
+
+        let (head, tail) = decoded.split_at(10);
+        buffer.extend_from_slice(head.as_bytes());
+        assert_eq!(codec.decode(&mut buffer).unwrap(), None);
+        assert_eq!(codec.remaining_msg_bytes(), encoded_len);
+
+        buffer.extend_from_slice(tail.as_bytes());
+        assert_eq!(codec.decode(&mut buffer).unwrap(), Some(decoded));
+        assert_eq!(codec.remaining_msg_bytes(), 0);
+        assert!(buffer.is_empty());
+    }
+
+    #[test]
+    fn decodes_frame_byte_by_byte() {
+        let decoded = r#"{"jsonrpc":"2.0","method":"exit"}"#.to_string();
+        let encoded = format!("Content-Length: {}\r\n\r\n{}", decoded.len(), decoded);
+        let (last, init) = encoded.as_bytes().split_last().unwrap();
+
+        let mut codec = LanguageServerCodec::<String>::default();
+        let mut buffer = BytesMut::new();
+        for byte in init {
+            buffer.extend_from_slice(&[*byte]);
+            assert_eq!(codec.decode(&mut buffer).unwrap(), None);
+        }
+
+        assert_eq!(codec.remaining_msg_bytes(), encoded.len());
+        assert_eq!(buffer.len(), encoded.len() - 1);
+
+        buffer.extend_from_slice(&[*last]);
+        assert_eq!(codec.decode(&mut buffer).unwrap(), Some(decoded));
+        assert_eq!(codec.remaining_msg_bytes(), 0);
+        assert!(buffer.is_empty());
+    }
+
+    #[test]
+    fn rejects_oversized_header_line() {
+        let mut codec = LanguageServerCodec::<String>::default();
+        codec.set_max_header_line_len(64);
+
+        let unterminated = format!("Content-Length: 2\r\nContent-Type: {}", "a".repeat(64));
+        let mut buffer = BytesMut::from(unterminated);
+        match codec.decode(&mut buffer) {
+            Err(ParseError::HeaderLineTooLong) => {}
+            other => panic!("expected oversized header line error, got {:?}", other),
+        }
+
+        let body = "x".repeat(128);
+        let encoded = format!("Content-Length: {}\r\n\r\n{}", body.len(), body);
+        let mut buffer = BytesMut::from(encoded);
+        assert_eq!(codec.decode(&mut buffer).unwrap(), Some(body));
+    }
+
+    #[test]
+    fn accepts_leading_zeros_in_length() {
+        let decoded = r#"{"jsonrpc":"2.0","method":"exit"}"#.to_string();
+        let encoded = format!("Content-Length: 00{}\r\n\r\n{}", decoded.len(), decoded);
+
+        let mut codec = LanguageServerCodec::<String>::default();
+        let mut buffer = BytesMut::from(encoded);
+        assert_eq!(codec.decode(&mut buffer).unwrap(), Some(decoded));
+    }
+
+    #[test]
+    fn rejects_signed_or_padded_length() {
+        let headers = [
+            "Content-Length: +2\r\n\r\n{}",
+            "Content-Length: -2\r\n\r\n{}",
+            "Content-Length: 2 \r\n\r\n{}",
+            "Content-Length: 2\t\r\n\r\n{}",
+        ];
+
+        for header in &headers {
+            let mut codec = LanguageServerCodec::<String>::default();
+            let mut buffer = BytesMut::from(*header);
+            match codec.decode(&mut buffer) {
+                Err(ParseError::InvalidLength) => {}
+                other => panic!("expected invalid length for {:?}, got {:?}", header, other),
+            }
+        }
+    }
+
+    #[test]
+    fn accepts_varying_spaces_after_colon() {
+        for header in &[
+            "Content-Length:2",
+            "Content-Length:  2",
+            "Content-Length:\t2",
+        ] {
+            let mut codec = LanguageServerCodec::<String>::default();
+            let mut buffer = BytesMut::from(format!("{}\r\n\r\n{{}}", header));
+            let message = codec.decode(&mut buffer).unwrap();
+            assert_eq!(
+                message,
+                Some("{}".to_owned()),
+                "failed to decode {:?}",
+                header
+            );
+        }
+    }
+
+    #[test]
+    fn reads_and_writes_messages() {
+        let messages = [r#"{"jsonrpc":"2.0","method":"exit"}"#, "", "{}"];
+
+        let mut stream = Vec::new();
+        for message in &messages {
+            write_message(&mut stream, message).unwrap();
+        }
+        assert_eq!(
+            stream,
+            format!(
+                "Content-Length: {}\r\n\r\n{}Content-Length: 2\r\n\r\n{{}}",
+                messages[0].len(),
+                messages[0]
+            )
+            .into_bytes()
+        );
+
+        let mut reader = &stream[..];
+        assert_eq!(read_message(&mut reader).unwrap(), messages[0]);
+        assert_eq!(read_message(&mut reader).unwrap(), messages[2]);
+
+        let err = read_message(&mut reader).unwrap_err();
+        assert_eq!(err.kind(), IoErrorKind::UnexpectedEof);
+
+        let mut reader = &b"Content-Length: x\r\n\r\n"[..];
+        let err = read_message(&mut reader).unwrap_err();
+        assert_eq!(err.kind(), IoErrorKind::InvalidData);
+    }
+
+    #[derive(Clone, Default)]
+    struct SharedBuffer(Arc<Mutex<Vec<u8>>>);
+
+    impl Write for SharedBuffer {
+        fn write(&mut self, buf: &[u8]) -> std::io::Result<usize> {
+            self.0.lock().unwrap().write(buf)
+        }
+
+        fn flush(&mut self) -> std::io::Result<()> {
+            Ok(())
+        }
+    }
+
+    #[test]
+    fn logs_wire_bytes() {
+        let decoded = r#"{"jsonrpc":"2.0","method":"exit"}"#.to_string();
+        let encoded = format!("Content-Length: {}\r\n\r\n{}", decoded.len(), decoded);
+
+        let log = SharedBuffer::default();
+        let mut codec = LanguageServerCodec::<String>::default();
+        codec.set_wire_log(WireLog::new(log.clone()));
+
+        let mut buffer = BytesMut::from(encoded.clone());
+        assert_eq!(codec.decode(&mut buffer).unwrap(), Some(decoded.clone()));
+        codec.encode(decoded, &mut buffer).unwrap();
+
+        let expected = format!("<-- {}\n--> {}\n", encoded, encoded);
+        assert_eq!(*log.0.lock().unwrap(), expected.into_bytes());
+    }
+
+    #[test]
+    fn decodes_without_utf8_validation() {
+        let decoded = r#"{"jsonrpc":"2.0","method":"exit"}"#.to_string();
+        let encoded = format!("Content-Length: {}\r\n\r\n{}", decoded.len(), decoded);
+
+        let mut codec = LanguageServerCodec::<Bytes>::default();
+        let mut buffer = BytesMut::from(encoded);
+        let message = codec.decode(&mut buffer).unwrap();
+        assert_eq!(message, Some(Bytes::from(decoded)));
+        assert!(buffer.is_empty());
+    }
+
+    #[cfg(feature = "metrics")]
+    #[test]
+    fn records_metrics() {
+        #[derive(Default)]
+        struct Recorder(Mutex<Vec<(&'static str, usize)>>);
+
+        impl CodecMetrics for Recorder {
+            fn record_decode(&self, len: usize, _: Duration) {
+                self.0.lock().unwrap().push(("decode", len));
+            }
+
+            fn record_encode(&self, len: usize, _: Duration) {
+                self.0.lock().unwrap().push(("encode", len));
+            }
+        }
+
+        let recorder = Arc::new(Recorder::default());
+        let mut codec = LanguageServerCodec::<String>::default();
+        codec.set_metrics(recorder.clone());
+
+        let message = r#"{"jsonrpc":"2.0","method":"exit"}"#.to_string();
+        let mut buffer = BytesMut::new();
+        codec.encode(message.clone(), &mut buffer).unwrap();
+        codec.encode(String::new(), &mut buffer).unwrap();
+
+        let mut partial = buffer.split_to(buffer.len() - 1);
+        assert_eq!(codec.decode(&mut partial).unwrap(), None);
+        partial.unsplit(buffer);
+        assert_eq!(codec.decode(&mut partial).unwrap(), Some(message.clone()));
+
+        let recorded = recorder.0.lock().unwrap();
+        assert_eq!(
+            *recorded,
+            [("encode", message.len()), ("decode", message.len())]
+        );
+    }
+
+    #[test]
+    fn dumps_bytes_around_errors() {
+        let dump = hex_dump(b"Content-Length: x\r\n", 32);
+        assert_eq!(
+            dump,
+            "00000020  43 6f 6e 74 65 6e 74 2d 4c 65 6e 67 74 68 3a 20  |Content-Length: |\n\
+             00000030  78 0d 0a                                         |x..|"
+        );
+
+        let body = [b'a'; 40]
+            .iter()
+            .chain(b"\xff}")
+            .cloned()
+            .collect::<Vec<_>>();
+        let error = ParseError::from(str::from_utf8(&body).unwrap_err());
+        let context = error_context(&error, &body).unwrap();
+        assert!(context.starts_with("00000008  61"));
+        assert!(context.ends_with("  |.}|"));
+        assert_eq!(context.lines().count(), 3);
+
+        let error = ParseError::MessageTooLarge(body.len(), None);
+        assert_eq!(error_context(&error, &body), None);
+    }
+
+    #[test]
+    fn rejects_invalid_utf8_body() {
+        let body = b"{\"jsonrpc\":\"2.0\",\"method\":\"\xff\"}";
+        let mut encoded = format!("Content-Length: {}\r\n\r\n", body.len()).into_bytes();
+        encoded.extend_from_slice(body);
+
+        let mut codec = LanguageServerCodec::<String>::default();
+        let mut buffer = BytesMut::from(encoded.clone());
+        match codec.decode(&mut buffer) {
+            Err(ParseError::Utf8(_)) => {}
+            other => panic!("expected UTF-8 error, got {:?}", other),
+        }
+
+        let mut codec = LanguageServerCodec::<Bytes>::default();
+        let mut buffer = BytesMut::from(encoded);
+        let message = codec.decode(&mut buffer).unwrap();
+        assert_eq!(message, Some(Bytes::from(&body[..])));
+    }
 }
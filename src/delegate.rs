@@ -1,12 +1,18 @@
 //! Type-safe wrapper for the JSON-RPC interface.
 
+pub use self::diagnostics::DiagnosticsPublisher;
+pub use self::extensions::Extensions;
 pub use self::printer::Printer;
+pub use self::progress::{CancellationToken, ProgressReporter};
 
+use std::collections::HashMap;
+use std::fmt::{self, Debug, Formatter};
 use std::sync::atomic::{AtomicBool, Ordering};
 use std::sync::Arc;
 
-use futures::sync::mpsc::{self, Receiver};
-use futures::{future, Poll, Stream};
+use futures::sync::mpsc::{self, UnboundedReceiver};
+use futures::{future, try_ready, Async, Future, Poll, Stream};
+use jsonrpc_core::delegates::IoDelegate;
 use jsonrpc_core::types::{ErrorCode, Params};
 use jsonrpc_core::{BoxFuture, Error, Result as RpcResult};
 use jsonrpc_derive::rpc;
@@ -15,22 +21,40 @@ use lsp_types::notification::{Notification, *};
 use lsp_types::request::{Request, *};
 use lsp_types::*;
 use serde::de::DeserializeOwned;
+use serde::Serialize;
 use serde_json::Value;
 
+use self::printer::Outgoing;
+use super::process::ParentMonitor;
 use super::LanguageServer;
 
+mod diagnostics;
+mod extensions;
 mod printer;
+mod progress;
 
 /// Stream of notification messages produced by the language server.
 #[derive(Debug)]
-pub struct MessageStream(Receiver<String>);
+pub struct MessageStream(UnboundedReceiver<Outgoing>);
 
 impl Stream for MessageStream {
     type Item = String;
     type Error = ();
 
     fn poll(&mut self) -> Poll<Option<String>, ()> {
-        self.0.poll()
+        loop {
+            match try_ready!(self.0.poll()) {
+                Some(Outgoing::Message(message, slot)) => {
+                    // Frees up the slot in the queue for the next message.
+                    drop(slot);
+                    return Ok(Async::Ready(Some(message)));
+                }
+                Some(Outgoing::Flush(flushed)) => {
+                    let _ = flushed.send(());
+                }
+                None => return Ok(Async::Ready(None)),
+            }
+        }
     }
 }
 
@@ -45,25 +69,55 @@ pub trait LanguageServerCore {
     #[rpc(name = "initialized", raw_params)]
     fn initialized(&self, params: Params);
 
+    #[rpc(name = "$/setTrace", raw_params)]
+    fn set_trace(&self, params: Params);
+
     #[rpc(name = "shutdown")]
     fn shutdown(&self) -> BoxFuture<()>;
 
+    #[rpc(name = "window/workDoneProgress/cancel", raw_params)]
+    fn work_done_progress_cancel(&self, params: Params);
+
+    #[rpc(name = "$/progress", raw_params)]
+    fn progress(&self, params: Params);
+
     // Workspace
 
     #[rpc(name = "workspace/didChangeWorkspaceFolders", raw_params)]
     fn did_change_workspace_folders(&self, params: Params);
 
-    #[rpc(name = "workspace/DidChangeConfiguration", raw_params)]
+    #[rpc(name = "workspace/didChangeConfiguration", raw_params)]
     fn did_change_configuration(&self, params: Params);
 
     #[rpc(name = "workspace/didChangeWatchedFiles", raw_params)]
     fn did_change_watched_files(&self, params: Params);
 
     #[rpc(name = "workspace/symbol", raw_params)]
-    fn symbol(&self, params: Params) -> BoxFuture<Option<Vec<SymbolInformation>>>;
+    fn symbol(&self, params: Params) -> BoxFuture<Value>;
+
+    #[rpc(name = "workspaceSymbol/resolve", raw_params)]
+    fn symbol_resolve(&self, params: Params) -> BoxFuture<Value>;
 
     #[rpc(name = "workspace/executeCommand", raw_params)]
-    fn execute_command(&self, params: Params) -> BoxFuture<Option<Value>>;
+    fn execute_command(&self, params: Params) -> BoxFuture<Value>;
+
+    #[rpc(name = "workspace/willCreateFiles", raw_params)]
+    fn will_create_files(&self, params: Params) -> BoxFuture<Value>;
+
+    #[rpc(name = "workspace/didCreateFiles", raw_params)]
+    fn did_create_files(&self, params: Params);
+
+    #[rpc(name = "workspace/willRenameFiles", raw_params)]
+    fn will_rename_files(&self, params: Params) -> BoxFuture<Value>;
+
+    #[rpc(name = "workspace/didRenameFiles", raw_params)]
+    fn did_rename_files(&self, params: Params);
+
+    #[rpc(name = "workspace/willDeleteFiles", raw_params)]
+    fn will_delete_files(&self, params: Params) -> BoxFuture<Value>;
+
+    #[rpc(name = "workspace/didDeleteFiles", raw_params)]
+    fn did_delete_files(&self, params: Params);
 
     // Text synchronization
 
@@ -82,13 +136,157 @@ pub trait LanguageServerCore {
     // Language features
 
     #[rpc(name = "textDocument/completion", raw_params)]
-    fn completion(&self, params: Params) -> BoxFuture<Option<CompletionResponse>>;
+    fn completion(&self, params: Params) -> BoxFuture<Value>;
+
+    #[rpc(name = "completionItem/resolve", raw_params)]
+    fn completion_resolve(&self, params: Params) -> BoxFuture<Value>;
 
     #[rpc(name = "textDocument/hover", raw_params)]
-    fn hover(&self, params: Params) -> BoxFuture<Option<Hover>>;
+    fn hover(&self, params: Params) -> BoxFuture<Value>;
+
+    #[rpc(name = "textDocument/declaration", raw_params)]
+    fn goto_declaration(&self, params: Params) -> BoxFuture<Value>;
+
+    #[rpc(name = "textDocument/typeDefinition", raw_params)]
+    fn goto_type_definition(&self, params: Params) -> BoxFuture<Value>;
+
+    #[rpc(name = "textDocument/implementation", raw_params)]
+    fn goto_implementation(&self, params: Params) -> BoxFuture<Value>;
+
+    #[rpc(name = "textDocument/signatureHelp", raw_params)]
+    fn signature_help(&self, params: Params) -> BoxFuture<Value>;
 
     #[rpc(name = "textDocument/documentHighlight", raw_params)]
-    fn document_highlight(&self, params: Params) -> BoxFuture<Option<Vec<DocumentHighlight>>>;
+    fn document_highlight(&self, params: Params) -> BoxFuture<Value>;
+
+    #[rpc(name = "textDocument/codeAction", raw_params)]
+    fn code_action(&self, params: Params) -> BoxFuture<Value>;
+
+    #[rpc(name = "codeAction/resolve", raw_params)]
+    fn code_action_resolve(&self, params: Params) -> BoxFuture<Value>;
+
+    #[rpc(name = "textDocument/codeLens", raw_params)]
+    fn code_lens(&self, params: Params) -> BoxFuture<Value>;
+
+    #[rpc(name = "codeLens/resolve", raw_params)]
+    fn code_lens_resolve(&self, params: Params) -> BoxFuture<Value>;
+
+    #[rpc(name = "textDocument/documentLink", raw_params)]
+    fn document_link(&self, params: Params) -> BoxFuture<Value>;
+
+    #[rpc(name = "documentLink/resolve", raw_params)]
+    fn document_link_resolve(&self, params: Params) -> BoxFuture<Value>;
+
+    #[rpc(name = "textDocument/documentColor", raw_params)]
+    fn document_color(&self, params: Params) -> BoxFuture<Value>;
+
+    #[rpc(name = "textDocument/colorPresentation", raw_params)]
+    fn color_presentation(&self, params: Params) -> BoxFuture<Value>;
+
+    #[rpc(name = "textDocument/onTypeFormatting", raw_params)]
+    fn on_type_formatting(&self, params: Params) -> BoxFuture<Value>;
+
+    #[rpc(name = "textDocument/rangeFormatting", raw_params)]
+    fn range_formatting(&self, params: Params) -> BoxFuture<Value>;
+
+    #[rpc(name = "textDocument/foldingRange", raw_params)]
+    fn folding_range(&self, params: Params) -> BoxFuture<Value>;
+
+    #[rpc(name = "textDocument/selectionRange", raw_params)]
+    fn selection_range(&self, params: Params) -> BoxFuture<Value>;
+
+    #[rpc(name = "textDocument/linkedEditingRange", raw_params)]
+    fn linked_editing_range(&self, params: Params) -> BoxFuture<Value>;
+
+    #[rpc(name = "textDocument/moniker", raw_params)]
+    fn moniker(&self, params: Params) -> BoxFuture<Value>;
+
+    #[rpc(name = "textDocument/inlayHint", raw_params)]
+    fn inlay_hint(&self, params: Params) -> BoxFuture<Value>;
+
+    #[rpc(name = "inlayHint/resolve", raw_params)]
+    fn inlay_hint_resolve(&self, params: Params) -> BoxFuture<Value>;
+
+    #[rpc(name = "textDocument/semanticTokens/full", raw_params)]
+    fn semantic_tokens_full(&self, params: Params) -> BoxFuture<Value>;
+
+    #[rpc(name = "textDocument/semanticTokens/full/delta", raw_params)]
+    fn semantic_tokens_full_delta(&self, params: Params) -> BoxFuture<Value>;
+
+    #[rpc(name = "textDocument/semanticTokens/range", raw_params)]
+    fn semantic_tokens_range(&self, params: Params) -> BoxFuture<Value>;
+
+    #[rpc(name = "textDocument/rename", raw_params)]
+    fn rename(&self, params: Params) -> BoxFuture<Value>;
+
+    #[rpc(name = "textDocument/prepareRename", raw_params)]
+    fn prepare_rename(&self, params: Params) -> BoxFuture<Value>;
+
+    #[rpc(name = "textDocument/prepareCallHierarchy", raw_params)]
+    fn prepare_call_hierarchy(&self, params: Params) -> BoxFuture<Value>;
+
+    #[rpc(name = "callHierarchy/incomingCalls", raw_params)]
+    fn incoming_calls(&self, params: Params) -> BoxFuture<Value>;
+
+    #[rpc(name = "callHierarchy/outgoingCalls", raw_params)]
+    fn outgoing_calls(&self, params: Params) -> BoxFuture<Value>;
+
+    #[rpc(name = "textDocument/prepareTypeHierarchy", raw_params)]
+    fn prepare_type_hierarchy(&self, params: Params) -> BoxFuture<Value>;
+
+    #[rpc(name = "typeHierarchy/supertypes", raw_params)]
+    fn supertypes(&self, params: Params) -> BoxFuture<Value>;
+
+    #[rpc(name = "typeHierarchy/subtypes", raw_params)]
+    fn subtypes(&self, params: Params) -> BoxFuture<Value>;
+
+    #[rpc(name = "textDocument/diagnostic", raw_params)]
+    fn diagnostic(&self, params: Params) -> BoxFuture<Value>;
+
+    #[rpc(name = "workspace/diagnostic", raw_params)]
+    fn workspace_diagnostic(&self, params: Params) -> BoxFuture<Value>;
+}
+
+type ValidatorFn = dyn Fn(&str, &Value) -> RpcResult<()> + Send + Sync;
+
+/// Hook for validating the raw parameters of incoming messages before they are dispatched.
+pub struct Validator(Box<ValidatorFn>);
+
+impl Validator {
+    /// Creates a new `Validator` from the given function.
+    pub fn new<F>(validator: F) -> Self
+    where
+        F: Fn(&str, &Value) -> RpcResult<()> + Send + Sync + 'static,
+    {
+        Validator(Box::new(validator))
+    }
+}
+
+impl Debug for Validator {
+    fn fmt(&self, fmt: &mut Formatter) -> fmt::Result {
+        fmt.debug_tuple(stringify!(Validator)).field(&"_").finish()
+    }
+}
+
+type CommandFn = dyn Fn(&Printer, Vec<Value>) -> BoxFuture<Option<Value>> + Send + Sync;
+
+/// Handler for a single command executed through the `workspace/executeCommand` request.
+pub struct Command(Box<CommandFn>);
+
+impl Command {
+    /// Creates a new `Command` from the given function.
+    pub fn new<F>(command: F) -> Self
+    where
+        F: Fn(&Printer, Vec<Value>) -> BoxFuture<Option<Value>> + Send + Sync + 'static,
+    {
+        Command(Box::new(command))
+    }
+}
+
+impl Debug for Command {
+    fn fmt(&self, fmt: &mut Formatter) -> fmt::Result {
+        fmt.debug_tuple(stringify!(Command)).field(&"_").finish()
+    }
 }
 
 /// Wraps the language server backend and provides a `Printer` for sending notifications.
@@ -97,23 +295,110 @@ pub struct Delegate<T> {
     server: T,
     printer: Printer,
     initialized: Arc<AtomicBool>,
+    shut_down: Arc<AtomicBool>,
+    validator: Option<Validator>,
+    commands: HashMap<String, Command>,
+    parent_monitor: Option<ParentMonitor>,
+    compact_results: bool,
 }
 
 impl<T: LanguageServer> Delegate<T> {
     /// Creates a new `Delegate` and a stream of notifications from the server to the client.
     pub fn new(server: T) -> (Self, MessageStream) {
-        let (tx, rx) = mpsc::channel(1);
+        let (tx, rx) = mpsc::unbounded();
         let messages = MessageStream(rx);
         let initialized = Arc::new(AtomicBool::new(false));
         let delegate = Delegate {
             server,
             printer: Printer::new(tx, initialized.clone()),
             initialized,
+            shut_down: Arc::new(AtomicBool::new(false)),
+            validator: None,
+            commands: HashMap::new(),
+            parent_monitor: None,
+            compact_results: false,
         };
 
         (delegate, messages)
     }
 
+    /// Returns the `Printer` used for sending messages to the client.
+    pub fn printer(&self) -> Printer {
+        self.printer.clone()
+    }
+
+    /// Returns the flags signaling whether the server has been initialized and shut down,
+    /// respectively.
+    pub(crate) fn lifecycle_flags(&self) -> (Arc<AtomicBool>, Arc<AtomicBool>) {
+        (self.initialized.clone(), self.shut_down.clone())
+    }
+
+    /// Validates the parameters of every request and notification with `validator` before they
+    /// are passed to the language server.
+    pub fn set_validator(&mut self, validator: Validator) {
+        self.validator = Some(validator);
+    }
+
+    /// Handles `workspace/executeCommand` requests for the command with the given name with
+    /// `command` instead of the language server.
+    pub fn add_command(&mut self, name: String, command: Command) {
+        self.commands.insert(name, command);
+    }
+
+    /// Monitors the client process once its ID is received in the `initialize` request.
+    pub(crate) fn set_parent_monitor(&mut self, monitor: ParentMonitor) {
+        self.parent_monitor = Some(monitor);
+    }
+
+    /// Limits the number of messages which may be queued for the client at once.
+    pub fn set_max_queued_messages(&mut self, max: usize) {
+        self.printer.set_max_queued_messages(max);
+    }
+
+    /// Registers shared state for access from handlers through the `Printer`.
+    pub fn set_extensions(&mut self, extensions: Extensions) {
+        self.printer.set_extensions(extensions);
+    }
+
+    /// Sends requests to the client with string IDs starting with `prefix`.
+    pub fn set_request_id_prefix(&mut self, prefix: String) {
+        self.printer.set_request_id_prefix(prefix);
+    }
+
+    /// Holds back all messages from the server to the client until the `initialized` notification
+    /// has been received.
+    pub fn buffer_until_initialized(&mut self) {
+        self.printer.buffer_pending();
+    }
+
+    /// Omits object fields which are `null` from the results of requests.
+    pub fn compact_results(&mut self) {
+        self.compact_results = true;
+    }
+
+    /// Converts the `Delegate` into the set of JSON-RPC methods it handles.
+    ///
+    /// In addition to the methods of `LanguageServerCore`, this includes methods only present
+    /// behind feature flags, which cannot be declared in the `#[rpc]` trait itself.
+    pub fn into_methods(self) -> IoDelegate<Self, ()> {
+        #[allow(unused_mut)]
+        let mut methods = self.to_delegate();
+
+        #[cfg(feature = "proposed")]
+        methods.add_method(InlineCompletionRequest::METHOD, |delegate, params| {
+            delegate.inline_completion(params)
+        });
+
+        methods
+    }
+
+    #[cfg(feature = "proposed")]
+    fn inline_completion(&self, params: Params) -> BoxFuture<Value> {
+        self.delegate_request::<InlineCompletionRequest, _>(params, |p| {
+            self.server.inline_completion(p)
+        })
+    }
+
     fn delegate_notification<N, F>(&self, params: Params, delegate: F)
     where
         N: Notification,
@@ -121,15 +406,21 @@ impl<T: LanguageServer> Delegate<T> {
         F: Fn(&Printer, N::Params),
     {
         trace!("received `{}` notification: {:?}", N::METHOD, params);
-        if self.initialized.load(Ordering::SeqCst) {
+        if self.shut_down.load(Ordering::SeqCst) {
+            trace!("server shut down, dropping `{}` notification", N::METHOD);
+        } else if self.initialized.load(Ordering::SeqCst) {
+            let raw = self.validator.as_ref().map(|_| Value::from(params.clone()));
             match params.parse::<N::Params>() {
-                Ok(params) => delegate(&self.printer, params),
+                Ok(params) => match self.validate(N::METHOD, raw) {
+                    Ok(()) => delegate(&self.printer, params),
+                    Err(err) => error!("rejected `{}` notification: {}", N::METHOD, err.message),
+                },
                 Err(err) => error!("invalid parameters for `{}`: {:?}", N::METHOD, err),
             }
         }
     }
 
-    fn delegate_request<R, F>(&self, params: Params, delegate: F) -> BoxFuture<R::Result>
+    fn delegate_request<R, F>(&self, params: Params, delegate: F) -> BoxFuture<Value>
     where
         R: Request,
         R::Params: DeserializeOwned,
@@ -137,9 +428,24 @@ impl<T: LanguageServer> Delegate<T> {
         F: Fn(R::Params) -> BoxFuture<R::Result>,
     {
         trace!("received `{}` request: {:?}", R::METHOD, params);
-        if self.initialized.load(Ordering::SeqCst) {
+        if self.shut_down.load(Ordering::SeqCst) {
+            Box::new(future::err(shut_down_error()))
+        } else if self.initialized.load(Ordering::SeqCst) {
+            let raw = self.validator.as_ref().map(|_| Value::from(params.clone()));
+            let compact = self.compact_results;
             match params.parse() {
-                Ok(params) => delegate(params),
+                Ok(params) => match self.validate(R::METHOD, raw) {
+                    Ok(()) => Box::new(delegate(params).and_then(move |result| {
+                        let result = serialize_result(R::METHOD, result)?;
+                        // The result of a command is entirely up to the server.
+                        if compact && R::METHOD != ExecuteCommand::METHOD {
+                            Ok(strip_nulls(result))
+                        } else {
+                            Ok(result)
+                        }
+                    })),
+                    Err(err) => Box::new(future::err(err)),
+                },
                 Err(err) => Box::new(future::err(Error::invalid_params_with_details(
                     "invalid parameters",
                     err,
@@ -149,32 +455,75 @@ impl<T: LanguageServer> Delegate<T> {
             Box::new(future::err(not_initialized_error()))
         }
     }
+
+    /// Runs the user-supplied validator, if any, on the raw parameters of a message.
+    fn validate(&self, method: &str, params: Option<Value>) -> RpcResult<()> {
+        match (self.validator.as_ref(), params) {
+            (Some(validator), Some(params)) => (validator.0)(method, &params),
+            _ => Ok(()),
+        }
+    }
 }
 
 impl<T: LanguageServer> LanguageServerCore for Delegate<T> {
     fn initialize(&self, params: Params) -> RpcResult<InitializeResult> {
         trace!("received `initialize` request: {:?}", params);
+        if self.shut_down.load(Ordering::SeqCst) {
+            return Err(shut_down_error());
+        }
+
         let params: InitializeParams = params.parse()?;
+        self.printer
+            .set_client_capabilities(params.capabilities.clone());
+        self.printer
+            .set_trace_value(params.trace.unwrap_or_default());
+        let process_id = params.process_id;
         let response = self.server.initialize(&self.printer, params)?;
         self.initialized.store(true, Ordering::SeqCst);
+
+        if let (Some(monitor), Some(pid)) = (&self.parent_monitor, process_id) {
+            monitor.watch(pid);
+        }
         Ok(response)
     }
 
     fn initialized(&self, params: Params) {
         self.delegate_notification::<Initialized, _>(params, |p, params| {
+            p.flush_pending();
             self.server.initialized(p, params)
         });
     }
 
+    fn set_trace(&self, params: Params) {
+        self.delegate_notification::<SetTrace, _>(params, |p, params| {
+            p.set_trace_value(params.value)
+        });
+    }
+
     fn shutdown(&self) -> BoxFuture<()> {
         trace!("received `shutdown` request");
-        if self.initialized.load(Ordering::SeqCst) {
-            Box::new(self.server.shutdown())
-        } else {
+        if !self.initialized.load(Ordering::SeqCst) {
             Box::new(future::err(not_initialized_error()))
+        } else if self.shut_down.swap(true, Ordering::SeqCst) {
+            Box::new(future::err(shut_down_error()))
+        } else {
+            Box::new(self.server.shutdown())
         }
     }
 
+    fn work_done_progress_cancel(&self, params: Params) {
+        self.delegate_notification::<WorkDoneProgressCancel, _>(params, |p, params| {
+            p.cancel_progress(&params.token);
+            self.server.work_done_progress_cancel(p, params)
+        });
+    }
+
+    fn progress(&self, params: Params) {
+        self.delegate_notification::<Progress, _>(params, |p, params| {
+            self.server.progress(p, params)
+        });
+    }
+
     fn did_change_workspace_folders(&self, params: Params) {
         self.delegate_notification::<DidChangeWorkspaceFolders, _>(params, |p, params| {
             self.server.did_change_workspace_folders(p, params)
@@ -193,24 +542,69 @@ impl<T: LanguageServer> LanguageServerCore for Delegate<T> {
         });
     }
 
-    fn symbol(&self, params: Params) -> BoxFuture<Option<Vec<SymbolInformation>>> {
-        self.delegate_request::<WorkspaceSymbol, _>(params, |p| Box::new(self.server.symbol(p)))
+    fn symbol(&self, params: Params) -> BoxFuture<Value> {
+        self.delegate_request::<WorkspaceSymbolRequest, _>(params, |p| {
+            Box::new(self.server.symbol(p))
+        })
+    }
+
+    fn symbol_resolve(&self, params: Params) -> BoxFuture<Value> {
+        self.delegate_request::<WorkspaceSymbolResolve, _>(params, |p| {
+            self.server.symbol_resolve(p)
+        })
     }
 
-    fn execute_command(&self, params: Params) -> BoxFuture<Option<Value>> {
+    fn execute_command(&self, params: Params) -> BoxFuture<Value> {
         self.delegate_request::<ExecuteCommand, _>(params, |p| {
-            Box::new(self.server.execute_command(&self.printer, p))
+            match self.commands.get(&p.command) {
+                Some(command) => (command.0)(&self.printer, p.arguments),
+                None => Box::new(self.server.execute_command(&self.printer, p)),
+            }
         })
     }
 
+    fn will_create_files(&self, params: Params) -> BoxFuture<Value> {
+        self.delegate_request::<WillCreateFiles, _>(params, |p| self.server.will_create_files(p))
+    }
+
+    fn did_create_files(&self, params: Params) {
+        self.delegate_notification::<DidCreateFiles, _>(params, |p, params| {
+            self.server.did_create_files(p, params)
+        });
+    }
+
+    fn will_rename_files(&self, params: Params) -> BoxFuture<Value> {
+        self.delegate_request::<WillRenameFiles, _>(params, |p| self.server.will_rename_files(p))
+    }
+
+    fn did_rename_files(&self, params: Params) {
+        self.delegate_notification::<DidRenameFiles, _>(params, |p, params| {
+            self.server.did_rename_files(p, params)
+        });
+    }
+
+    fn will_delete_files(&self, params: Params) -> BoxFuture<Value> {
+        self.delegate_request::<WillDeleteFiles, _>(params, |p| self.server.will_delete_files(p))
+    }
+
+    fn did_delete_files(&self, params: Params) {
+        self.delegate_notification::<DidDeleteFiles, _>(params, |p, params| {
+            self.server.did_delete_files(p, params)
+        });
+    }
+
     fn did_open(&self, params: Params) {
         self.delegate_notification::<DidOpenTextDocument, _>(params, |p, params| {
+            let uri = params.text_document.uri.clone();
+            p.set_document_version(uri, Some(params.text_document.version));
             self.server.did_open(p, params)
         });
     }
 
     fn did_change(&self, params: Params) {
         self.delegate_notification::<DidChangeTextDocument, _>(params, |p, params| {
+            let uri = params.text_document.uri.clone();
+            p.set_document_version(uri, Some(params.text_document.version));
             self.server.did_change(p, params)
         });
     }
@@ -223,33 +617,328 @@ impl<T: LanguageServer> LanguageServerCore for Delegate<T> {
 
     fn did_close(&self, params: Params) {
         self.delegate_notification::<DidCloseTextDocument, _>(params, |p, params| {
+            let uri = params.text_document.uri.clone();
+            p.set_document_version(uri, None);
             self.server.did_close(p, params)
         });
     }
 
-    fn completion(&self, params: Params) -> BoxFuture<Option<CompletionResponse>> {
+    fn completion(&self, params: Params) -> BoxFuture<Value> {
         self.delegate_request::<Completion, _>(params, |p| Box::new(self.server.completion(p)))
     }
 
-    fn hover(&self, params: Params) -> BoxFuture<Option<Hover>> {
+    fn completion_resolve(&self, params: Params) -> BoxFuture<Value> {
+        self.delegate_request::<ResolveCompletionItem, _>(params, |p| {
+            self.server.completion_resolve(p)
+        })
+    }
+
+    fn hover(&self, params: Params) -> BoxFuture<Value> {
         self.delegate_request::<HoverRequest, _>(params, |p| Box::new(self.server.hover(p)))
     }
 
-    fn document_highlight(&self, params: Params) -> BoxFuture<Option<Vec<DocumentHighlight>>> {
+    fn goto_declaration(&self, params: Params) -> BoxFuture<Value> {
+        self.delegate_request::<GotoDeclaration, _>(params, |p| self.server.goto_declaration(p))
+    }
+
+    fn goto_type_definition(&self, params: Params) -> BoxFuture<Value> {
+        self.delegate_request::<GotoTypeDefinition, _>(params, |p| {
+            self.server.goto_type_definition(p)
+        })
+    }
+
+    fn goto_implementation(&self, params: Params) -> BoxFuture<Value> {
+        self.delegate_request::<GotoImplementation, _>(params, |p| {
+            self.server.goto_implementation(p)
+        })
+    }
+
+    fn signature_help(&self, params: Params) -> BoxFuture<Value> {
+        self.delegate_request::<SignatureHelpRequest, _>(params, |p| self.server.signature_help(p))
+    }
+
+    fn document_highlight(&self, params: Params) -> BoxFuture<Value> {
         self.delegate_request::<DocumentHighlightRequest, _>(params, |p| {
             Box::new(self.server.document_highlight(p))
         })
     }
+
+    fn code_action(&self, params: Params) -> BoxFuture<Value> {
+        self.delegate_request::<CodeActionRequest, _>(params, |p| self.server.code_action(p))
+    }
+
+    fn code_action_resolve(&self, params: Params) -> BoxFuture<Value> {
+        self.delegate_request::<CodeActionResolveRequest, _>(params, |p| {
+            self.server.code_action_resolve(p)
+        })
+    }
+
+    fn code_lens(&self, params: Params) -> BoxFuture<Value> {
+        self.delegate_request::<CodeLensRequest, _>(params, |p| self.server.code_lens(p))
+    }
+
+    fn code_lens_resolve(&self, params: Params) -> BoxFuture<Value> {
+        self.delegate_request::<CodeLensResolve, _>(params, |p| self.server.code_lens_resolve(p))
+    }
+
+    fn document_link(&self, params: Params) -> BoxFuture<Value> {
+        self.delegate_request::<DocumentLinkRequest, _>(params, |p| self.server.document_link(p))
+    }
+
+    fn document_link_resolve(&self, params: Params) -> BoxFuture<Value> {
+        self.delegate_request::<DocumentLinkResolve, _>(params, |p| {
+            self.server.document_link_resolve(p)
+        })
+    }
+
+    fn document_color(&self, params: Params) -> BoxFuture<Value> {
+        self.delegate_request::<DocumentColor, _>(params, |p| self.server.document_color(p))
+    }
+
+    fn color_presentation(&self, params: Params) -> BoxFuture<Value> {
+        self.delegate_request::<ColorPresentationRequest, _>(params, |p| {
+            self.server.color_presentation(p)
+        })
+    }
+
+    fn on_type_formatting(&self, params: Params) -> BoxFuture<Value> {
+        self.delegate_request::<OnTypeFormatting, _>(params, |p| self.server.on_type_formatting(p))
+    }
+
+    fn range_formatting(&self, params: Params) -> BoxFuture<Value> {
+        self.delegate_request::<RangeFormatting, _>(params, |p| self.server.range_formatting(p))
+    }
+
+    fn folding_range(&self, params: Params) -> BoxFuture<Value> {
+        self.delegate_request::<FoldingRangeRequest, _>(params, |p| self.server.folding_range(p))
+    }
+
+    fn selection_range(&self, params: Params) -> BoxFuture<Value> {
+        self.delegate_request::<SelectionRangeRequest, _>(params, |p| {
+            self.server.selection_range(p)
+        })
+    }
+
+    fn linked_editing_range(&self, params: Params) -> BoxFuture<Value> {
+        self.delegate_request::<LinkedEditingRange, _>(params, |p| {
+            self.server.linked_editing_range(p)
+        })
+    }
+
+    fn moniker(&self, params: Params) -> BoxFuture<Value> {
+        self.delegate_request::<MonikerRequest, _>(params, |p| self.server.moniker(p))
+    }
+
+    fn inlay_hint(&self, params: Params) -> BoxFuture<Value> {
+        self.delegate_request::<InlayHintRequest, _>(params, |p| self.server.inlay_hint(p))
+    }
+
+    fn inlay_hint_resolve(&self, params: Params) -> BoxFuture<Value> {
+        self.delegate_request::<InlayHintResolveRequest, _>(params, |p| {
+            self.server.inlay_hint_resolve(p)
+        })
+    }
+
+    fn semantic_tokens_full(&self, params: Params) -> BoxFuture<Value> {
+        self.delegate_request::<request::SemanticTokensFullRequest, _>(params, |p| {
+            self.server.semantic_tokens_full(p)
+        })
+    }
+
+    fn semantic_tokens_full_delta(&self, params: Params) -> BoxFuture<Value> {
+        self.delegate_request::<request::SemanticTokensFullDeltaRequest, _>(params, |p| {
+            self.server.semantic_tokens_full_delta(p)
+        })
+    }
+
+    fn semantic_tokens_range(&self, params: Params) -> BoxFuture<Value> {
+        self.delegate_request::<request::SemanticTokensRangeRequest, _>(params, |p| {
+            self.server.semantic_tokens_range(p)
+        })
+    }
+
+    fn rename(&self, params: Params) -> BoxFuture<Value> {
+        self.delegate_request::<Rename, _>(params, |p| self.server.rename(p))
+    }
+
+    fn prepare_rename(&self, params: Params) -> BoxFuture<Value> {
+        self.delegate_request::<PrepareRenameRequest, _>(params, |p| self.server.prepare_rename(p))
+    }
+
+    fn prepare_call_hierarchy(&self, params: Params) -> BoxFuture<Value> {
+        self.delegate_request::<CallHierarchyPrepare, _>(params, |p| {
+            self.server.prepare_call_hierarchy(p)
+        })
+    }
+
+    fn incoming_calls(&self, params: Params) -> BoxFuture<Value> {
+        self.delegate_request::<CallHierarchyIncomingCalls, _>(params, |p| {
+            self.server.incoming_calls(p)
+        })
+    }
+
+    fn outgoing_calls(&self, params: Params) -> BoxFuture<Value> {
+        self.delegate_request::<CallHierarchyOutgoingCalls, _>(params, |p| {
+            self.server.outgoing_calls(p)
+        })
+    }
+
+    fn prepare_type_hierarchy(&self, params: Params) -> BoxFuture<Value> {
+        self.delegate_request::<TypeHierarchyPrepare, _>(params, |p| {
+            self.server.prepare_type_hierarchy(p)
+        })
+    }
+
+    fn supertypes(&self, params: Params) -> BoxFuture<Value> {
+        self.delegate_request::<TypeHierarchySupertypes, _>(params, |p| self.server.supertypes(p))
+    }
+
+    fn subtypes(&self, params: Params) -> BoxFuture<Value> {
+        self.delegate_request::<TypeHierarchySubtypes, _>(params, |p| self.server.subtypes(p))
+    }
+
+    fn diagnostic(&self, params: Params) -> BoxFuture<Value> {
+        self.delegate_request::<DocumentDiagnosticRequest, _>(params, |p| self.server.diagnostic(p))
+    }
+
+    fn workspace_diagnostic(&self, params: Params) -> BoxFuture<Value> {
+        self.delegate_request::<WorkspaceDiagnosticRequest, _>(params, |p| {
+            self.server.workspace_diagnostic(p)
+        })
+    }
+}
+
+/// Serializes the result of a request, turning failures into an `InternalError` response.
+///
+/// Results of custom types may fail to serialize, e.g. maps with non-string keys. This is logged
+/// and reported to the client instead of taking down the session.
+fn serialize_result<T: Serialize>(method: &str, result: T) -> RpcResult<Value> {
+    serde_json::to_value(result).map_err(|e| {
+        error!("failed to serialize result of `{}` request: {}", method, e);
+        Error::internal_error()
+    })
+}
+
+/// Fields holding arbitrary values chosen by the server or the client, e.g. the `data` of a
+/// completion item or the `arguments` of a command, which must be passed on verbatim.
+const OPAQUE_FIELDS: &[&str] = &["data", "arguments", "experimental", "settings"];
+
+/// Fields which must be present even when `null`, given as the name of the enclosing object
+/// followed by the name of the field.
+///
+/// The `version` of an `OptionalVersionedTextDocumentIdentifier` in a text document edit is the
+/// only such field which may be part of a result.
+const REQUIRED_NULLABLE_FIELDS: &[(&str, &str)] = &[("textDocument", "version")];
+
+/// Recursively removes object fields which are `null`, e.g. from `Option::None` values.
+///
+/// A `null` value itself is kept, as are `null` elements of arrays, so that a result of `null`
+/// stays a valid response. Opaque values and fields required to be present are kept as well.
+fn strip_nulls(value: Value) -> Value {
+    strip_null_fields(None, value)
+}
+
+/// Removes `null` fields from `value`, which is found in the field named `parent`, if any.
+fn strip_null_fields(parent: Option<&str>, value: Value) -> Value {
+    match value {
+        Value::Object(map) => Value::Object(
+            map.into_iter()
+                .filter(|(key, value)| {
+                    !value.is_null()
+                        || parent.is_some_and(|parent| {
+                            REQUIRED_NULLABLE_FIELDS.contains(&(parent, key.as_str()))
+                        })
+                })
+                .map(|(key, value)| {
+                    let value = if OPAQUE_FIELDS.contains(&key.as_str()) {
+                        value
+                    } else {
+                        strip_null_fields(Some(&key), value)
+                    };
+                    (key, value)
+                })
+                .collect(),
+        ),
+        Value::Array(values) => Value::Array(
+            values
+                .into_iter()
+                .map(|value| strip_null_fields(parent, value))
+                .collect(),
+        ),
+        value => value,
+    }
 }
 
 /// Error response returned for every request received before the server is initialized.
 ///
 /// See [here](https://microsoft.github.io/language-server-protocol/specification#initialize) for
 /// reference.
-fn not_initialized_error() -> Error {
+pub(crate) fn not_initialized_error() -> Error {
     Error {
         code: ErrorCode::ServerError(-32002),
         message: "Server not initialized".to_string(),
         data: None,
     }
 }
+
+/// Error response returned for every request received after the server has been shut down.
+///
+/// See [here](https://microsoft.github.io/language-server-protocol/specification#shutdown) for
+/// reference.
+pub(crate) fn shut_down_error() -> Error {
+    Error {
+        code: ErrorCode::InvalidRequest,
+        message: "Server has been shut down".to_string(),
+        data: None,
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn reports_unserializable_results() {
+        let mut result = HashMap::new();
+        result.insert((1, 2), "non-string key");
+        let error = serialize_result("custom/method", result).unwrap_err();
+        assert_eq!(error, Error::internal_error());
+
+        let result = serialize_result("custom/method", Some(vec![1, 2]));
+        assert_eq!(result, Ok(serde_json::json!([1, 2])));
+    }
+
+    #[test]
+    fn strips_null_fields() {
+        let result = serde_json::json!({
+            "items": [{ "label": "foo", "detail": null, "textEdit": { "range": null } }, null],
+            "isIncomplete": false,
+            "itemDefaults": null,
+        });
+        let expected = serde_json::json!({
+            "items": [{ "label": "foo", "textEdit": {} }, null],
+            "isIncomplete": false,
+        });
+        assert_eq!(strip_nulls(result), expected);
+        assert_eq!(strip_nulls(Value::Null), Value::Null);
+    }
+
+    #[test]
+    fn keeps_opaque_and_required_null_fields() {
+        let result = serde_json::json!([{
+            "title": "Remove unused import",
+            "kind": null,
+            "data": { "nested": { "hint": null } },
+            "command": { "title": "Run", "command": "run", "arguments": [{ "id": null }] },
+            "edit": {
+                "documentChanges": [{
+                    "textDocument": { "uri": "file:///foo.rs", "version": null },
+                    "edits": [],
+                }],
+            },
+        }]);
+        let mut expected = result.clone();
+        expected[0].as_object_mut().unwrap().remove("kind");
+        assert_eq!(strip_nulls(result), expected);
+    }
+}
@@ -0,0 +1,296 @@
+//! Coalescing and rate limiting of published diagnostics.
+
+use std::collections::HashMap;
+use std::sync::{Arc, Condvar, Mutex, MutexGuard};
+use std::thread;
+use std::time::{Duration, Instant};
+
+use log::{error, trace};
+use lsp_types::{Diagnostic, Url};
+
+use super::Printer;
+
+/// Publishes diagnostics to the client, limiting how often they are sent for each URI.
+///
+/// While a document is being edited rapidly, diagnostics may be recomputed far more often than the
+/// client can usefully display them. Once the limit for a URI has been reached, further sets are
+/// coalesced: intermediate sets are dropped and only the latest one is held back until it may be
+/// sent. The latest set for each URI is sent eventually, even if the publisher is dropped in the
+/// meantime, unless it was computed against a document version which has since been superseded by
+/// an edit.
+///
+/// Cloning a `DiagnosticsPublisher` is cheap, since all clones share the same limits.
+#[derive(Clone, Debug)]
+pub struct DiagnosticsPublisher {
+    inner: Arc<Inner>,
+}
+
+#[derive(Debug)]
+struct Inner {
+    printer: Printer,
+    interval: Duration,
+    state: Mutex<State>,
+    wakeup: Condvar,
+}
+
+#[derive(Debug, Default)]
+struct State {
+    last_sent: HashMap<Url, Instant>,
+    pending: HashMap<Url, (Vec<Diagnostic>, Option<i32>)>,
+    worker: bool,
+}
+
+impl DiagnosticsPublisher {
+    /// Creates a new publisher which sends at most `max_per_second` sets of diagnostics per URI.
+    ///
+    /// # Panics
+    ///
+    /// Panics if `max_per_second` is zero.
+    pub fn new(printer: Printer, max_per_second: u32) -> Self {
+        assert!(
+            max_per_second > 0,
+            "diagnostics rate limit must be positive"
+        );
+        DiagnosticsPublisher::with_interval(printer, Duration::from_secs(1) / max_per_second)
+    }
+
+    /// Creates a new publisher which waits at least `interval` between two sets of diagnostics for
+    /// the same URI.
+    pub fn with_interval(printer: Printer, interval: Duration) -> Self {
+        DiagnosticsPublisher {
+            inner: Arc::new(Inner {
+                printer,
+                interval,
+                state: Mutex::new(State::default()),
+                wakeup: Condvar::new(),
+            }),
+        }
+    }
+
+    /// Submits validation diagnostics for an open file with the given URI.
+    ///
+    /// The diagnostics are sent right away if the limit for `uri` permits it. Otherwise, they
+    /// replace any set still held back for `uri` and are sent in the background once the limit has
+    /// elapsed.
+    ///
+    /// See [`Printer::publish_diagnostics`] for details.
+    ///
+    /// [`Printer::publish_diagnostics`]: ./struct.Printer.html#method.publish_diagnostics
+    pub fn publish(&self, uri: Url, diags: Vec<Diagnostic>, version: Option<i32>) {
+        let now = Instant::now();
+        let mut state = self.inner.lock();
+
+        let interval = self.inner.interval;
+        let State {
+            last_sent, pending, ..
+        } = &mut *state;
+        last_sent.retain(|uri, sent| pending.contains_key(uri) || now < *sent + interval);
+
+        if !last_sent.contains_key(&uri) {
+            last_sent.insert(uri.clone(), now);
+            self.inner.printer.publish_diagnostics(uri, diags, version);
+            return;
+        }
+
+        pending.insert(uri, (diags, version));
+        if state.worker {
+            self.inner.wakeup.notify_one();
+        } else {
+            state.worker = true;
+            drop(state);
+            self.spawn_worker();
+        }
+    }
+
+    /// Submits validation diagnostics for an open file, tagged with its latest known version.
+    ///
+    /// The version is taken from [`Printer::document_version`] at the time of the call, so this
+    /// should be called with diagnostics computed against the current document contents. Should
+    /// the document be edited again before a held back set is sent, that set is dropped as stale
+    /// rather than flickering briefly in the client.
+    ///
+    /// [`Printer::document_version`]: ./struct.Printer.html#method.document_version
+    pub fn publish_current(&self, uri: Url, diags: Vec<Diagnostic>) {
+        let version = self.inner.printer.document_version(&uri);
+        self.publish(uri, diags, version);
+    }
+
+    fn spawn_worker(&self) {
+        let inner = self.inner.clone();
+        let result = thread::Builder::new()
+            .name("diagnostics".into())
+            .spawn(move || inner.run());
+
+        if let Err(e) = result {
+            error!("failed to start publishing held back diagnostics: {}", e);
+            let mut state = self.inner.lock();
+            state.worker = false;
+            for (uri, (diags, version)) in state.pending.drain() {
+                self.inner.printer.publish_diagnostics(uri, diags, version);
+            }
+        }
+    }
+}
+
+impl Inner {
+    fn lock(&self) -> MutexGuard<'_, State> {
+        self.state.lock().unwrap_or_else(|s| s.into_inner())
+    }
+
+    /// Sends held back diagnostics as their limits elapse, until none are left.
+    fn run(&self) {
+        let mut state = self.lock();
+        loop {
+            let now = Instant::now();
+            let due: Vec<_> = state
+                .pending
+                .keys()
+                .filter(|uri| self.deadline(&state, uri) <= now)
+                .cloned()
+                .collect();
+
+            for uri in due {
+                let (diags, version) = state.pending.remove(&uri).unwrap();
+                if self.is_stale(&uri, version) {
+                    trace!("dropping stale diagnostics for {} at {:?}", uri, version);
+                    continue;
+                }
+
+                state.last_sent.insert(uri.clone(), now);
+                self.printer.publish_diagnostics(uri, diags, version);
+            }
+
+            let next = state
+                .pending
+                .keys()
+                .map(|uri| self.deadline(&state, uri))
+                .min();
+            match next {
+                Some(deadline) => {
+                    let timeout = deadline.saturating_duration_since(now);
+                    state = match self.wakeup.wait_timeout(state, timeout) {
+                        Ok((state, _)) => state,
+                        Err(e) => e.into_inner().0,
+                    };
+                }
+                None => {
+                    state.worker = false;
+                    break;
+                }
+            }
+        }
+    }
+
+    /// Returns whether diagnostics tagged with `version` were computed against an outdated revision
+    /// of the document with the given URI.
+    fn is_stale(&self, uri: &Url, version: Option<i32>) -> bool {
+        match (version, self.printer.document_version(uri)) {
+            (Some(version), Some(current)) => version != current,
+            _ => false,
+        }
+    }
+
+    /// Returns the earliest time at which diagnostics for `uri` may be sent again.
+    fn deadline(&self, state: &State, uri: &Url) -> Instant {
+        match state.last_sent.get(uri) {
+            Some(sent) => *sent + self.interval,
+            None => Instant::now(),
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use std::sync::atomic::AtomicBool;
+
+    use futures::sync::mpsc;
+    use futures::{Future, Stream};
+    use lsp_types::notification::{Notification, PublishDiagnostics};
+    use lsp_types::PublishDiagnosticsParams;
+    use serde_json::{json, Value};
+
+    use super::*;
+    use crate::delegate::MessageStream;
+
+    fn published(uri: &Url, version: i32) -> Value {
+        let params = PublishDiagnosticsParams::new(uri.clone(), Vec::new(), Some(version));
+        json!({
+            "jsonrpc": "2.0",
+            "method": PublishDiagnostics::METHOD,
+            "params": params,
+        })
+    }
+
+    #[test]
+    fn coalesces_and_sends_latest() {
+        let (tx, rx) = mpsc::unbounded();
+        let printer = Printer::new(tx, Arc::new(AtomicBool::new(true)));
+        let publisher = DiagnosticsPublisher::with_interval(printer, Duration::from_millis(50));
+
+        let foo: Url = "file:///foo.rs".parse().unwrap();
+        let bar: Url = "file:///bar.rs".parse().unwrap();
+        for version in 0..5 {
+            publisher.publish(foo.clone(), Vec::new(), Some(version));
+        }
+        publisher.publish(bar.clone(), Vec::new(), Some(0));
+        drop(publisher);
+
+        let messages = MessageStream(rx).collect().wait().unwrap();
+        let messages: Vec<Value> = messages
+            .iter()
+            .map(|m| serde_json::from_str(m).unwrap())
+            .collect();
+        let expected: Vec<_> = [(&foo, 0), (&bar, 0), (&foo, 4)]
+            .iter()
+            .map(|&(uri, version)| published(uri, version))
+            .collect();
+        assert_eq!(messages, expected);
+    }
+
+    #[test]
+    fn drops_stale_diagnostics() {
+        let (tx, rx) = mpsc::unbounded();
+        let printer = Printer::new(tx, Arc::new(AtomicBool::new(true)));
+        let publisher =
+            DiagnosticsPublisher::with_interval(printer.clone(), Duration::from_millis(50));
+
+        let foo: Url = "file:///foo.rs".parse().unwrap();
+        let bar: Url = "file:///bar.rs".parse().unwrap();
+        printer.set_document_version(foo.clone(), Some(1));
+        printer.set_document_version(bar.clone(), Some(1));
+        for uri in &[&foo, &foo, &bar, &bar] {
+            publisher.publish_current((*uri).clone(), Vec::new());
+        }
+        printer.set_document_version(foo.clone(), Some(2));
+        drop((publisher, printer));
+
+        let messages = MessageStream(rx).collect().wait().unwrap();
+        let messages: Vec<Value> = messages
+            .iter()
+            .map(|m| serde_json::from_str(m).unwrap())
+            .collect();
+        let expected: Vec<_> = [(&foo, 1), (&bar, 1), (&bar, 1)]
+            .iter()
+            .map(|&(uri, version)| published(uri, version))
+            .collect();
+        assert_eq!(messages, expected);
+    }
+
+    #[test]
+    fn sends_immediately_once_limit_elapsed() {
+        let (tx, rx) = mpsc::unbounded();
+        let printer = Printer::new(tx, Arc::new(AtomicBool::new(true)));
+        let publisher = DiagnosticsPublisher::new(printer, 100);
+
+        let uri: Url = "file:///foo.rs".parse().unwrap();
+        publisher.publish(uri.clone(), Vec::new(), Some(0));
+        thread::sleep(Duration::from_millis(20));
+        publisher.publish(uri.clone(), Vec::new(), Some(1));
+
+        let (first, rx) = MessageStream(rx).into_future().wait().ok().unwrap();
+        let (second, _) = rx.into_future().wait().ok().unwrap();
+        assert!(first.unwrap().contains(r#""version":0"#));
+        assert!(second.unwrap().contains(r#""version":1"#));
+        assert!(!publisher.inner.lock().worker);
+    }
+}
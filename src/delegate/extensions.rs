@@ -0,0 +1,91 @@
+//! Shared state attached to the `Printer` for access from handlers.
+
+use std::any::{Any, TypeId};
+use std::collections::HashMap;
+use std::fmt::{self, Debug, Formatter};
+use std::sync::Arc;
+
+/// Type map holding shared state of the language server, such as a database handle or a cache.
+///
+/// At most one value of each type is stored. Values are kept behind an `Arc`, so that handlers
+/// may retrieve them from the `Printer` without holding on to any lock.
+///
+/// Extensions are usually registered at startup with [`LspServiceBuilder::extension`] and are
+/// retrieved with [`Printer::extension`].
+///
+/// [`LspServiceBuilder::extension`]: ./struct.LspServiceBuilder.html#method.extension
+/// [`Printer::extension`]: ./struct.Printer.html#method.extension
+#[derive(Clone, Default)]
+pub struct Extensions(HashMap<TypeId, Arc<dyn Any + Send + Sync>>);
+
+impl Extensions {
+    /// Creates a new, empty type map.
+    pub fn new() -> Self {
+        Extensions::default()
+    }
+
+    /// Inserts a value, returning the previous value of the same type, if any.
+    pub fn insert<T: Send + Sync + 'static>(&mut self, value: T) -> Option<Arc<T>> {
+        self.0
+            .insert(TypeId::of::<T>(), Arc::new(value))
+            .and_then(|previous| previous.downcast().ok())
+    }
+
+    /// Returns the value of the given type, if any.
+    pub fn get<T: Send + Sync + 'static>(&self) -> Option<Arc<T>> {
+        self.0
+            .get(&TypeId::of::<T>())
+            .and_then(|value| value.clone().downcast().ok())
+    }
+
+    /// Removes and returns the value of the given type, if any.
+    pub fn remove<T: Send + Sync + 'static>(&mut self) -> Option<Arc<T>> {
+        self.0
+            .remove(&TypeId::of::<T>())
+            .and_then(|value| value.downcast().ok())
+    }
+
+    /// Returns the number of stored values.
+    pub fn len(&self) -> usize {
+        self.0.len()
+    }
+
+    /// Returns whether no values are stored.
+    pub fn is_empty(&self) -> bool {
+        self.0.is_empty()
+    }
+}
+
+impl Debug for Extensions {
+    fn fmt(&self, fmt: &mut Formatter) -> fmt::Result {
+        fmt.debug_tuple(stringify!(Extensions))
+            .field(&self.0.len())
+            .finish()
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use std::sync::Mutex;
+
+    use super::*;
+
+    #[test]
+    fn stores_one_value_per_type() {
+        let mut extensions = Extensions::new();
+        assert_eq!(extensions.insert(1u32), None);
+        assert_eq!(extensions.insert(Mutex::new(vec!["foo"])).map(|_| ()), None);
+        assert_eq!(extensions.insert(2u32), Some(Arc::new(1)));
+        assert_eq!(extensions.len(), 2);
+
+        assert_eq!(extensions.get::<u32>(), Some(Arc::new(2)));
+        assert_eq!(extensions.get::<u64>(), None);
+        let cache = extensions.get::<Mutex<Vec<&str>>>().unwrap();
+        cache.lock().unwrap().push("bar");
+
+        let cache = extensions.remove::<Mutex<Vec<&str>>>().unwrap();
+        assert_eq!(*cache.lock().unwrap(), vec!["foo", "bar"]);
+        assert!(extensions.get::<Mutex<Vec<&str>>>().is_none());
+        assert_eq!(extensions.len(), 1);
+    }
+}
@@ -1,36 +1,337 @@
 //! Types for sending data back to the language client.
 
+use std::collections::HashMap;
 use std::fmt::Display;
-use std::sync::atomic::{AtomicBool, AtomicU64, Ordering};
-use std::sync::Arc;
+use std::sync::atomic::{AtomicBool, AtomicU64, AtomicUsize, Ordering};
+use std::sync::{Arc, Mutex, RwLock};
 
-use futures::sync::mpsc::Sender;
-use futures::{Future, Sink};
-use jsonrpc_core::types::{request, Id, Version};
-use log::{error, trace};
+use futures::sync::mpsc::UnboundedSender;
+use futures::sync::oneshot;
+use futures::Future;
+use jsonrpc_core::types::{request, Id, Output, Params, Version};
+use jsonrpc_core::{Error as RpcError, Result as RpcResult};
+use log::{debug, error, trace, warn};
 use lsp_types::notification::{Notification, *};
-use lsp_types::request::{ApplyWorkspaceEdit, RegisterCapability, Request, UnregisterCapability};
+use lsp_types::request::{
+    ApplyWorkspaceEdit, RegisterCapability, Request, UnregisterCapability, WorkspaceConfiguration,
+};
 use lsp_types::*;
+use serde::de::DeserializeOwned;
 use serde::Serialize;
 use serde_json::Value;
 
+use super::extensions::Extensions;
+use super::progress::{CancellationToken, Cancellations, ProgressReporter};
+use crate::{content_modified_error, WorkspaceEditBuilder};
+
 /// Sends notifications from the language server to the client.
-#[derive(Debug)]
+///
+/// Messages are enqueued in a single outbound queue and are delivered to the client in the same
+/// order in which they were sent.
+///
+/// Cloning a `Printer` is cheap, since all clones share the same queue and state. This allows
+/// moving a clone into each concurrently running handler or background task.
+#[derive(Clone, Debug)]
 pub struct Printer {
-    buffer: Sender<String>,
+    inner: Arc<Inner>,
+}
+
+#[derive(Debug)]
+struct Inner {
+    buffer: UnboundedSender<Outgoing>,
     initialized: Arc<AtomicBool>,
     request_id: AtomicU64,
+    request_id_prefix: RwLock<Option<String>>,
+    capabilities: RwLock<Option<ClientCapabilities>>,
+    trace: RwLock<TraceValue>,
+    pending: Mutex<Option<Vec<Outgoing>>>,
+    held_requests: Mutex<Vec<Outgoing>>,
+    versions: RwLock<HashMap<Url, i32>>,
+    cancellations: Cancellations,
+    responses: Mutex<HashMap<Id, oneshot::Sender<Output>>>,
+    queued: Arc<AtomicUsize>,
+    max_queued: AtomicUsize,
+    extensions: RwLock<Extensions>,
 }
 
 impl Printer {
-    pub(super) const fn new(buffer: Sender<String>, initialized: Arc<AtomicBool>) -> Self {
+    pub(super) fn new(buffer: UnboundedSender<Outgoing>, initialized: Arc<AtomicBool>) -> Self {
         Printer {
-            buffer,
-            initialized,
-            request_id: AtomicU64::new(0),
+            inner: Arc::new(Inner {
+                buffer,
+                initialized,
+                request_id: AtomicU64::new(0),
+                request_id_prefix: RwLock::new(None),
+                capabilities: RwLock::new(None),
+                trace: RwLock::new(TraceValue::Off),
+                pending: Mutex::new(None),
+                held_requests: Mutex::new(Vec::new()),
+                versions: RwLock::new(HashMap::new()),
+                cancellations: Cancellations::default(),
+                responses: Mutex::new(HashMap::new()),
+                queued: Arc::new(AtomicUsize::new(0)),
+                max_queued: AtomicUsize::new(usize::MAX),
+                extensions: RwLock::new(Extensions::new()),
+            }),
+        }
+    }
+
+    /// Returns the capabilities announced by the client in the [`initialize`] request.
+    ///
+    /// Returns `None` if the `initialize` request has not been received yet.
+    ///
+    /// [`initialize`]: https://microsoft.github.io/language-server-protocol/specification#initialize
+    pub fn client_capabilities(&self) -> Option<ClientCapabilities> {
+        let capabilities = self
+            .inner
+            .capabilities
+            .read()
+            .unwrap_or_else(|c| c.into_inner());
+        capabilities.clone()
+    }
+
+    /// Returns a builder for a `WorkspaceEdit` in the representation supported by the client.
+    ///
+    /// If the `initialize` request has not been received yet, the builder falls back to the plain
+    /// `changes` representation, which every client understands.
+    pub fn workspace_edit(&self) -> WorkspaceEditBuilder {
+        let capabilities = self
+            .inner
+            .capabilities
+            .read()
+            .unwrap_or_else(|c| c.into_inner());
+        match *capabilities {
+            Some(ref capabilities) => WorkspaceEditBuilder::new(capabilities),
+            None => WorkspaceEditBuilder::default(),
         }
     }
 
+    /// Returns the shared state of the given type registered with the language server, if any.
+    ///
+    /// This allows handlers to access e.g. a database handle or a parser cache without resorting to
+    /// global statics. State is usually registered at startup with
+    /// [`LspServiceBuilder::extension`], or later with [`insert_extension`].
+    ///
+    /// [`LspServiceBuilder::extension`]: ./struct.LspServiceBuilder.html#method.extension
+    /// [`insert_extension`]: #method.insert_extension
+    pub fn extension<T: Send + Sync + 'static>(&self) -> Option<Arc<T>> {
+        let extensions = self
+            .inner
+            .extensions
+            .read()
+            .unwrap_or_else(|e| e.into_inner());
+        extensions.get()
+    }
+
+    /// Registers shared state with the language server, returning the previous state of the same
+    /// type, if any.
+    ///
+    /// The state is shared by all clones of this `Printer`.
+    pub fn insert_extension<T: Send + Sync + 'static>(&self, value: T) -> Option<Arc<T>> {
+        let mut extensions = self
+            .inner
+            .extensions
+            .write()
+            .unwrap_or_else(|e| e.into_inner());
+        extensions.insert(value)
+    }
+
+    pub(super) fn set_extensions(&self, extensions: Extensions) {
+        let mut current = self
+            .inner
+            .extensions
+            .write()
+            .unwrap_or_else(|e| e.into_inner());
+        *current = extensions;
+    }
+
+    pub(super) fn set_client_capabilities(&self, capabilities: ClientCapabilities) {
+        let mut current = self
+            .inner
+            .capabilities
+            .write()
+            .unwrap_or_else(|c| c.into_inner());
+        *current = Some(capabilities);
+    }
+
+    /// Returns the markup kind preferred by the client for the contents of hover results.
+    ///
+    /// This is the first kind listed in the `textDocument.hover.contentFormat` capability. Falls
+    /// back to `MarkupKind::PlainText`, which every client can display, if the client has not
+    /// announced any preference or has not been initialized yet.
+    pub fn hover_markup_kind(&self) -> MarkupKind {
+        let capabilities = self
+            .inner
+            .capabilities
+            .read()
+            .unwrap_or_else(|c| c.into_inner());
+        capabilities
+            .as_ref()
+            .and_then(|c| {
+                c.text_document
+                    .as_ref()?
+                    .hover
+                    .as_ref()?
+                    .content_format
+                    .as_ref()
+            })
+            .and_then(|formats| formats.first().cloned())
+            .unwrap_or(MarkupKind::PlainText)
+    }
+
+    /// Builds hover contents in the markup kind preferred by the client.
+    ///
+    /// Clients which cannot render Markdown receive the `plaintext` variant instead of raw
+    /// Markdown syntax. See [`hover_markup_kind`](#method.hover_markup_kind) for details.
+    pub fn hover_markup<M, P>(&self, markdown: M, plaintext: P) -> MarkupContent
+    where
+        M: Into<String>,
+        P: Into<String>,
+    {
+        match self.hover_markup_kind() {
+            MarkupKind::Markdown => MarkupContent {
+                kind: MarkupKind::Markdown,
+                value: markdown.into(),
+            },
+            MarkupKind::PlainText => MarkupContent {
+                kind: MarkupKind::PlainText,
+                value: plaintext.into(),
+            },
+        }
+    }
+
+    /// Returns the latest version of the open text document with the given URI.
+    ///
+    /// Versions are tracked from the `textDocument/didOpen` and `textDocument/didChange`
+    /// notifications. Returns `None` if the document is not open.
+    pub fn document_version(&self, uri: &Url) -> Option<i32> {
+        let versions = self
+            .inner
+            .versions
+            .read()
+            .unwrap_or_else(|v| v.into_inner());
+        versions.get(uri).cloned()
+    }
+
+    /// Checks that the text document with the given URI is still open at `version`.
+    ///
+    /// Long running requests may capture the document version when they start and call this
+    /// before responding. If the document has since been changed or closed, this returns a
+    /// [`content_modified_error`] which can be returned as is, prompting the client to retry.
+    ///
+    /// [`content_modified_error`]: ./fn.content_modified_error.html
+    pub fn ensure_document_version(&self, uri: &Url, version: i32) -> RpcResult<()> {
+        if self.document_version(uri) == Some(version) {
+            Ok(())
+        } else {
+            Err(content_modified_error())
+        }
+    }
+
+    /// Records the latest version of an open text document, or forgets it if `version` is `None`.
+    pub(super) fn set_document_version(&self, uri: Url, version: Option<i32>) {
+        let mut versions = self
+            .inner
+            .versions
+            .write()
+            .unwrap_or_else(|v| v.into_inner());
+        match version {
+            Some(version) => versions.insert(uri, version),
+            None => versions.remove(&uri),
+        };
+    }
+
+    /// Returns a token which is cancelled once the client cancels the work done progress with the
+    /// given token.
+    ///
+    /// This happens when the client sends a [`window/workDoneProgress/cancel`] notification, e.g.
+    /// because the user clicked the cancel button of a progress report. The token should be
+    /// obtained before the progress is reported to the client, so that no cancellation is missed.
+    ///
+    /// [`window/workDoneProgress/cancel`]: https://microsoft.github.io/language-server-protocol/specification#window_workDoneProgress_cancel
+    pub fn progress_cancellation(&self, token: ProgressToken) -> CancellationToken {
+        self.inner.cancellations.register(token)
+    }
+
+    /// Notifies the client that a long running operation identified by `token` has begun.
+    ///
+    /// Returns a [`ProgressReporter`] for reporting further progress, which notifies the client
+    /// that the operation has ended once it is dropped. If `cancellable` is `true`, the client may
+    /// show a cancel button, which cancels the [`cancellation`] token of the reporter.
+    ///
+    /// The token must either have been provided by the client as the `workDoneToken` of a request,
+    /// or have been created with the [`window/workDoneProgress/create`] request beforehand.
+    ///
+    /// This corresponds to the [`$/progress`] notification.
+    ///
+    /// [`ProgressReporter`]: ./struct.ProgressReporter.html
+    /// [`cancellation`]: ./struct.ProgressReporter.html#method.cancellation
+    /// [`window/workDoneProgress/create`]: https://microsoft.github.io/language-server-protocol/specification#window_workDoneProgress_create
+    /// [`$/progress`]: https://microsoft.github.io/language-server-protocol/specification#progress
+    pub fn begin_progress<T: Display>(
+        &self,
+        token: ProgressToken,
+        title: T,
+        cancellable: bool,
+    ) -> ProgressReporter {
+        let cancellation = self.progress_cancellation(token.clone());
+        let begin = WorkDoneProgressBegin {
+            title: title.to_string(),
+            cancellable: Some(cancellable),
+            message: None,
+            percentage: None,
+        };
+        self.send_progress(token.clone(), WorkDoneProgress::Begin(begin));
+        ProgressReporter::new(self.clone(), token, cancellation)
+    }
+
+    pub(super) fn send_progress(&self, token: ProgressToken, value: WorkDoneProgress) {
+        self.send_message(make_notification::<Progress>(ProgressParams {
+            token,
+            value: ProgressParamsValue::WorkDone(value),
+        }));
+    }
+
+    pub(super) fn cancel_progress(&self, token: &ProgressToken) {
+        self.inner.cancellations.cancel(token);
+    }
+
+    /// Returns the current trace level, as set by the client.
+    ///
+    /// The initial value is taken from the [`initialize`] request and may be changed by the client
+    /// at any time with the [`$/setTrace`] notification.
+    ///
+    /// [`initialize`]: https://microsoft.github.io/language-server-protocol/specification#initialize
+    /// [`$/setTrace`]: https://microsoft.github.io/language-server-protocol/specification#setTrace
+    pub fn trace_value(&self) -> TraceValue {
+        *self.inner.trace.read().unwrap_or_else(|t| t.into_inner())
+    }
+
+    pub(super) fn set_trace_value(&self, value: TraceValue) {
+        *self.inner.trace.write().unwrap_or_else(|t| t.into_inner()) = value;
+    }
+
+    /// Notifies the client to log a trace of the server's execution.
+    ///
+    /// Nothing is sent if tracing is turned off, and the `verbose` information is only included if
+    /// the [`trace_value`](#method.trace_value) is `TraceValue::Verbose`.
+    ///
+    /// This corresponds to the [`$/logTrace`] notification.
+    ///
+    /// [`$/logTrace`]: https://microsoft.github.io/language-server-protocol/specification#logTrace
+    pub fn log_trace<M: Display>(&self, message: M, verbose: Option<String>) {
+        let verbose = match self.trace_value() {
+            TraceValue::Off => return,
+            TraceValue::Messages => None,
+            TraceValue::Verbose => verbose,
+        };
+
+        self.send_message(make_notification::<LogTrace>(LogTraceParams {
+            message: message.to_string(),
+            verbose,
+        }));
+    }
+
     /// Notifies the client to log a particular message.
     ///
     /// This corresponds to the [`window/logMessage`] notification.
@@ -63,17 +364,47 @@ impl Printer {
     pub fn telemetry_event<S: Serialize>(&self, data: S) {
         match serde_json::to_value(data) {
             Err(e) => error!("invalid JSON in `telemetry/event` notification: {}", e),
+            Ok(Value::Null) => {
+                self.send_message(make_raw_notification::<TelemetryEvent>(Params::None));
+            }
+            Ok(Value::Object(map)) => {
+                self.send_message(make_notification::<TelemetryEvent>(OneOf::Left(map)));
+            }
+            Ok(Value::Array(array)) => {
+                self.send_message(make_notification::<TelemetryEvent>(OneOf::Right(array)));
+            }
             Ok(value) => {
-                if !value.is_null() && !value.is_array() && !value.is_object() {
-                    let value = Value::Array(vec![value]);
-                    self.send_message(make_notification::<TelemetryEvent>(value));
-                } else {
-                    self.send_message(make_notification::<TelemetryEvent>(value));
-                }
+                let array = vec![value];
+                self.send_message(make_notification::<TelemetryEvent>(OneOf::Right(array)));
             }
         }
     }
 
+    /// Notifies the client that the request with the given ID should be cancelled.
+    ///
+    /// This may be used to cancel requests sent by the server to the client. The ID is sent back
+    /// verbatim, so it must match the one used in the original request.
+    ///
+    /// This corresponds to the [`$/cancelRequest`] notification.
+    ///
+    /// [`$/cancelRequest`]: https://microsoft.github.io/language-server-protocol/specification#cancelRequest
+    pub fn cancel_request(&self, id: Id) {
+        let mut params = serde_json::Map::new();
+        params.insert("id".into(), serde_json::to_value(id).unwrap());
+        self.send_message(make_raw_notification::<Cancel>(Params::Map(params)));
+    }
+
+    /// Sends a fully formed JSON-RPC message to the client as is.
+    ///
+    /// This bypasses the typed helpers for messages constructed dynamically, e.g. when proxying
+    /// another server. The message is neither validated nor held back before the server has been
+    /// initialized, but is otherwise queued in order with all other messages. Responses to
+    /// requests sent this way are discarded, so their IDs should not collide with the IDs of
+    /// requests sent by the `Printer` itself.
+    pub fn send_raw(&self, message: Value) {
+        self.send_message(message.to_string());
+    }
+
     /// Register a new capability with the client.
     ///
     /// This corresponds to the [`client/registerCapability`] request.
@@ -81,11 +412,11 @@ impl Printer {
     /// [`client/registerCapability`]: https://microsoft.github.io/language-server-protocol/specification#client_registerCapability
     pub fn register_capability(&self, registrations: Vec<Registration>) {
         // FIXME: Check whether the request succeeded or failed.
-        let id = self.request_id.fetch_add(1, Ordering::SeqCst);
+        let id = self.next_request_id();
         self.send_message_initialized(make_request::<RegisterCapability>(
             id,
             RegistrationParams { registrations },
-        ))
+        ));
     }
 
     /// Unregister a capability with the client.
@@ -95,11 +426,11 @@ impl Printer {
     /// [`client/unregisterCapability`]: https://microsoft.github.io/language-server-protocol/specification#client_unregisterCapability
     pub fn unregister_capability(&self, unregisterations: Vec<Unregistration>) {
         // FIXME: Check whether the request succeeded or failed.
-        let id = self.request_id.fetch_add(1, Ordering::SeqCst);
+        let id = self.next_request_id();
         self.send_message_initialized(make_request::<UnregisterCapability>(
             id,
             UnregistrationParams { unregisterations },
-        ))
+        ));
     }
 
     /// Requests a workspace resource be edited on the client side and returns whether the edit was
@@ -110,46 +441,309 @@ impl Printer {
     /// [`workspace/applyEdit`]: https://microsoft.github.io/language-server-protocol/specification#workspace_applyEdit
     pub fn apply_edit(&self, edit: WorkspaceEdit) -> bool {
         // FIXME: Check whether the request succeeded or failed and retrieve apply status.
-        let id = self.request_id.fetch_add(1, Ordering::SeqCst);
+        let id = self.next_request_id();
         self.send_message_initialized(make_request::<ApplyWorkspaceEdit>(
             id,
-            ApplyWorkspaceEditParams { edit },
+            ApplyWorkspaceEditParams { label: None, edit },
         ));
         true
     }
 
+    /// Fetches configuration settings from the client.
+    ///
+    /// Resolves with one value per requested item, in the same order. If the client does not
+    /// know a setting, its value is `null`.
+    ///
+    /// Clients may send a `workspace/didChangeConfiguration` notification without the new
+    /// settings, in which case servers are expected to pull them with this request.
+    ///
+    /// This corresponds to the [`workspace/configuration`] request.
+    ///
+    /// [`workspace/configuration`]: https://microsoft.github.io/language-server-protocol/specification#workspace_configuration
+    pub fn configuration(
+        &self,
+        items: Vec<ConfigurationItem>,
+    ) -> impl Future<Item = Vec<Value>, Error = RpcError> + Send {
+        self.send_request::<WorkspaceConfiguration>(ConfigurationParams { items })
+    }
+
     /// Submits validation diagnostics for an open file with the given URI.
     ///
+    /// The optional `version` number identifies the version of the document the diagnostics were
+    /// computed against.
+    ///
     /// This corresponds to the [`textDocument/publishDiagnostics`] notification.
     ///
     /// [`textDocument/publishDiagnostics`]: https://microsoft.github.io/language-server-protocol/specification#textDocument_publishDiagnostics
-    pub fn publish_diagnostics(&self, uri: Url, diagnostics: Vec<Diagnostic>) {
+    pub fn publish_diagnostics(&self, uri: Url, diags: Vec<Diagnostic>, version: Option<i32>) {
         self.send_message_initialized(make_notification::<PublishDiagnostics>(
-            PublishDiagnosticsParams::new(uri, diagnostics),
+            PublishDiagnosticsParams::new(uri, diags, version),
         ));
     }
 
-    fn send_message(&self, message: String) {
-        tokio_executor::spawn(
-            self.buffer
-                .clone()
-                .send(message)
-                .map(|_| ())
-                .map_err(|_| error!("failed to send message")),
-        );
+    /// Returns a future which resolves once all messages sent so far have been handed over to the
+    /// transport.
+    ///
+    /// Messages sent through the `Printer` are queued separately from responses. Waiting for this
+    /// future before returning from a request handler therefore guarantees that, e.g., a final
+    /// progress notification is written ahead of the response. Resolves with an error if the
+    /// outbound queue has been closed.
+    pub fn flush(&self) -> impl Future<Item = (), Error = ()> + Send {
+        let (tx, rx) = oneshot::channel();
+        self.send(Outgoing::Flush(tx));
+        rx.map_err(|_| error!("failed to flush messages, outbound queue closed"))
     }
 
-    fn send_message_initialized(&self, message: String) {
-        if self.initialized.load(Ordering::SeqCst) {
+    /// Holds back all messages until [`flush_pending`] is called.
+    ///
+    /// [`flush_pending`]: #method.flush_pending
+    pub(super) fn buffer_pending(&self) {
+        let mut pending = self.inner.pending.lock().unwrap_or_else(|p| p.into_inner());
+        pending.get_or_insert_with(Vec::new);
+    }
+
+    /// Sends all messages held back since [`buffer_pending`] was called, in order, followed by
+    /// any requests held back until the client finished initializing.
+    ///
+    /// Messages sent afterwards are no longer held back.
+    ///
+    /// [`buffer_pending`]: #method.buffer_pending
+    pub(super) fn flush_pending(&self) {
+        {
+            let mut pending = self.inner.pending.lock().unwrap_or_else(|p| p.into_inner());
+            for message in pending.take().into_iter().flatten() {
+                self.enqueue(message);
+            }
+        }
+
+        self.release_held_requests();
+    }
+
+    /// Sends all requests held back before the server was initialized, in order.
+    fn release_held_requests(&self) {
+        let mut held = self
+            .inner
+            .held_requests
+            .lock()
+            .unwrap_or_else(|h| h.into_inner());
+        for message in held.drain(..) {
+            self.enqueue(message);
+        }
+    }
+
+    /// Limits the number of messages which may be queued for the client at once.
+    ///
+    /// Messages sent while the limit is reached are dropped.
+    pub(super) fn set_max_queued_messages(&self, max: usize) {
+        self.inner.max_queued.store(max, Ordering::SeqCst);
+    }
+
+    /// Sends requests to the client with string IDs made up of `prefix` and a counter.
+    pub(super) fn set_request_id_prefix(&self, prefix: String) {
+        let mut request_id_prefix = self
+            .inner
+            .request_id_prefix
+            .write()
+            .unwrap_or_else(|p| p.into_inner());
+        *request_id_prefix = Some(prefix);
+    }
+
+    /// Allocates the ID of the next request sent to the client.
+    ///
+    /// IDs are taken from a counter shared by all clones of the `Printer`, so they are unique even
+    /// when requests are sent concurrently from several threads.
+    fn next_request_id(&self) -> Id {
+        let id = self.inner.request_id.fetch_add(1, Ordering::SeqCst);
+        let prefix = self
+            .inner
+            .request_id_prefix
+            .read()
+            .unwrap_or_else(|p| p.into_inner());
+        match *prefix {
+            Some(ref prefix) => Id::Str(format!("{}{}", prefix, id)),
+            None => Id::Num(id),
+        }
+    }
+
+    /// Passes a response from the client to the request it answers, if it is still pending.
+    pub(crate) fn handle_response(&self, output: Output) {
+        let id = match output {
+            Output::Success(ref success) => success.id.clone(),
+            Output::Failure(ref failure) => failure.id.clone(),
+        };
+
+        let mut responses = self
+            .inner
+            .responses
+            .lock()
+            .unwrap_or_else(|r| r.into_inner());
+        match responses.remove(&id) {
+            Some(tx) => {
+                let _ = tx.send(output);
+            }
+            None => debug!("dropping response to unknown request: {:?}", output),
+        }
+    }
+
+    /// Sends a request to the client, returning a future which resolves with its result.
+    ///
+    /// Requests sent before the server has been initialized, e.g. from within `initialize`, are
+    /// held back rather than dropped, and are sent once the `initialized` notification has been
+    /// received.
+    fn send_request<R>(&self, params: R::Params) -> impl Future<Item = R::Result, Error = RpcError>
+    where
+        R: Request,
+        R::Params: Serialize,
+        R::Result: DeserializeOwned,
+    {
+        let id = self.next_request_id();
+        let (tx, rx) = oneshot::channel();
+        let mut responses = self
+            .inner
+            .responses
+            .lock()
+            .unwrap_or_else(|r| r.into_inner());
+        responses.insert(id.clone(), tx);
+        drop(responses);
+
+        let message = make_request::<R>(id.clone(), params);
+        let sent = if self.is_buffering() {
+            self.send_message(message)
+        } else {
+            self.send_or_hold_request(message)
+        };
+
+        if !sent {
+            let mut responses = self
+                .inner
+                .responses
+                .lock()
+                .unwrap_or_else(|r| r.into_inner());
+            responses.remove(&id);
+        }
+
+        rx.map_err(|_| RpcError::internal_error())
+            .and_then(|output| match output {
+                Output::Success(success) => serde_json::from_value(success.result).map_err(|e| {
+                    error!("invalid result in `{}` response: {}", R::METHOD, e);
+                    RpcError::internal_error()
+                }),
+                Output::Failure(failure) => Err(failure.error),
+            })
+    }
+
+    /// Enqueues the message, unless the outbound queue is full. Returns whether it was enqueued.
+    fn send_message(&self, message: String) -> bool {
+        let max = self.inner.max_queued.load(Ordering::SeqCst);
+        match QueueSlot::acquire(&self.inner.queued, max) {
+            Some(slot) => {
+                self.send(Outgoing::Message(message, slot));
+                true
+            }
+            None => {
+                warn!("outbound queue is full, dropping message: {}", message);
+                false
+            }
+        }
+    }
+
+    /// Sends a request right away if the server has been initialized, or holds it back until
+    /// the client has finished initializing, unless the outbound queue is full. Returns whether it
+    /// was sent or held back.
+    ///
+    /// Requests are also held back while earlier ones still are, so that none overtakes another.
+    fn send_or_hold_request(&self, message: String) -> bool {
+        let mut held = self
+            .inner
+            .held_requests
+            .lock()
+            .unwrap_or_else(|h| h.into_inner());
+        if self.inner.initialized.load(Ordering::SeqCst) && held.is_empty() {
+            return self.send_message(message);
+        }
+
+        let max = self.inner.max_queued.load(Ordering::SeqCst);
+        match QueueSlot::acquire(&self.inner.queued, max) {
+            Some(slot) => {
+                trace!("server not initialized, holding back request: {}", message);
+                held.push(Outgoing::Message(message, slot));
+                true
+            }
+            None => {
+                warn!("outbound queue is full, dropping request: {}", message);
+                false
+            }
+        }
+    }
+
+    fn send(&self, item: Outgoing) {
+        let mut pending = self.inner.pending.lock().unwrap_or_else(|p| p.into_inner());
+        match *pending {
+            Some(ref mut pending) => pending.push(item),
+            None => self.enqueue(item),
+        }
+    }
+
+    /// Sends the message, unless the server has not been initialized yet. Returns whether it was
+    /// sent.
+    fn send_message_initialized(&self, message: String) -> bool {
+        if self.inner.initialized.load(Ordering::SeqCst) || self.is_buffering() {
             self.send_message(message)
         } else {
             trace!("server not initialized, supressing message: {}", message);
+            false
+        }
+    }
+
+    fn is_buffering(&self) -> bool {
+        let pending = self.inner.pending.lock().unwrap_or_else(|p| p.into_inner());
+        pending.is_some()
+    }
+
+    fn enqueue(&self, item: Outgoing) {
+        if self.inner.buffer.unbounded_send(item).is_err() {
+            error!("failed to send message");
         }
     }
 }
 
+/// An item in the outbound queue of the `Printer`.
+#[derive(Debug)]
+pub(super) enum Outgoing {
+    /// A serialized message to be sent to the client, occupying a slot of the queue.
+    Message(String, QueueSlot),
+    /// A marker which is signaled once all preceding messages have been taken from the queue.
+    Flush(oneshot::Sender<()>),
+}
+
+/// A slot of the outbound queue, which is released once dropped.
+#[derive(Debug)]
+pub(super) struct QueueSlot(Arc<AtomicUsize>);
+
+impl QueueSlot {
+    /// Occupies a slot of the queue, unless `max` slots are occupied already.
+    fn acquire(queued: &Arc<AtomicUsize>, max: usize) -> Option<Self> {
+        queued
+            .fetch_update(Ordering::SeqCst, Ordering::SeqCst, |n| {
+                if n < max {
+                    Some(n + 1)
+                } else {
+                    None
+                }
+            })
+            .ok()
+            .map(|_| QueueSlot(queued.clone()))
+    }
+}
+
+impl Drop for QueueSlot {
+    fn drop(&mut self) {
+        self.0.fetch_sub(1, Ordering::SeqCst);
+    }
+}
+
 /// Constructs a JSON-RPC request from its corresponding LSP type.
-fn make_request<N>(id: u64, params: N::Params) -> String
+fn make_request<N>(id: Id, params: N::Params) -> String
 where
     N: Request,
     N::Params: Serialize,
@@ -160,7 +754,7 @@ where
     let params = serde_json::from_str(&output).unwrap();
     serde_json::to_string(&request::MethodCall {
         jsonrpc: Some(Version::V2),
-        id: Id::Num(id),
+        id,
         method: N::METHOD.to_owned(),
         params,
     })
@@ -185,22 +779,38 @@ where
     .unwrap()
 }
 
+/// Constructs a JSON-RPC notification with arbitrary parameters.
+///
+/// Unlike `make_notification()`, this permits the parameters to be omitted entirely or to be
+/// built from types other than `N::Params`.
+fn make_raw_notification<N: Notification>(params: Params) -> String {
+    serde_json::to_string(&request::Notification {
+        jsonrpc: Some(Version::V2),
+        method: N::METHOD.to_owned(),
+        params,
+    })
+    .unwrap()
+}
+
 #[cfg(test)]
 mod tests {
-    use futures::{future, sync::mpsc, Stream};
+    use std::thread;
+
+    use futures::{future, sync::mpsc, Async, Stream};
     use serde_json::json;
     use tokio::runtime::current_thread;
 
     use super::*;
+    use crate::delegate::MessageStream;
 
     fn assert_printer_messages<F: FnOnce(Printer)>(f: F, expected: String) {
-        let (tx, rx) = mpsc::channel(1);
+        let (tx, rx) = mpsc::unbounded();
         let printer = Printer::new(tx, Arc::new(AtomicBool::new(true)));
 
         current_thread::block_on_all(
             future::lazy(move || {
                 f(printer);
-                rx.collect()
+                MessageStream(rx).collect()
             })
             .and_then(move |messages| {
                 assert_eq!(messages, vec![expected]);
@@ -210,9 +820,139 @@ mod tests {
         .unwrap();
     }
 
+    #[test]
+    fn clones_share_outbound_queue() {
+        fn assert_clone_send_sync<T: Clone + Send + Sync>() {}
+        assert_clone_send_sync::<Printer>();
+
+        let (tx, rx) = mpsc::unbounded();
+        let printer = Printer::new(tx, Arc::new(AtomicBool::new(true)));
+        let message = |task, n| format!("{}-{}", task, n);
+
+        let tasks: Vec<_> = (0..4)
+            .map(|task| {
+                let printer = printer.clone();
+                thread::spawn(move || {
+                    for n in 0..25 {
+                        printer.log_message(MessageType::LOG, message(task, n));
+                    }
+                })
+            })
+            .collect();
+
+        for task in tasks {
+            task.join().unwrap();
+        }
+
+        drop(printer);
+        let messages: Vec<String> = MessageStream(rx).collect().wait().unwrap();
+        assert_eq!(messages.len(), 100);
+
+        // Messages sent through the same clone must arrive in the order they were sent.
+        for task in 0..4 {
+            let expected: Vec<_> = (0..25)
+                .map(|n| {
+                    make_notification::<LogMessage>(LogMessageParams {
+                        typ: MessageType::LOG,
+                        message: message(task, n),
+                    })
+                })
+                .collect();
+            let received: Vec<_> = messages
+                .iter()
+                .filter(|m| expected.contains(m))
+                .cloned()
+                .collect();
+            assert_eq!(received, expected);
+        }
+    }
+
+    #[test]
+    fn client_capabilities() {
+        let (tx, _rx) = mpsc::unbounded();
+        let printer = Printer::new(tx, Arc::new(AtomicBool::new(false)));
+        assert_eq!(printer.client_capabilities(), None);
+
+        let capabilities = ClientCapabilities {
+            experimental: Some(json!({"foo": "bar"})),
+            ..ClientCapabilities::default()
+        };
+        printer.set_client_capabilities(capabilities.clone());
+        assert_eq!(printer.client_capabilities(), Some(capabilities));
+    }
+
+    #[test]
+    fn hover_markup() {
+        let (tx, _rx) = mpsc::unbounded();
+        let printer = Printer::new(tx, Arc::new(AtomicBool::new(false)));
+        let markup = printer.hover_markup("*foo*", "foo");
+        assert_eq!(markup.kind, MarkupKind::PlainText);
+        assert_eq!(markup.value, "foo");
+
+        let hover = HoverClientCapabilities {
+            dynamic_registration: None,
+            content_format: Some(vec![MarkupKind::Markdown, MarkupKind::PlainText]),
+        };
+        printer.set_client_capabilities(ClientCapabilities {
+            text_document: Some(TextDocumentClientCapabilities {
+                hover: Some(hover),
+                ..TextDocumentClientCapabilities::default()
+            }),
+            ..ClientCapabilities::default()
+        });
+        let markup = printer.hover_markup("*foo*", "foo");
+        assert_eq!(markup.kind, MarkupKind::Markdown);
+        assert_eq!(markup.value, "*foo*");
+    }
+
+    #[test]
+    fn workspace_edit_for_client() {
+        let (tx, _rx) = mpsc::unbounded();
+        let printer = Printer::new(tx, Arc::new(AtomicBool::new(false)));
+        let uri: Url = "file:///foo.rs".parse().unwrap();
+        let edit = printer
+            .workspace_edit()
+            .edit(uri.clone(), Some(1), Vec::new());
+        assert!(edit.finish().unwrap().changes.is_some());
+
+        let workspace_edit = WorkspaceEditClientCapabilities {
+            document_changes: Some(true),
+            ..WorkspaceEditClientCapabilities::default()
+        };
+        printer.set_client_capabilities(ClientCapabilities {
+            workspace: Some(WorkspaceClientCapabilities {
+                workspace_edit: Some(workspace_edit),
+                ..WorkspaceClientCapabilities::default()
+            }),
+            ..ClientCapabilities::default()
+        });
+        let edit = printer.workspace_edit().edit(uri, Some(1), Vec::new());
+        assert!(edit.finish().unwrap().document_changes.is_some());
+    }
+
+    #[test]
+    fn tracks_document_versions() {
+        let (tx, _rx) = mpsc::unbounded();
+        let printer = Printer::new(tx, Arc::new(AtomicBool::new(true)));
+        let uri: Url = "file:///foo.rs".parse().unwrap();
+        assert_eq!(printer.document_version(&uri), None);
+        assert!(printer.ensure_document_version(&uri, 1).is_err());
+
+        printer.set_document_version(uri.clone(), Some(1));
+        assert_eq!(printer.document_version(&uri), Some(1));
+        assert_eq!(printer.ensure_document_version(&uri, 1), Ok(()));
+
+        printer.set_document_version(uri.clone(), Some(2));
+        let error = printer.ensure_document_version(&uri, 1).unwrap_err();
+        assert_eq!(error, content_modified_error());
+
+        printer.set_document_version(uri.clone(), None);
+        assert_eq!(printer.document_version(&uri), None);
+    }
+
     #[test]
     fn log_message() {
-        let (typ, message) = (MessageType::Log, "foo bar".to_owned());
+        let (typ, message) = (MessageType::LOG, "foo bar".to_owned());
         let expected = make_notification::<LogMessage>(LogMessageParams {
             typ,
             message: message.clone(),
@@ -223,7 +963,7 @@ mod tests {
 
     #[test]
     fn show_message() {
-        let (typ, message) = (MessageType::Log, "foo bar".to_owned());
+        let (typ, message) = (MessageType::LOG, "foo bar".to_owned());
         let expected = make_notification::<ShowMessage>(ShowMessageParams {
             typ,
             message: message.clone(),
@@ -235,31 +975,349 @@ mod tests {
     #[test]
     fn telemetry_event() {
         let null = json!(null);
-        let expected = make_notification::<TelemetryEvent>(null.clone());
+        let expected = make_raw_notification::<TelemetryEvent>(Params::None);
         assert_printer_messages(|p| p.telemetry_event(null), expected);
 
-        let array = json!([1, 2, 3]);
-        let expected = make_notification::<TelemetryEvent>(array.clone());
+        let array = vec![json!(1), json!(2), json!(3)];
+        let expected = make_notification::<TelemetryEvent>(OneOf::Right(array.clone()));
         assert_printer_messages(|p| p.telemetry_event(array), expected);
 
-        let object = json!({});
-        let expected = make_notification::<TelemetryEvent>(object.clone());
+        let object = serde_json::Map::new();
+        let expected = make_notification::<TelemetryEvent>(OneOf::Left(object.clone()));
         assert_printer_messages(|p| p.telemetry_event(object), expected);
 
         let anything_else = json!("hello");
-        let wrapped = Value::Array(vec![anything_else.clone()]);
-        let expected = make_notification::<TelemetryEvent>(wrapped);
+        let wrapped = vec![anything_else.clone()];
+        let expected = make_notification::<TelemetryEvent>(OneOf::Right(wrapped));
         assert_printer_messages(|p| p.telemetry_event(anything_else), expected);
     }
 
+    #[test]
+    fn cancel_request() {
+        let expected = r#"{"jsonrpc":"2.0","method":"$/cancelRequest","params":{"id":1}}"#;
+        assert_printer_messages(|p| p.cancel_request(Id::Num(1)), expected.to_owned());
+
+        let expected = r#"{"jsonrpc":"2.0","method":"$/cancelRequest","params":{"id":"abc"}}"#;
+        assert_printer_messages(
+            |p| p.cancel_request(Id::Str("abc".into())),
+            expected.to_owned(),
+        );
+    }
+
+    #[test]
+    fn reports_progress() {
+        let (tx, rx) = mpsc::unbounded();
+        let printer = Printer::new(tx, Arc::new(AtomicBool::new(true)));
+
+        let token = NumberOrString::String("indexing".into());
+        let progress = printer.begin_progress(token.clone(), "Indexing", true);
+        progress.report(Some(50), None);
+        progress.report(None, Some("foo.rs".into()));
+        progress.end(Some("Done".into()));
+        drop(printer.begin_progress(NumberOrString::Number(1), "Loading", false));
+        drop(printer);
+
+        let progress = |token: &NumberOrString, value| {
+            make_notification::<Progress>(ProgressParams {
+                token: token.clone(),
+                value: ProgressParamsValue::WorkDone(value),
+            })
+        };
+        let report = |percentage, message| {
+            WorkDoneProgress::Report(WorkDoneProgressReport {
+                cancellable: None,
+                message,
+                percentage,
+            })
+        };
+        let begin = |title: &str, cancellable| {
+            WorkDoneProgress::Begin(WorkDoneProgressBegin {
+                title: title.into(),
+                cancellable: Some(cancellable),
+                message: None,
+                percentage: None,
+            })
+        };
+        let end = |message| WorkDoneProgress::End(WorkDoneProgressEnd { message });
+
+        let other = NumberOrString::Number(1);
+        let expected = vec![
+            progress(&token, begin("Indexing", true)),
+            progress(&token, report(Some(50), None)),
+            progress(&token, report(None, Some("foo.rs".into()))),
+            progress(&token, end(Some("Done".into()))),
+            progress(&other, begin("Loading", false)),
+            progress(&other, end(None)),
+        ];
+        assert!(expected[1].contains(r#"{"kind":"report","percentage":50}"#));
+        assert!(expected[2].contains(r#"{"kind":"report","message":"foo.rs"}"#));
+        assert_eq!(MessageStream(rx).collect().wait(), Ok(expected));
+    }
+
+    #[test]
+    fn log_trace() {
+        let (message, verbose) = ("foo".to_owned(), Some("bar".to_owned()));
+        let expected = make_notification::<LogTrace>(LogTraceParams {
+            message: message.clone(),
+            verbose: verbose.clone(),
+        });
+        assert_printer_messages(
+            |p| {
+                p.log_trace("suppressed", verbose.clone());
+                p.set_trace_value(TraceValue::Verbose);
+                p.log_trace(&message, verbose.clone());
+            },
+            expected,
+        );
+
+        let expected = make_notification::<LogTrace>(LogTraceParams {
+            message: message.clone(),
+            verbose: None,
+        });
+        assert_printer_messages(
+            |p| {
+                p.set_trace_value(TraceValue::Messages);
+                p.log_trace(message, verbose);
+            },
+            expected,
+        );
+    }
+
     #[test]
     fn publish_diagnostics() {
         let uri: Url = "file:///path/to/file".parse().unwrap();
         let diagnostics = vec![Diagnostic::new_simple(Default::default(), "example".into())];
 
-        let params = PublishDiagnosticsParams::new(uri.clone(), diagnostics.clone());
+        let params = PublishDiagnosticsParams::new(uri.clone(), diagnostics.clone(), None);
         let expected = make_notification::<PublishDiagnostics>(params);
 
-        assert_printer_messages(|p| p.publish_diagnostics(uri, diagnostics), expected);
+        assert_printer_messages(|p| p.publish_diagnostics(uri, diagnostics, None), expected);
+    }
+
+    #[test]
+    fn buffers_pending_messages() {
+        let (tx, rx) = mpsc::unbounded();
+        let printer = Printer::new(tx, Arc::new(AtomicBool::new(false)));
+        printer.buffer_pending();
+
+        let uri: Url = "file:///path/to/file".parse().unwrap();
+        printer.publish_diagnostics(uri.clone(), Vec::new(), None);
+        printer.log_message(MessageType::INFO, "foo");
+        printer.flush_pending();
+        printer.log_message(MessageType::INFO, "bar");
+        drop(printer);
+
+        let expected = vec![
+            make_notification::<PublishDiagnostics>(PublishDiagnosticsParams::new(
+                uri,
+                Vec::new(),
+                None,
+            )),
+            make_notification::<LogMessage>(LogMessageParams {
+                typ: MessageType::INFO,
+                message: "foo".into(),
+            }),
+            make_notification::<LogMessage>(LogMessageParams {
+                typ: MessageType::INFO,
+                message: "bar".into(),
+            }),
+        ];
+        assert_eq!(MessageStream(rx).collect().wait(), Ok(expected));
+    }
+
+    #[test]
+    fn flushes_once_preceding_messages_are_taken() {
+        let (tx, rx) = mpsc::unbounded();
+        let printer = Printer::new(tx, Arc::new(AtomicBool::new(true)));
+        let mut messages = MessageStream(rx);
+
+        future::lazy(move || {
+            printer.log_message(MessageType::INFO, "foo");
+            let mut flushed = printer.flush();
+            assert_eq!(flushed.poll(), Ok(Async::NotReady));
+
+            assert!(messages.poll().unwrap().is_ready());
+            assert_eq!(flushed.poll(), Ok(Async::NotReady));
+            assert_eq!(messages.poll(), Ok(Async::NotReady));
+            assert_eq!(flushed.poll(), Ok(Async::Ready(())));
+
+            drop(messages);
+            assert_eq!(printer.flush().poll(), Err(()));
+            Ok::<_, ()>(())
+        })
+        .wait()
+        .unwrap();
+    }
+
+    #[test]
+    fn send_raw() {
+        let raw = json!({"jsonrpc": "2.0", "method": "custom/notification", "params": [1]});
+        let expected = make_notification::<LogMessage>(LogMessageParams {
+            typ: MessageType::INFO,
+            message: "foo".into(),
+        });
+
+        let (tx, rx) = mpsc::unbounded();
+        let printer = Printer::new(tx, Arc::new(AtomicBool::new(true)));
+        printer.send_raw(raw.clone());
+        printer.log_message(MessageType::INFO, "foo");
+        drop(printer);
+
+        let messages = MessageStream(rx).collect().wait().unwrap();
+        assert_eq!(messages, vec![raw.to_string(), expected]);
+    }
+
+    #[test]
+    fn allocates_unique_request_ids() {
+        let (tx, rx) = mpsc::unbounded();
+        let printer = Printer::new(tx, Arc::new(AtomicBool::new(true)));
+
+        let senders: Vec<_> = (0..8)
+            .map(|_| {
+                let printer = printer.clone();
+                thread::spawn(move || {
+                    for _ in 0..32 {
+                        drop(printer.configuration(Vec::new()));
+                    }
+                })
+            })
+            .collect();
+        for sender in senders {
+            sender.join().unwrap();
+        }
+        drop(printer);
+
+        let messages = MessageStream(rx).collect().wait().unwrap();
+        let mut ids: Vec<u64> = messages
+            .iter()
+            .map(|m| {
+                serde_json::from_str::<Value>(m).unwrap()["id"]
+                    .as_u64()
+                    .unwrap()
+            })
+            .collect();
+        ids.sort();
+        assert_eq!(ids, (0..256).collect::<Vec<_>>());
+    }
+
+    #[test]
+    fn prefixes_request_ids() {
+        let (tx, rx) = mpsc::unbounded();
+        let printer = Printer::new(tx, Arc::new(AtomicBool::new(true)));
+        printer.set_request_id_prefix("server-".into());
+
+        let response = printer.configuration(Vec::new());
+        printer.apply_edit(WorkspaceEdit::default());
+
+        let (first, messages) = MessageStream(rx).into_future().wait().ok().unwrap();
+        let (second, _) = messages.into_future().wait().ok().unwrap();
+        assert!(first.unwrap().contains(r#""id":"server-0""#));
+        assert!(second.unwrap().contains(r#""id":"server-1""#));
+
+        let output = json!({"jsonrpc": "2.0", "result": [null], "id": "server-0"});
+        printer.handle_response(serde_json::from_value(output).unwrap());
+        assert_eq!(response.wait(), Ok(vec![Value::Null]));
+    }
+
+    #[test]
+    fn keeps_held_requests_in_order() {
+        let (tx, rx) = mpsc::unbounded();
+        let initialized = Arc::new(AtomicBool::new(false));
+        let printer = Printer::new(tx, initialized.clone());
+
+        let section = |name: &str| ConfigurationItem {
+            scope_uri: None,
+            section: Some(name.into()),
+        };
+        drop(printer.configuration(vec![section("held")]));
+
+        // Requests sent between the `initialize` response and the `initialized` notification must
+        // not overtake those held back earlier.
+        initialized.store(true, Ordering::SeqCst);
+        drop(printer.configuration(vec![section("queued")]));
+        let mut rx = MessageStream(rx);
+        assert_eq!(future::lazy(|| rx.poll()).wait(), Ok(Async::NotReady));
+
+        printer.flush_pending();
+        drop(printer.configuration(vec![section("sent")]));
+        drop(printer);
+
+        let messages = rx.collect().wait().unwrap();
+        let sections: Vec<Value> = messages
+            .iter()
+            .map(|m| {
+                serde_json::from_str::<Value>(m).unwrap()["params"]["items"][0]["section"].clone()
+            })
+            .collect();
+        assert_eq!(
+            sections,
+            vec![json!("held"), json!("queued"), json!("sent")]
+        );
+    }
+
+    #[test]
+    fn drops_messages_once_queue_is_full() {
+        let (tx, rx) = mpsc::unbounded();
+        let printer = Printer::new(tx, Arc::new(AtomicBool::new(true)));
+        printer.set_max_queued_messages(2);
+
+        for message in &["foo", "bar", "baz"] {
+            printer.log_message(MessageType::INFO, message);
+        }
+        let (first, messages) = MessageStream(rx).into_future().wait().ok().unwrap();
+        assert!(first.unwrap().contains("foo"));
+
+        printer.log_message(MessageType::INFO, "qux");
+        assert!(printer.configuration(Vec::new()).wait().is_err());
+
+        drop(printer);
+        let rest = messages.collect().wait().unwrap();
+        assert_eq!(rest.len(), 2);
+        assert!(rest[0].contains("bar"));
+        assert!(rest[1].contains("qux"));
+    }
+
+    #[test]
+    fn flushes_after_pending_messages() {
+        let (tx, rx) = mpsc::unbounded();
+        let printer = Printer::new(tx, Arc::new(AtomicBool::new(false)));
+        let mut messages = MessageStream(rx);
+
+        future::lazy(move || {
+            printer.buffer_pending();
+            printer.log_message(MessageType::INFO, "foo");
+            let mut flushed = printer.flush();
+            assert_eq!(messages.poll(), Ok(Async::NotReady));
+            assert_eq!(flushed.poll(), Ok(Async::NotReady));
+
+            printer.flush_pending();
+            assert!(messages.poll().unwrap().is_ready());
+            assert_eq!(messages.poll(), Ok(Async::NotReady));
+            assert_eq!(flushed.poll(), Ok(Async::Ready(())));
+            Ok::<_, ()>(())
+        })
+        .wait()
+        .unwrap();
+    }
+
+    #[test]
+    fn publish_diagnostics_in_order() {
+        let (tx, rx) = mpsc::unbounded();
+        let printer = Printer::new(tx, Arc::new(AtomicBool::new(true)));
+
+        let uris: Vec<Url> = (0..10)
+            .map(|i| format!("file:///path/to/file{}", i).parse().unwrap())
+            .collect();
+        for uri in &uris {
+            printer.publish_diagnostics(uri.clone(), Vec::new(), None);
+        }
+        drop(printer);
+
+        let expected: Vec<_> = uris
+            .into_iter()
+            .map(|uri| PublishDiagnosticsParams::new(uri, Vec::new(), None))
+            .map(make_notification::<PublishDiagnostics>)
+            .collect();
+        assert_eq!(MessageStream(rx).collect().wait(), Ok(expected));
     }
 }
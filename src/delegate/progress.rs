@@ -0,0 +1,202 @@
+//! Reporting and cancellation of work done progress.
+
+use std::collections::HashMap;
+use std::sync::atomic::{AtomicBool, Ordering};
+use std::sync::{Arc, Mutex, Weak};
+
+use futures::task::AtomicTask;
+use futures::{Async, Future, Poll};
+use lsp_types::{ProgressToken, WorkDoneProgress, WorkDoneProgressEnd, WorkDoneProgressReport};
+
+use super::Printer;
+
+/// Reports the progress of a long running operation to the client.
+///
+/// A `ProgressReporter` is obtained from [`Printer::begin_progress`], which notifies the client
+/// that the operation has begun. The client is notified that the operation has ended by calling
+/// [`end`](#method.end), or otherwise once the reporter is dropped.
+///
+/// These notifications correspond to the [`$/progress`] notification with work done progress
+/// values.
+///
+/// [`Printer::begin_progress`]: ./struct.Printer.html#method.begin_progress
+/// [`$/progress`]: https://microsoft.github.io/language-server-protocol/specification#progress
+#[derive(Debug)]
+pub struct ProgressReporter {
+    printer: Printer,
+    token: ProgressToken,
+    cancellation: CancellationToken,
+    ended: bool,
+}
+
+impl ProgressReporter {
+    pub(super) fn new(
+        printer: Printer,
+        token: ProgressToken,
+        cancellation: CancellationToken,
+    ) -> Self {
+        ProgressReporter {
+            printer,
+            token,
+            cancellation,
+            ended: false,
+        }
+    }
+
+    /// Returns the token identifying this progress.
+    pub fn token(&self) -> &ProgressToken {
+        &self.token
+    }
+
+    /// Returns a token which is cancelled once the client cancels this progress.
+    pub fn cancellation(&self) -> CancellationToken {
+        self.cancellation.clone()
+    }
+
+    /// Notifies the client of the progress made so far.
+    ///
+    /// Either field may be omitted, e.g. to update only the `percentage` of a progress bar while
+    /// keeping the previous `message` displayed, or vice versa. The `percentage` should range from
+    /// 0 to 100 and should never decrease.
+    pub fn report(&self, percentage: Option<u32>, message: Option<String>) {
+        let report = WorkDoneProgressReport {
+            cancellable: None,
+            message,
+            percentage,
+        };
+        self.printer
+            .send_progress(self.token.clone(), WorkDoneProgress::Report(report));
+    }
+
+    /// Notifies the client that the operation has ended, with an optional final message.
+    pub fn end(mut self, message: Option<String>) {
+        self.send_end(message);
+    }
+
+    fn send_end(&mut self, message: Option<String>) {
+        if !self.ended {
+            self.ended = true;
+            let end = WorkDoneProgressEnd { message };
+            self.printer
+                .send_progress(self.token.clone(), WorkDoneProgress::End(end));
+        }
+    }
+}
+
+impl Drop for ProgressReporter {
+    fn drop(&mut self) {
+        self.send_end(None);
+    }
+}
+
+/// Signals that the client has cancelled a work done progress.
+///
+/// Tokens are obtained from [`Printer::progress_cancellation`] and are cancelled once the client
+/// sends a [`window/workDoneProgress/cancel`] notification for the corresponding progress token,
+/// e.g. because the user clicked the cancel button of a progress report. Long running operations
+/// may poll [`is_cancelled`](#method.is_cancelled) periodically, or wait on the token as a future
+/// which resolves upon cancellation.
+///
+/// Cloning a `CancellationToken` is cheap, since all clones share the same state.
+///
+/// [`Printer::progress_cancellation`]: ./struct.Printer.html#method.progress_cancellation
+/// [`window/workDoneProgress/cancel`]: https://microsoft.github.io/language-server-protocol/specification#window_workDoneProgress_cancel
+#[derive(Clone, Debug)]
+pub struct CancellationToken {
+    inner: Arc<Inner>,
+}
+
+#[derive(Debug, Default)]
+struct Inner {
+    cancelled: AtomicBool,
+    task: AtomicTask,
+}
+
+impl CancellationToken {
+    /// Returns whether the client has cancelled the progress.
+    pub fn is_cancelled(&self) -> bool {
+        self.inner.cancelled.load(Ordering::SeqCst)
+    }
+}
+
+impl Future for CancellationToken {
+    type Item = ();
+    type Error = ();
+
+    fn poll(&mut self) -> Poll<Self::Item, Self::Error> {
+        self.inner.task.register();
+        if self.is_cancelled() {
+            Ok(Async::Ready(()))
+        } else {
+            Ok(Async::NotReady)
+        }
+    }
+}
+
+/// Cancellation tokens handed out for pending work done progress, keyed by progress token.
+///
+/// Only weak references are kept, so that tokens which have been dropped without ever being
+/// cancelled do not accumulate.
+#[derive(Debug, Default)]
+pub(super) struct Cancellations(Mutex<HashMap<ProgressToken, Vec<Weak<Inner>>>>);
+
+impl Cancellations {
+    /// Returns a new cancellation token for the given progress token.
+    pub fn register(&self, token: ProgressToken) -> CancellationToken {
+        let inner = Arc::new(Inner::default());
+        let mut tokens = self.0.lock().unwrap_or_else(|t| t.into_inner());
+        tokens.retain(|_, list| {
+            list.retain(|t| t.strong_count() > 0);
+            !list.is_empty()
+        });
+        tokens
+            .entry(token)
+            .or_default()
+            .push(Arc::downgrade(&inner));
+        CancellationToken { inner }
+    }
+
+    /// Cancels all tokens handed out for the given progress token.
+    pub fn cancel(&self, token: &ProgressToken) {
+        let mut tokens = self.0.lock().unwrap_or_else(|t| t.into_inner());
+        for inner in tokens.remove(token).into_iter().flatten() {
+            if let Some(inner) = inner.upgrade() {
+                inner.cancelled.store(true, Ordering::SeqCst);
+                inner.task.notify();
+            }
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use lsp_types::NumberOrString;
+
+    use super::*;
+
+    #[test]
+    fn cancels_matching_tokens() {
+        let cancellations = Cancellations::default();
+        let token = NumberOrString::String("indexing".into());
+        let first = cancellations.register(token.clone());
+        let second = first.clone();
+        let other = cancellations.register(NumberOrString::Number(1));
+
+        assert!(!first.is_cancelled());
+        cancellations.cancel(&token);
+        assert!(first.is_cancelled());
+        assert_eq!(second.wait(), Ok(()));
+        assert!(!other.is_cancelled());
+    }
+
+    #[test]
+    fn forgets_dropped_tokens() {
+        let cancellations = Cancellations::default();
+        drop(cancellations.register(NumberOrString::Number(1)));
+        let _token = cancellations.register(NumberOrString::Number(2));
+
+        let tokens = cancellations.0.lock().unwrap();
+        assert_eq!(tokens.len(), 1);
+        assert!(tokens.contains_key(&NumberOrString::Number(2)));
+    }
+}
@@ -0,0 +1,452 @@
+//! Tracking of open text documents with debounced analysis.
+
+use std::collections::HashMap;
+use std::fmt::{self, Debug, Formatter};
+use std::sync::atomic::{AtomicUsize, Ordering};
+use std::sync::{Arc, Condvar, Mutex, MutexGuard};
+use std::thread;
+use std::time::{Duration, Instant};
+
+use log::{error, trace, warn};
+use lsp_types::{
+    DidChangeTextDocumentParams, DidCloseTextDocumentParams, DidOpenTextDocumentParams,
+    PositionEncodingKind, TextDocumentContentChangeEvent, Url,
+};
+
+use super::position::position_to_offset;
+
+type AnalyzeFn = dyn Fn(&Url, &Document) + Send + Sync;
+
+/// Snapshot of the contents of an open text document.
+#[derive(Clone, Debug, Eq, PartialEq)]
+pub struct Document {
+    text: String,
+    version: i32,
+}
+
+impl Document {
+    /// Returns the full text of the document.
+    pub fn text(&self) -> &str {
+        &self.text
+    }
+
+    /// Returns the version of the document, as sent by the client.
+    pub fn version(&self) -> i32 {
+        self.version
+    }
+
+    /// Applies a content change sent by the client to the text.
+    ///
+    /// Positions which lie beyond the end of the text are clamped to it.
+    fn apply(&mut self, change: TextDocumentContentChangeEvent, encoding: &PositionEncodingKind) {
+        let range = match change.range {
+            Some(range) => range,
+            None => {
+                self.text = change.text;
+                return;
+            }
+        };
+
+        let offset = |position| {
+            position_to_offset(&self.text, position, encoding).unwrap_or(self.text.len())
+        };
+        let start = offset(range.start);
+        let end = offset(range.end).max(start);
+        self.text.replace_range(start..end, &change.text);
+    }
+}
+
+/// Keeps track of the contents of open text documents, coalescing bursts of changes into a single
+/// analysis per document.
+///
+/// While typing, clients may send many `textDocument/didChange` notifications in rapid
+/// succession. Each change is applied to the tracked document right away, but the expensive
+/// analysis, e.g. recomputing diagnostics, only runs on a background thread once a document has
+/// been quiet for the configured period. The analysis is passed a snapshot of the document at that
+/// time, and documents are analyzed one at a time.
+///
+/// The `did_open`, `did_change` and `did_close` methods are meant to be called from the
+/// corresponding [`LanguageServer`] methods. Cloning a `ChangeDebouncer` is cheap, since all clones
+/// share the same documents.
+///
+/// [`LanguageServer`]: ./trait.LanguageServer.html
+#[derive(Clone, Debug)]
+pub struct ChangeDebouncer {
+    inner: Arc<Inner>,
+}
+
+struct Inner {
+    quiet_period: Duration,
+    encoding: PositionEncodingKind,
+    analyze: Box<AnalyzeFn>,
+    max_documents: AtomicUsize,
+    state: Mutex<State>,
+    wakeup: Condvar,
+}
+
+#[derive(Debug, Default)]
+struct State {
+    documents: HashMap<Url, Document>,
+    pending: HashMap<Url, Instant>,
+    last_used: HashMap<Url, u64>,
+    clock: u64,
+    worker: bool,
+}
+
+impl State {
+    /// Marks the document with the given URI as the most recently used one.
+    fn touch(&mut self, uri: Url) {
+        self.clock += 1;
+        self.last_used.insert(uri, self.clock);
+    }
+
+    /// Stops tracking the least recently opened or changed documents until at most `max` remain.
+    fn evict(&mut self, max: usize) {
+        while self.documents.len() > max {
+            let oldest = match self.last_used.iter().min_by_key(|&(_, used)| *used) {
+                Some((uri, _)) => uri.clone(),
+                None => break,
+            };
+
+            warn!("tracking more than {} documents, evicting {}", max, oldest);
+            self.documents.remove(&oldest);
+            self.pending.remove(&oldest);
+            self.last_used.remove(&oldest);
+        }
+    }
+}
+
+impl Debug for Inner {
+    fn fmt(&self, f: &mut Formatter) -> fmt::Result {
+        f.debug_struct("Inner")
+            .field("quiet_period", &self.quiet_period)
+            .field("encoding", &self.encoding)
+            .field("max_documents", &self.max_documents)
+            .field("state", &self.state)
+            .finish()
+    }
+}
+
+impl ChangeDebouncer {
+    /// Creates a new debouncer which calls `analyze` once a document has not been changed for the
+    /// given quiet period.
+    ///
+    /// Positions of incremental changes are assumed to be in UTF-16, the default encoding of the
+    /// protocol.
+    pub fn new<F>(quiet_period: Duration, analyze: F) -> Self
+    where
+        F: Fn(&Url, &Document) + Send + Sync + 'static,
+    {
+        ChangeDebouncer::with_encoding(quiet_period, PositionEncodingKind::UTF16, analyze)
+    }
+
+    /// Creates a new debouncer like [`new`](#method.new), interpreting the positions of
+    /// incremental changes in the given encoding.
+    ///
+    /// This should be the position encoding negotiated with the client during initialization.
+    pub fn with_encoding<F>(
+        quiet_period: Duration,
+        encoding: PositionEncodingKind,
+        analyze: F,
+    ) -> Self
+    where
+        F: Fn(&Url, &Document) + Send + Sync + 'static,
+    {
+        ChangeDebouncer {
+            inner: Arc::new(Inner {
+                quiet_period,
+                encoding,
+                analyze: Box::new(analyze),
+                max_documents: AtomicUsize::new(usize::MAX),
+                state: Mutex::new(State::default()),
+                wakeup: Condvar::new(),
+            }),
+        }
+    }
+
+    /// Limits the number of documents tracked at once.
+    ///
+    /// A buggy client which opens documents without ever closing them would otherwise make the
+    /// tracked documents grow without bounds. Once the limit is exceeded, the documents which were
+    /// least recently opened or changed are evicted with a warning, as if they had been closed:
+    /// their pending analysis is cancelled and further changes to them are ignored until they are
+    /// opened again. Defaults to no limit.
+    pub fn set_max_documents(&self, max: usize) {
+        self.inner.max_documents.store(max, Ordering::SeqCst);
+        self.inner.lock().evict(max);
+    }
+
+    /// Starts tracking a newly opened document and schedules its analysis.
+    ///
+    /// This may evict other documents, see [`set_max_documents`](#method.set_max_documents).
+    pub fn did_open(&self, params: DidOpenTextDocumentParams) {
+        let document = Document {
+            text: params.text_document.text,
+            version: params.text_document.version,
+        };
+
+        let uri = params.text_document.uri;
+        let mut state = self.inner.lock();
+        state.documents.insert(uri.clone(), document);
+        state.touch(uri.clone());
+        state.evict(self.inner.max_documents.load(Ordering::SeqCst));
+        self.schedule(state, uri);
+    }
+
+    /// Applies changes to a tracked document and postpones its analysis until the document has
+    /// been quiet for the configured period.
+    ///
+    /// Changes to documents which are not being tracked are ignored.
+    pub fn did_change(&self, params: DidChangeTextDocumentParams) {
+        let uri = params.text_document.uri;
+        let mut state = self.inner.lock();
+        let document = match state.documents.get_mut(&uri) {
+            Some(document) => document,
+            None => {
+                trace!("ignoring changes to untracked document {}", uri);
+                return;
+            }
+        };
+
+        for change in params.content_changes {
+            document.apply(change, &self.inner.encoding);
+        }
+        document.version = params.text_document.version;
+        state.touch(uri.clone());
+        self.schedule(state, uri);
+    }
+
+    /// Stops tracking a closed document, cancelling its pending analysis.
+    pub fn did_close(&self, params: DidCloseTextDocumentParams) {
+        let uri = params.text_document.uri;
+        let mut state = self.inner.lock();
+        state.documents.remove(&uri);
+        state.pending.remove(&uri);
+        state.last_used.remove(&uri);
+    }
+
+    /// Returns a snapshot of the tracked document with the given URI, including all changes
+    /// received so far.
+    pub fn document(&self, uri: &Url) -> Option<Document> {
+        self.inner.lock().documents.get(uri).cloned()
+    }
+
+    fn schedule(&self, mut state: MutexGuard<'_, State>, uri: Url) {
+        state
+            .pending
+            .insert(uri, Instant::now() + self.inner.quiet_period);
+        if state.worker {
+            self.inner.wakeup.notify_one();
+        } else {
+            state.worker = true;
+            drop(state);
+            self.spawn_worker();
+        }
+    }
+
+    fn spawn_worker(&self) {
+        let inner = self.inner.clone();
+        let result = thread::Builder::new()
+            .name("document-analysis".into())
+            .spawn(move || inner.run());
+
+        if let Err(e) = result {
+            error!("failed to start analyzing changed documents: {}", e);
+            let mut state = self.inner.lock();
+            state.worker = false;
+            let due: Vec<_> = state.pending.drain().map(|(uri, _)| uri).collect();
+            for uri in due {
+                if let Some(document) = state.documents.get(&uri).cloned() {
+                    drop(state);
+                    (self.inner.analyze)(&uri, &document);
+                    state = self.inner.lock();
+                }
+            }
+        }
+    }
+}
+
+impl Inner {
+    fn lock(&self) -> MutexGuard<'_, State> {
+        self.state.lock().unwrap_or_else(|s| s.into_inner())
+    }
+
+    /// Analyzes documents as their quiet periods elapse, until none are left pending.
+    fn run(&self) {
+        let mut state = self.lock();
+        loop {
+            let now = Instant::now();
+            let due = state
+                .pending
+                .iter()
+                .find(|&(_, deadline)| *deadline <= now)
+                .map(|(uri, _)| uri.clone());
+
+            if let Some(uri) = due {
+                state.pending.remove(&uri);
+                if let Some(document) = state.documents.get(&uri).cloned() {
+                    drop(state);
+                    (self.analyze)(&uri, &document);
+                    state = self.lock();
+                }
+                continue;
+            }
+
+            match state.pending.values().min().cloned() {
+                Some(deadline) => {
+                    let timeout = deadline.saturating_duration_since(now);
+                    state = match self.wakeup.wait_timeout(state, timeout) {
+                        Ok((state, _)) => state,
+                        Err(e) => e.into_inner().0,
+                    };
+                }
+                None => {
+                    state.worker = false;
+                    break;
+                }
+            }
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use std::sync::mpsc;
+
+    use lsp_types::{
+        Position, Range, TextDocumentIdentifier, TextDocumentItem, VersionedTextDocumentIdentifier,
+    };
+
+    use super::*;
+
+    fn change(
+        uri: &Url,
+        version: i32,
+        range: Option<Range>,
+        text: &str,
+    ) -> DidChangeTextDocumentParams {
+        DidChangeTextDocumentParams {
+            text_document: VersionedTextDocumentIdentifier::new(uri.clone(), version),
+            content_changes: vec![TextDocumentContentChangeEvent {
+                range,
+                range_length: None,
+                text: text.into(),
+            }],
+        }
+    }
+
+    fn open(uri: &Url, text: &str) -> DidOpenTextDocumentParams {
+        DidOpenTextDocumentParams {
+            text_document: TextDocumentItem::new(uri.clone(), "rust".into(), 0, text.into()),
+        }
+    }
+
+    #[test]
+    fn applies_incremental_changes() {
+        let mut document = Document {
+            text: "fn 😀() {}\nfoo".into(),
+            version: 0,
+        };
+        let utf16 = PositionEncodingKind::UTF16;
+
+        let range = Range::new(Position::new(0, 3), Position::new(0, 5));
+        let change = TextDocumentContentChangeEvent {
+            range: Some(range),
+            range_length: None,
+            text: "main".into(),
+        };
+        document.apply(change, &utf16);
+        assert_eq!(document.text(), "fn main() {}\nfoo");
+
+        let range = Range::new(Position::new(1, 3), Position::new(5, 0));
+        let change = TextDocumentContentChangeEvent {
+            range: Some(range),
+            range_length: None,
+            text: "bar".into(),
+        };
+        document.apply(change, &utf16);
+        assert_eq!(document.text(), "fn main() {}\nfoobar");
+
+        let change = TextDocumentContentChangeEvent {
+            range: None,
+            range_length: None,
+            text: "".into(),
+        };
+        document.apply(change, &utf16);
+        assert_eq!(document.text(), "");
+    }
+
+    #[test]
+    fn coalesces_changes_until_quiet() {
+        let (tx, rx) = mpsc::channel();
+        let tx = Mutex::new(tx);
+        let debouncer = ChangeDebouncer::new(Duration::from_millis(50), move |uri, document| {
+            let message = (uri.clone(), document.clone());
+            tx.lock().unwrap().send(message).unwrap();
+        });
+
+        let uri: Url = "file:///foo.rs".parse().unwrap();
+        debouncer.did_open(open(&uri, ""));
+        for (version, c) in "abc".chars().enumerate() {
+            let position = Position::new(0, version as u32);
+            let range = Range::new(position, position);
+            let params = change(&uri, version as i32 + 1, Some(range), &c.to_string());
+            debouncer.did_change(params);
+        }
+
+        let expected = Document {
+            text: "abc".into(),
+            version: 3,
+        };
+        assert_eq!(debouncer.document(&uri), Some(expected.clone()));
+        let timeout = Duration::from_secs(5);
+        assert_eq!(rx.recv_timeout(timeout), Ok((uri, expected)));
+        assert!(rx.recv_timeout(Duration::from_millis(100)).is_err());
+    }
+
+    #[test]
+    fn cancels_analysis_on_close() {
+        let (tx, rx) = mpsc::channel();
+        let tx = Mutex::new(tx);
+        let debouncer =
+            ChangeDebouncer::new(Duration::from_millis(50), move |uri, _: &Document| {
+                tx.lock().unwrap().send(uri.clone()).unwrap();
+            });
+
+        let foo: Url = "file:///foo.rs".parse().unwrap();
+        let bar: Url = "file:///bar.rs".parse().unwrap();
+        debouncer.did_open(open(&foo, "foo"));
+        debouncer.did_open(open(&bar, "bar"));
+        debouncer.did_close(DidCloseTextDocumentParams {
+            text_document: TextDocumentIdentifier::new(foo.clone()),
+        });
+        debouncer.did_change(change(&foo, 1, None, "ignored"));
+
+        assert_eq!(debouncer.document(&foo), None);
+        assert_eq!(rx.recv_timeout(Duration::from_secs(5)), Ok(bar));
+        assert!(rx.recv_timeout(Duration::from_millis(100)).is_err());
+    }
+
+    #[test]
+    fn evicts_least_recently_used_documents() {
+        let debouncer = ChangeDebouncer::new(Duration::from_secs(60), |_, _: &Document| {});
+        debouncer.set_max_documents(2);
+
+        let uris: Vec<Url> = (0..3)
+            .map(|i| format!("file:///{}.rs", i).parse().unwrap())
+            .collect();
+        debouncer.did_open(open(&uris[0], "zero"));
+        debouncer.did_open(open(&uris[1], "one"));
+        debouncer.did_change(change(&uris[0], 1, None, "changed"));
+        debouncer.did_open(open(&uris[2], "two"));
+
+        assert_eq!(debouncer.document(&uris[1]), None);
+        assert_eq!(debouncer.document(&uris[0]).unwrap().text(), "changed");
+        assert_eq!(debouncer.document(&uris[2]).unwrap().text(), "two");
+        assert!(!debouncer.inner.lock().pending.contains_key(&uris[1]));
+
+        debouncer.set_max_documents(1);
+        assert_eq!(debouncer.document(&uris[0]), None);
+        assert!(debouncer.document(&uris[2]).is_some());
+    }
+}
@@ -0,0 +1,117 @@
+//! Helpers for constructing LSP-specific error responses.
+
+use jsonrpc_core::types::ErrorCode;
+use jsonrpc_core::Error;
+use log::error;
+use serde::de::DeserializeOwned;
+use serde::Serialize;
+
+/// Error code indicating that the content of a document changed while a request was processed.
+pub const CONTENT_MODIFIED: i64 = -32801;
+
+/// Error code indicating that a request was syntactically correct but failed nonetheless.
+pub const REQUEST_FAILED: i64 = -32803;
+
+/// Returns a [`ContentModified`] error for responding to requests whose result became stale.
+///
+/// Servers should return this error when they detect that a document changed while they were
+/// computing a result which depends on its content, e.g. a completion list. This tells the client
+/// to discard the request and to issue a new one, if still relevant.
+///
+/// [`ContentModified`]: https://microsoft.github.io/language-server-protocol/specification#errorCodes
+pub fn content_modified_error() -> Error {
+    Error {
+        code: ErrorCode::ServerError(CONTENT_MODIFIED),
+        message: "Content modified".to_owned(),
+        data: None,
+    }
+}
+
+/// Returns a [`RequestFailed`] error with the given message.
+///
+/// Unlike internal errors, this indicates that the server understood the request but could not
+/// process it, e.g. because it exceeded a configured limit.
+///
+/// [`RequestFailed`]: https://microsoft.github.io/language-server-protocol/specification#errorCodes
+pub fn request_failed_error<M: Into<String>>(message: M) -> Error {
+    Error {
+        code: ErrorCode::ServerError(REQUEST_FAILED),
+        message: message.into(),
+        data: None,
+    }
+}
+
+/// Attaches structured `data` to the given error, e.g. details on which file or range caused it.
+///
+/// The data is serialized into the `data` field of the error object sent to the client, replacing
+/// any previous data. Should it fail to serialize, the error is returned without any data.
+///
+/// # Example
+///
+/// ```rust
+/// # use serde_json::json;
+/// # use tower_lsp::{request_failed_error, with_error_data};
+/// let error = with_error_data(request_failed_error("parse error"), json!({ "line": 3 }));
+/// assert_eq!(error.data, Some(json!({ "line": 3 })));
+/// ```
+pub fn with_error_data<D: Serialize>(mut error: Error, data: D) -> Error {
+    error.data = match serde_json::to_value(data) {
+        Ok(data) => Some(data),
+        Err(e) => {
+            error!("failed to serialize error data: {}", e);
+            None
+        }
+    };
+    error
+}
+
+/// Deserializes the structured `data` of the given error into a typed payload.
+///
+/// This is the inverse of [`with_error_data`], e.g. for inspecting errors returned by the client.
+/// Returns `None` if the error carries no data or if it does not match `D`.
+///
+/// [`with_error_data`]: ./fn.with_error_data.html
+pub fn error_data<D: DeserializeOwned>(error: &Error) -> Option<D> {
+    let data = error.data.clone()?;
+    serde_json::from_value(data).ok()
+}
+
+#[cfg(test)]
+mod tests {
+    use lsp_types::{Position, Range};
+    use serde_json::json;
+
+    use super::*;
+
+    #[test]
+    fn serializes_content_modified() {
+        let error = serde_json::to_string(&content_modified_error()).unwrap();
+        assert_eq!(error, r#"{"code":-32801,"message":"Content modified"}"#);
+    }
+
+    #[test]
+    fn serializes_request_failed() {
+        let error = serde_json::to_string(&request_failed_error("too large")).unwrap();
+        assert_eq!(error, r#"{"code":-32803,"message":"too large"}"#);
+    }
+
+    #[test]
+    fn attaches_typed_data() {
+        let range = Range::new(Position::new(1, 2), Position::new(1, 5));
+        let error = with_error_data(request_failed_error("invalid syntax"), range);
+        let serialized = serde_json::to_value(&error).unwrap();
+        let expected = json!({
+            "code": -32803,
+            "message": "invalid syntax",
+            "data": {
+                "start": { "line": 1, "character": 2 },
+                "end": { "line": 1, "character": 5 },
+            },
+        });
+        assert_eq!(serialized, expected);
+
+        assert_eq!(error_data::<Range>(&error), Some(range));
+        assert_eq!(error_data::<Position>(&error), None);
+        assert_eq!(error_data::<Range>(&content_modified_error()), None);
+    }
+}
@@ -16,7 +16,7 @@
 //!
 //! impl LanguageServer for Backend {
 //!     type ShutdownFuture = BoxFuture<()>;
-//!     type SymbolFuture = BoxFuture<Option<Vec<SymbolInformation>>>;
+//!     type SymbolFuture = BoxFuture<Option<WorkspaceSymbolResponse>>;
 //!     type ExecuteFuture = BoxFuture<Option<Value>>;
 //!     type CompletionFuture = BoxFuture<Option<CompletionResponse>>;
 //!     type HoverFuture = BoxFuture<Option<Hover>>;
@@ -27,7 +27,7 @@
 //!     }
 //!
 //!     fn initialized(&self, printer: &Printer, _: InitializedParams) {
-//!         printer.log_message(MessageType::Info, "server initialized!");
+//!         printer.log_message(MessageType::INFO, "server initialized!");
 //!     }
 //!
 //!     fn shutdown(&self) -> Self::ShutdownFuture {
@@ -46,11 +46,11 @@
 //!         Box::new(future::ok(None))
 //!     }
 //!
-//!     fn hover(&self, _: TextDocumentPositionParams) -> Self::HoverFuture {
+//!     fn hover(&self, _: HoverParams) -> Self::HoverFuture {
 //!         Box::new(future::ok(None))
 //!     }
 //!
-//!     fn document_highlight(&self, _: TextDocumentPositionParams) -> Self::HighlightFuture {
+//!     fn document_highlight(&self, _: DocumentHighlightParams) -> Self::HighlightFuture {
 //!         Box::new(future::ok(None))
 //!     }
 //! }
@@ -59,7 +59,7 @@
 //!     let stdin = tokio::io::stdin();
 //!     let stdout = tokio::io::stdout();
 //!
-//!     let (service, messages) = LspService::new(Backend::default());
+//!     let (service, messages) = LspService::new(Backend);
 //!     let handle = service.close_handle();
 //!     let server = Server::new(stdin, stdout)
 //!         .interleave(messages)
@@ -68,6 +68,9 @@
 //!     tokio::run(handle.run_until_exit(server));
 //! }
 //! ```
+//!
+//! With the `runtime` feature enabled, the setup in `main` can be shortened to a single call to
+//! [`serve_stdio`](./fn.serve_stdio.html).
 
 #![deny(missing_debug_implementations)]
 #![deny(missing_docs)]
@@ -75,21 +78,48 @@
 
 pub extern crate lsp_types;
 
-pub use self::delegate::{MessageStream, Printer};
+#[cfg(feature = "metrics")]
+pub use self::codec::CodecMetrics;
+pub use self::codec::{read_message, write_message, CodecConfig, LanguageServerCodec, ParseError};
+pub use self::delegate::{
+    CancellationToken, DiagnosticsPublisher, Extensions, MessageStream, Printer, ProgressReporter,
+};
+pub use self::documents::{ChangeDebouncer, Document};
+pub use self::error::{
+    content_modified_error, error_data, request_failed_error, with_error_data, CONTENT_MODIFIED,
+    REQUEST_FAILED,
+};
 pub use self::message::Incoming;
-pub use self::service::{ExitReceiver, ExitedError, LspService};
-pub use self::stdio::Server;
+pub use self::position::{offset_to_position, position_to_offset};
+pub use self::semantic_tokens::{AbsoluteToken, TokenLegend, UnknownToken};
+pub use self::service::{
+    ExitReceiver, ExitedError, Lifecycle, LspService, LspServiceBuilder, ServerState,
+};
+#[cfg(feature = "runtime")]
+pub use self::stdio::serve_stdio;
+pub use self::stdio::{Server, ServerHandle};
+pub use self::workspace_edit::{UnsupportedOperation, WorkspaceEditBuilder};
 
-use futures::Future;
-use jsonrpc_core::{Error, Result};
+use futures::{future, Future};
+use jsonrpc_core::{BoxFuture, Error, Result};
+use lsp_types::request::{
+    GotoDeclarationParams, GotoDeclarationResponse, GotoImplementationParams,
+    GotoImplementationResponse, GotoTypeDefinitionParams, GotoTypeDefinitionResponse,
+};
 use lsp_types::*;
 use serde_json::Value;
 
 mod codec;
 mod delegate;
+mod documents;
+mod error;
 mod message;
+mod position;
+mod process;
+mod semantic_tokens;
 mod service;
 mod stdio;
+mod workspace_edit;
 
 /// Trait implemented by language server backends.
 ///
@@ -101,7 +131,7 @@ pub trait LanguageServer: Send + Sync + 'static {
     /// Response returned when a server shutdown is requested.
     type ShutdownFuture: Future<Item = (), Error = Error> + Send;
     /// Response returned when a workspace symbol action is requested.
-    type SymbolFuture: Future<Item = Option<Vec<SymbolInformation>>, Error = Error> + Send;
+    type SymbolFuture: Future<Item = Option<WorkspaceSymbolResponse>, Error = Error> + Send;
     /// Response returned when an execute command action is requested.
     type ExecuteFuture: Future<Item = Option<Value>, Error = Error> + Send;
     /// Response returned when a completion action is requested.
@@ -133,10 +163,43 @@ pub trait LanguageServer: Send + Sync + 'static {
     /// This request is often later followed by an [`exit`] notification, which will cause the
     /// server to exit immediately.
     ///
+    /// This is the place to release resources, e.g. to stop file watchers or to flush caches. The
+    /// client is only sent a response once the returned future has resolved, so any cleanup it
+    /// performs is guaranteed to have completed by then. Should the future fail, the client is sent
+    /// its error instead, but the server is considered shut down all the same and rejects any
+    /// further requests.
+    ///
     /// [`shutdown`]: https://microsoft.github.io/language-server-protocol/specification#shutdown
     /// [`exit`]: https://microsoft.github.io/language-server-protocol/specification#exit
     fn shutdown(&self) -> Self::ShutdownFuture;
 
+    /// The [`window/workDoneProgress/cancel`] notification is sent from the client to the server to
+    /// cancel a progress initiated on the server side.
+    ///
+    /// Any [`CancellationToken`] obtained for the progress token is cancelled before this method is
+    /// called.
+    ///
+    /// [`window/workDoneProgress/cancel`]: https://microsoft.github.io/language-server-protocol/specification#window_workDoneProgress_cancel
+    /// [`CancellationToken`]: ./struct.CancellationToken.html
+    fn work_done_progress_cancel(&self, printer: &Printer, params: WorkDoneProgressCancelParams) {
+        let _ = printer;
+        let _ = params;
+    }
+
+    /// The [`$/progress`] notification is sent from the client to the server to report progress on
+    /// a token created by the server.
+    ///
+    /// Progress usually flows from the server to the client, see [`Printer::begin_progress`]. Servers
+    /// acting as proxies, e.g. for a sub-process reporting partial results, may observe progress sent
+    /// the other way with this method.
+    ///
+    /// [`$/progress`]: https://microsoft.github.io/language-server-protocol/specification#progress
+    /// [`Printer::begin_progress`]: ./struct.Printer.html#method.begin_progress
+    fn progress(&self, printer: &Printer, params: ProgressParams) {
+        let _ = printer;
+        let _ = params;
+    }
+
     /// The [`workspace/didChangeWorkspaceFolders`] notification is sent from the client to the
     /// server to inform about workspace folder configuration changes.
     ///
@@ -159,7 +222,12 @@ pub trait LanguageServer: Send + Sync + 'static {
     /// The [`workspace/didChangeConfiguration`] notification is sent from the client to the server
     /// to signal the change of configuration settings.
     ///
+    /// The new settings may be deserialized from `params.settings`. Many clients send `null`
+    /// instead, expecting the server to pull the settings it is interested in with
+    /// [`Printer::configuration`].
+    ///
     /// [`workspace/didChangeConfiguration`]: https://microsoft.github.io/language-server-protocol/specification#workspace_didChangeConfiguration
+    /// [`Printer::configuration`]: ./struct.Printer.html#method.configuration
     fn did_change_configuration(&self, printer: &Printer, params: DidChangeConfigurationParams) {
         let _ = printer;
         let _ = params;
@@ -185,6 +253,20 @@ pub trait LanguageServer: Send + Sync + 'static {
     /// [`workspace/symbol`]: https://microsoft.github.io/language-server-protocol/specification#workspace_symbol
     fn symbol(&self, params: WorkspaceSymbolParams) -> Self::SymbolFuture;
 
+    /// The [`workspaceSymbol/resolve`] request is sent from the client to the server to resolve
+    /// additional information for a given workspace symbol.
+    ///
+    /// This allows [`symbol`] to return symbols whose location only consists of a URI, computing
+    /// the exact range only once a symbol is actually selected, which keeps queries on large
+    /// workspaces fast.
+    ///
+    /// [`workspaceSymbol/resolve`]: https://microsoft.github.io/language-server-protocol/specification#workspace_symbolResolve
+    /// [`symbol`]: #tymethod.symbol
+    fn symbol_resolve(&self, params: WorkspaceSymbol) -> BoxFuture<WorkspaceSymbol> {
+        let _ = params;
+        Box::new(future::err(Error::method_not_found()))
+    }
+
     /// The [`workspace/executeCommand`] request is sent from the client to the server to trigger
     /// command execution on the server.
     ///
@@ -194,6 +276,69 @@ pub trait LanguageServer: Send + Sync + 'static {
     /// [`workspace/executeCommand`]: https://microsoft.github.io/language-server-protocol/specification#workspace_executeCommand
     fn execute_command(&self, p: &Printer, params: ExecuteCommandParams) -> Self::ExecuteFuture;
 
+    /// The [`workspace/willCreateFiles`] request is sent from the client to the server before
+    /// files are actually created, as long as the creation is triggered from within the client.
+    ///
+    /// The server can return a `WorkspaceEdit` which will be applied to the workspace before the
+    /// files are created.
+    ///
+    /// [`workspace/willCreateFiles`]: https://microsoft.github.io/language-server-protocol/specification#workspace_willCreateFiles
+    fn will_create_files(&self, params: CreateFilesParams) -> BoxFuture<Option<WorkspaceEdit>> {
+        let _ = params;
+        Box::new(future::err(Error::method_not_found()))
+    }
+
+    /// The [`workspace/didCreateFiles`] notification is sent from the client to the server when
+    /// files were created from within the client.
+    ///
+    /// [`workspace/didCreateFiles`]: https://microsoft.github.io/language-server-protocol/specification#workspace_didCreateFiles
+    fn did_create_files(&self, printer: &Printer, params: CreateFilesParams) {
+        let _ = printer;
+        let _ = params;
+    }
+
+    /// The [`workspace/willRenameFiles`] request is sent from the client to the server before
+    /// files are actually renamed, as long as the rename is triggered from within the client.
+    ///
+    /// The server can return a `WorkspaceEdit` which will be applied to the workspace before the
+    /// files are renamed, e.g. to update import statements referring to them.
+    ///
+    /// [`workspace/willRenameFiles`]: https://microsoft.github.io/language-server-protocol/specification#workspace_willRenameFiles
+    fn will_rename_files(&self, params: RenameFilesParams) -> BoxFuture<Option<WorkspaceEdit>> {
+        let _ = params;
+        Box::new(future::err(Error::method_not_found()))
+    }
+
+    /// The [`workspace/didRenameFiles`] notification is sent from the client to the server when
+    /// files were renamed from within the client.
+    ///
+    /// [`workspace/didRenameFiles`]: https://microsoft.github.io/language-server-protocol/specification#workspace_didRenameFiles
+    fn did_rename_files(&self, printer: &Printer, params: RenameFilesParams) {
+        let _ = printer;
+        let _ = params;
+    }
+
+    /// The [`workspace/willDeleteFiles`] request is sent from the client to the server before
+    /// files are actually deleted, as long as the deletion is triggered from within the client.
+    ///
+    /// The server can return a `WorkspaceEdit` which will be applied to the workspace before the
+    /// files are deleted.
+    ///
+    /// [`workspace/willDeleteFiles`]: https://microsoft.github.io/language-server-protocol/specification#workspace_willDeleteFiles
+    fn will_delete_files(&self, params: DeleteFilesParams) -> BoxFuture<Option<WorkspaceEdit>> {
+        let _ = params;
+        Box::new(future::err(Error::method_not_found()))
+    }
+
+    /// The [`workspace/didDeleteFiles`] notification is sent from the client to the server when
+    /// files were deleted from within the client.
+    ///
+    /// [`workspace/didDeleteFiles`]: https://microsoft.github.io/language-server-protocol/specification#workspace_didDeleteFiles
+    fn did_delete_files(&self, printer: &Printer, params: DeleteFilesParams) {
+        let _ = printer;
+        let _ = params;
+    }
+
     /// The [`textDocument/didOpen`] notification is sent from the client to the server to signal
     /// that a new text document has been opened by the client.
     ///
@@ -250,6 +395,36 @@ pub trait LanguageServer: Send + Sync + 'static {
     /// [`textDocument/completion`]: https://microsoft.github.io/language-server-protocol/specification#textDocument_completion
     fn completion(&self, params: CompletionParams) -> Self::CompletionFuture;
 
+    /// The [`completionItem/resolve`] request is sent from the client to the server to resolve
+    /// additional information for a given completion item.
+    ///
+    /// This allows servers to return lightweight items from [`completion`] and to fill in
+    /// expensive properties, such as documentation, only once an item is selected.
+    ///
+    /// [`completionItem/resolve`]: https://microsoft.github.io/language-server-protocol/specification#completionItem_resolve
+    /// [`completion`]: #tymethod.completion
+    fn completion_resolve(&self, params: CompletionItem) -> BoxFuture<CompletionItem> {
+        let _ = params;
+        Box::new(future::err(Error::method_not_found()))
+    }
+
+    /// The [`textDocument/inlineCompletion`] request is sent from the client to the server to
+    /// compute inline completions, displayed as ghost text directly in the editor, for a given
+    /// text document position.
+    ///
+    /// Only available with the `proposed` feature enabled, as this method is not yet part of a
+    /// released version of the specification.
+    ///
+    /// [`textDocument/inlineCompletion`]: https://microsoft.github.io/language-server-protocol/specifications/lsp/3.18/specification/#textDocument_inlineCompletion
+    #[cfg(feature = "proposed")]
+    fn inline_completion(
+        &self,
+        params: InlineCompletionParams,
+    ) -> BoxFuture<Option<InlineCompletionResponse>> {
+        let _ = params;
+        Box::new(future::err(Error::method_not_found()))
+    }
+
     /// The [`textDocument/hover`] request asks the server for hover information at a given text
     /// document position.
     ///
@@ -257,7 +432,66 @@ pub trait LanguageServer: Send + Sync + 'static {
     /// documentation for the symbol at the given text document position.
     ///
     /// [`textDocument/hover`]: https://microsoft.github.io/language-server-protocol/specification#textDocument_hover
-    fn hover(&self, params: TextDocumentPositionParams) -> Self::HoverFuture;
+    fn hover(&self, params: HoverParams) -> Self::HoverFuture;
+
+    /// The [`textDocument/declaration`] request asks the server for the declaration location of a
+    /// symbol at a given text document position.
+    ///
+    /// The result has the same shape as that of a definition request. Languages which separate
+    /// declarations from definitions, such as C and C++ with their header files, may resolve this
+    /// differently, e.g. to the prototype of a function rather than its body.
+    ///
+    /// [`textDocument/declaration`]: https://microsoft.github.io/language-server-protocol/specification#textDocument_declaration
+    fn goto_declaration(
+        &self,
+        params: GotoDeclarationParams,
+    ) -> BoxFuture<Option<GotoDeclarationResponse>> {
+        let _ = params;
+        Box::new(future::err(Error::method_not_found()))
+    }
+
+    /// The [`textDocument/typeDefinition`] request asks the server for the location of the type
+    /// definition of a symbol at a given text document position.
+    ///
+    /// The result has the same shape as that of a definition request, but points to the definition
+    /// of the type of the symbol, e.g. to the struct of a variable rather than to the variable.
+    ///
+    /// [`textDocument/typeDefinition`]: https://microsoft.github.io/language-server-protocol/specification#textDocument_typeDefinition
+    fn goto_type_definition(
+        &self,
+        params: GotoTypeDefinitionParams,
+    ) -> BoxFuture<Option<GotoTypeDefinitionResponse>> {
+        let _ = params;
+        Box::new(future::err(Error::method_not_found()))
+    }
+
+    /// The [`textDocument/implementation`] request asks the server for the implementation
+    /// locations of a symbol at a given text document position.
+    ///
+    /// The result has the same shape as that of a definition request, but may point to several
+    /// locations, e.g. to every implementation of a trait or interface method.
+    ///
+    /// [`textDocument/implementation`]: https://microsoft.github.io/language-server-protocol/specification#textDocument_implementation
+    fn goto_implementation(
+        &self,
+        params: GotoImplementationParams,
+    ) -> BoxFuture<Option<GotoImplementationResponse>> {
+        let _ = params;
+        Box::new(future::err(Error::method_not_found()))
+    }
+
+    /// The [`textDocument/signatureHelp`] request is sent from the client to the server to request
+    /// signature information at a given cursor position.
+    ///
+    /// The `context` of the parameters indicates how the request was triggered and, if it was
+    /// retriggered, the previously active signature help. This allows servers to keep the active
+    /// signature stable while the user is typing the arguments.
+    ///
+    /// [`textDocument/signatureHelp`]: https://microsoft.github.io/language-server-protocol/specification#textDocument_signatureHelp
+    fn signature_help(&self, params: SignatureHelpParams) -> BoxFuture<Option<SignatureHelp>> {
+        let _ = params;
+        Box::new(future::err(Error::method_not_found()))
+    }
 
     /// The [`textDocument/documentHighlight`] request is sent from the client to the server to
     /// resolve appropriate highlights for a given text document position.
@@ -269,7 +503,421 @@ pub trait LanguageServer: Send + Sync + 'static {
     /// be more fuzzy.
     ///
     /// [`textDocument/documentHighlight`]: https://microsoft.github.io/language-server-protocol/specification#textDocument_documentHighlight
-    fn document_highlight(&self, params: TextDocumentPositionParams) -> Self::HighlightFuture;
+    fn document_highlight(&self, params: DocumentHighlightParams) -> Self::HighlightFuture;
+
+    /// The [`textDocument/codeAction`] request is sent from the client to the server to compute
+    /// commands for a given text document and range.
+    ///
+    /// These commands are typically code fixes to either fix problems or to beautify/refactor
+    /// code. Expensive edits may be omitted here and computed in [`code_action_resolve`] instead.
+    ///
+    /// [`textDocument/codeAction`]: https://microsoft.github.io/language-server-protocol/specification#textDocument_codeAction
+    /// [`code_action_resolve`]: #method.code_action_resolve
+    fn code_action(&self, params: CodeActionParams) -> BoxFuture<Option<CodeActionResponse>> {
+        let _ = params;
+        Box::new(future::err(Error::method_not_found()))
+    }
+
+    /// The [`codeAction/resolve`] request is sent from the client to the server to resolve
+    /// additional information for a given code action.
+    ///
+    /// This is usually used to compute the `edit` property of a code action to avoid its
+    /// unnecessary computation during the `textDocument/codeAction` request. Any `data` attached to
+    /// the code action in [`code_action`] is sent back by the client and passed in verbatim, so it
+    /// may carry whatever state is needed to compute the edit.
+    ///
+    /// [`codeAction/resolve`]: https://microsoft.github.io/language-server-protocol/specification#codeAction_resolve
+    /// [`code_action`]: #method.code_action
+    fn code_action_resolve(&self, params: CodeAction) -> BoxFuture<CodeAction> {
+        let _ = params;
+        Box::new(future::err(Error::method_not_found()))
+    }
+
+    /// The [`textDocument/codeLens`] request is sent from the client to the server to compute code
+    /// lenses for a given text document.
+    ///
+    /// [`textDocument/codeLens`]: https://microsoft.github.io/language-server-protocol/specification#textDocument_codeLens
+    fn code_lens(&self, params: CodeLensParams) -> BoxFuture<Option<Vec<CodeLens>>> {
+        let _ = params;
+        Box::new(future::err(Error::method_not_found()))
+    }
+
+    /// The [`codeLens/resolve`] request is sent from the client to the server to resolve the
+    /// command for a given code lens item.
+    ///
+    /// [`codeLens/resolve`]: https://microsoft.github.io/language-server-protocol/specification#codeLens_resolve
+    fn code_lens_resolve(&self, params: CodeLens) -> BoxFuture<CodeLens> {
+        let _ = params;
+        Box::new(future::err(Error::method_not_found()))
+    }
+
+    /// The [`textDocument/documentLink`] request is sent from the client to the server to request
+    /// the location of links in a document.
+    ///
+    /// [`textDocument/documentLink`]: https://microsoft.github.io/language-server-protocol/specification#textDocument_documentLink
+    fn document_link(&self, params: DocumentLinkParams) -> BoxFuture<Option<Vec<DocumentLink>>> {
+        let _ = params;
+        Box::new(future::err(Error::method_not_found()))
+    }
+
+    /// The [`documentLink/resolve`] request is sent from the client to the server to resolve the
+    /// target of a given document link.
+    ///
+    /// This allows servers to return links without a target from [`document_link`] and to compute
+    /// the target only once the link is actually opened.
+    ///
+    /// [`documentLink/resolve`]: https://microsoft.github.io/language-server-protocol/specification#documentLink_resolve
+    /// [`document_link`]: #method.document_link
+    fn document_link_resolve(&self, params: DocumentLink) -> BoxFuture<DocumentLink> {
+        let _ = params;
+        Box::new(future::err(Error::method_not_found()))
+    }
+
+    /// The [`textDocument/documentColor`] request is sent from the client to the server to list all
+    /// color references found in a given text document. Along with the range, a color value in RGB
+    /// is returned.
+    ///
+    /// Clients can use the result to decorate color references in an editor, e.g. with a color
+    /// swatch.
+    ///
+    /// [`textDocument/documentColor`]: https://microsoft.github.io/language-server-protocol/specification#textDocument_documentColor
+    fn document_color(&self, params: DocumentColorParams) -> BoxFuture<Vec<ColorInformation>> {
+        let _ = params;
+        Box::new(future::err(Error::method_not_found()))
+    }
+
+    /// The [`textDocument/colorPresentation`] request is sent from the client to the server to
+    /// obtain a list of presentations for a color value at a given location.
+    ///
+    /// Clients can use the result to modify a color reference, or show it in a color picker and let
+    /// users pick one of the presentations.
+    ///
+    /// [`textDocument/colorPresentation`]: https://microsoft.github.io/language-server-protocol/specification#textDocument_colorPresentation
+    fn color_presentation(
+        &self,
+        params: ColorPresentationParams,
+    ) -> BoxFuture<Vec<ColorPresentation>> {
+        let _ = params;
+        Box::new(future::err(Error::method_not_found()))
+    }
+
+    /// The [`textDocument/onTypeFormatting`] request is sent from the client to the server to format
+    /// parts of the document during typing.
+    ///
+    /// The request is only sent for the trigger characters announced by the server in the
+    /// `documentOnTypeFormattingProvider` capability. The typed character is passed in the `ch`
+    /// field of the parameters, e.g. to auto-indent after a newline or to close a brace.
+    ///
+    /// [`textDocument/onTypeFormatting`]: https://microsoft.github.io/language-server-protocol/specification#textDocument_onTypeFormatting
+    fn on_type_formatting(
+        &self,
+        params: DocumentOnTypeFormattingParams,
+    ) -> BoxFuture<Option<Vec<TextEdit>>> {
+        let _ = params;
+        Box::new(future::err(Error::method_not_found()))
+    }
+
+    /// The [`textDocument/rangeFormatting`] request is sent from the client to the server to
+    /// format a given range in a document.
+    ///
+    /// This is typically used to format the current selection. The LSP 3.18
+    /// `textDocument/rangesFormatting` request for formatting several ranges at once is not modeled
+    /// by `lsp_types` yet, but may be served with [`LspServiceBuilder::custom_method`] meanwhile.
+    ///
+    /// [`textDocument/rangeFormatting`]: https://microsoft.github.io/language-server-protocol/specification#textDocument_rangeFormatting
+    /// [`LspServiceBuilder::custom_method`]: ./struct.LspServiceBuilder.html#method.custom_method
+    fn range_formatting(
+        &self,
+        params: DocumentRangeFormattingParams,
+    ) -> BoxFuture<Option<Vec<TextEdit>>> {
+        let _ = params;
+        Box::new(future::err(Error::method_not_found()))
+    }
+
+    /// The [`textDocument/foldingRange`] request is sent from the client to the server to return
+    /// all folding ranges found in a given text document.
+    ///
+    /// [`textDocument/foldingRange`]: https://microsoft.github.io/language-server-protocol/specification#textDocument_foldingRange
+    fn folding_range(&self, params: FoldingRangeParams) -> BoxFuture<Option<Vec<FoldingRange>>> {
+        let _ = params;
+        Box::new(future::err(Error::method_not_found()))
+    }
+
+    /// The [`textDocument/selectionRange`] request is sent from the client to the server to return
+    /// suggested selection ranges at an array of given positions.
+    ///
+    /// A selection range is a range around the cursor position which the user might be interested
+    /// in selecting, e.g. when expanding the current selection.
+    ///
+    /// [`textDocument/selectionRange`]: https://microsoft.github.io/language-server-protocol/specification#textDocument_selectionRange
+    fn selection_range(
+        &self,
+        params: SelectionRangeParams,
+    ) -> BoxFuture<Option<Vec<SelectionRange>>> {
+        let _ = params;
+        Box::new(future::err(Error::method_not_found()))
+    }
+
+    /// The [`textDocument/linkedEditingRange`] request is sent from the client to the server to
+    /// return the ranges which have the same content as the range at the given position and which
+    /// should be edited together, e.g. the opening and closing tags of an HTML element.
+    ///
+    /// [`textDocument/linkedEditingRange`]: https://microsoft.github.io/language-server-protocol/specification#textDocument_linkedEditingRange
+    fn linked_editing_range(
+        &self,
+        params: LinkedEditingRangeParams,
+    ) -> BoxFuture<Option<LinkedEditingRanges>> {
+        let _ = params;
+        Box::new(future::err(Error::method_not_found()))
+    }
+
+    /// The [`textDocument/moniker`] request is sent from the client to the server to get the symbol
+    /// monikers for a given text document position.
+    ///
+    /// Monikers identify symbols across projects and repositories, e.g. for cross-repository code
+    /// navigation built on LSIF.
+    ///
+    /// [`textDocument/moniker`]: https://microsoft.github.io/language-server-protocol/specification#textDocument_moniker
+    fn moniker(&self, params: MonikerParams) -> BoxFuture<Option<Vec<Moniker>>> {
+        let _ = params;
+        Box::new(future::err(Error::method_not_found()))
+    }
+
+    /// The [`textDocument/inlayHint`] request is sent from the client to the server to compute
+    /// inlay hints for a given text document range, e.g. parameter names or inferred types.
+    ///
+    /// Computing the tooltip, text edits or label locations of a hint may be deferred to
+    /// [`inlay_hint_resolve`].
+    ///
+    /// [`textDocument/inlayHint`]: https://microsoft.github.io/language-server-protocol/specification#textDocument_inlayHint
+    /// [`inlay_hint_resolve`]: #method.inlay_hint_resolve
+    fn inlay_hint(&self, params: InlayHintParams) -> BoxFuture<Option<Vec<InlayHint>>> {
+        let _ = params;
+        Box::new(future::err(Error::method_not_found()))
+    }
+
+    /// The [`inlayHint/resolve`] request is sent from the client to the server to resolve
+    /// additional information for a given inlay hint.
+    ///
+    /// [`inlayHint/resolve`]: https://microsoft.github.io/language-server-protocol/specification#inlayHint_resolve
+    fn inlay_hint_resolve(&self, params: InlayHint) -> BoxFuture<InlayHint> {
+        let _ = params;
+        Box::new(future::err(Error::method_not_found()))
+    }
+
+    /// The [`textDocument/semanticTokens/full`] request is sent from the client to the server to
+    /// compute the semantic tokens of a whole text document.
+    ///
+    /// Tokens index into the legend advertised in the server capabilities. [`TokenLegend`] builds such
+    /// a legend and encodes tokens into the relative format expected by the client.
+    ///
+    /// [`textDocument/semanticTokens/full`]: https://microsoft.github.io/language-server-protocol/specification#semanticTokens_fullRequest
+    /// [`TokenLegend`]: ./struct.TokenLegend.html
+    fn semantic_tokens_full(
+        &self,
+        params: SemanticTokensParams,
+    ) -> BoxFuture<Option<SemanticTokensResult>> {
+        let _ = params;
+        Box::new(future::err(Error::method_not_found()))
+    }
+
+    /// The [`textDocument/semanticTokens/full/delta`] request is sent from the client to the server
+    /// to compute the changes to the semantic tokens of a whole text document since a previous result,
+    /// identified by its `result_id`.
+    ///
+    /// [`textDocument/semanticTokens/full/delta`]: https://microsoft.github.io/language-server-protocol/specification#semanticTokens_deltaRequest
+    fn semantic_tokens_full_delta(
+        &self,
+        params: SemanticTokensDeltaParams,
+    ) -> BoxFuture<Option<SemanticTokensFullDeltaResult>> {
+        let _ = params;
+        Box::new(future::err(Error::method_not_found()))
+    }
+
+    /// The [`textDocument/semanticTokens/range`] request is sent from the client to the server to
+    /// compute the semantic tokens of a visible range, e.g. while a large document is still being
+    /// processed in full.
+    ///
+    /// [`textDocument/semanticTokens/range`]: https://microsoft.github.io/language-server-protocol/specification#semanticTokens_rangeRequest
+    fn semantic_tokens_range(
+        &self,
+        params: SemanticTokensRangeParams,
+    ) -> BoxFuture<Option<SemanticTokensRangeResult>> {
+        let _ = params;
+        Box::new(future::err(Error::method_not_found()))
+    }
+
+    /// The [`textDocument/rename`] request is sent from the client to the server to ask the server
+    /// to compute a workspace change so that the client can perform a workspace-wide rename of a
+    /// symbol.
+    ///
+    /// [`textDocument/rename`]: https://microsoft.github.io/language-server-protocol/specification#textDocument_rename
+    fn rename(&self, params: RenameParams) -> BoxFuture<Option<WorkspaceEdit>> {
+        let _ = params;
+        Box::new(future::err(Error::method_not_found()))
+    }
+
+    /// The [`textDocument/prepareRename`] request is sent from the client to the server to setup
+    /// and test the validity of a rename operation at a given location.
+    ///
+    /// Clients send this request before `textDocument/rename`, which allows servers to reject
+    /// invalid rename targets early and to return the range of the symbol being renamed.
+    ///
+    /// If the client announced support for `prepareSupportDefaultBehavior` in its rename
+    /// capabilities, servers may return `PrepareRenameResponse::DefaultBehavior` to let the client
+    /// pick the range using its own word detection. This is serialized as
+    /// `{ "defaultBehavior": true }`.
+    ///
+    /// [`textDocument/prepareRename`]: https://microsoft.github.io/language-server-protocol/specification#textDocument_prepareRename
+    fn prepare_rename(
+        &self,
+        params: TextDocumentPositionParams,
+    ) -> BoxFuture<Option<PrepareRenameResponse>> {
+        let _ = params;
+        Box::new(future::err(Error::method_not_found()))
+    }
+
+    /// The [`textDocument/prepareCallHierarchy`] request is sent from the client to the server to
+    /// return a call hierarchy for the language element at the given text document position.
+    ///
+    /// The call hierarchy requests are executed in two steps:
+    ///
+    /// 1. First, a call hierarchy item is resolved for the given text document position.
+    /// 2. For a call hierarchy item, the incoming or outgoing call hierarchy items are resolved
+    ///    using [`incoming_calls`] and [`outgoing_calls`], respectively.
+    ///
+    /// Returning `None` signals that no call hierarchy applies at the given position and is sent
+    /// as `null`, whereas `Some(vec![])` is sent as an empty array.
+    ///
+    /// [`textDocument/prepareCallHierarchy`]: https://microsoft.github.io/language-server-protocol/specification#textDocument_prepareCallHierarchy
+    /// [`incoming_calls`]: #method.incoming_calls
+    /// [`outgoing_calls`]: #method.outgoing_calls
+    fn prepare_call_hierarchy(
+        &self,
+        params: CallHierarchyPrepareParams,
+    ) -> BoxFuture<Option<Vec<CallHierarchyItem>>> {
+        let _ = params;
+        Box::new(future::err(Error::method_not_found()))
+    }
+
+    /// The [`callHierarchy/incomingCalls`] request is sent from the client to the server to
+    /// resolve incoming calls for a given call hierarchy item.
+    ///
+    /// The request doesn't define its own client and server capabilities. It is only issued if a
+    /// server registers for the `textDocument/prepareCallHierarchy` request.
+    ///
+    /// [`callHierarchy/incomingCalls`]: https://microsoft.github.io/language-server-protocol/specification#callHierarchy_incomingCalls
+    fn incoming_calls(
+        &self,
+        params: CallHierarchyIncomingCallsParams,
+    ) -> BoxFuture<Option<Vec<CallHierarchyIncomingCall>>> {
+        let _ = params;
+        Box::new(future::err(Error::method_not_found()))
+    }
+
+    /// The [`callHierarchy/outgoingCalls`] request is sent from the client to the server to
+    /// resolve outgoing calls for a given call hierarchy item.
+    ///
+    /// The request doesn't define its own client and server capabilities. It is only issued if a
+    /// server registers for the `textDocument/prepareCallHierarchy` request.
+    ///
+    /// [`callHierarchy/outgoingCalls`]: https://microsoft.github.io/language-server-protocol/specification#callHierarchy_outgoingCalls
+    fn outgoing_calls(
+        &self,
+        params: CallHierarchyOutgoingCallsParams,
+    ) -> BoxFuture<Option<Vec<CallHierarchyOutgoingCall>>> {
+        let _ = params;
+        Box::new(future::err(Error::method_not_found()))
+    }
+
+    /// The [`textDocument/prepareTypeHierarchy`] request is sent from the client to the server to
+    /// return a type hierarchy for the language element at the given text document position.
+    ///
+    /// The type hierarchy requests are executed in two steps:
+    ///
+    /// 1. First, a type hierarchy item is prepared for the given text document position.
+    /// 2. For a type hierarchy item, the supertype or subtype type hierarchy items are resolved
+    ///    using [`supertypes`] and [`subtypes`], respectively.
+    ///
+    /// Returning `None` signals that no type hierarchy applies at the given position and is sent
+    /// as `null`, whereas `Some(vec![])` is sent as an empty array.
+    ///
+    /// [`textDocument/prepareTypeHierarchy`]: https://microsoft.github.io/language-server-protocol/specification#textDocument_prepareTypeHierarchy
+    /// [`supertypes`]: #method.supertypes
+    /// [`subtypes`]: #method.subtypes
+    fn prepare_type_hierarchy(
+        &self,
+        params: TypeHierarchyPrepareParams,
+    ) -> BoxFuture<Option<Vec<TypeHierarchyItem>>> {
+        let _ = params;
+        Box::new(future::err(Error::method_not_found()))
+    }
+
+    /// The [`typeHierarchy/supertypes`] request is sent from the client to the server to resolve
+    /// the supertypes for a given type hierarchy item.
+    ///
+    /// The request doesn't define its own client and server capabilities. It is only issued if a
+    /// server registers for the `textDocument/prepareTypeHierarchy` request.
+    ///
+    /// [`typeHierarchy/supertypes`]: https://microsoft.github.io/language-server-protocol/specification#typeHierarchy_supertypes
+    fn supertypes(
+        &self,
+        params: TypeHierarchySupertypesParams,
+    ) -> BoxFuture<Option<Vec<TypeHierarchyItem>>> {
+        let _ = params;
+        Box::new(future::err(Error::method_not_found()))
+    }
+
+    /// The [`typeHierarchy/subtypes`] request is sent from the client to the server to resolve
+    /// the subtypes for a given type hierarchy item.
+    ///
+    /// The request doesn't define its own client and server capabilities. It is only issued if a
+    /// server registers for the `textDocument/prepareTypeHierarchy` request.
+    ///
+    /// [`typeHierarchy/subtypes`]: https://microsoft.github.io/language-server-protocol/specification#typeHierarchy_subtypes
+    fn subtypes(
+        &self,
+        params: TypeHierarchySubtypesParams,
+    ) -> BoxFuture<Option<Vec<TypeHierarchyItem>>> {
+        let _ = params;
+        Box::new(future::err(Error::method_not_found()))
+    }
+
+    /// The [`textDocument/diagnostic`] request is sent from the client to the server to ask the
+    /// server to compute the diagnostics for a given document.
+    ///
+    /// This is the pull-based alternative to publishing diagnostics with
+    /// [`Printer::publish_diagnostics`].
+    ///
+    /// Reports may carry a `result_id`, which the client passes back as `previous_result_id` on
+    /// its next request for the same document. If the diagnostics haven't changed since, an
+    /// `Unchanged` report with that ID saves resending them. Servers whose diagnostics span
+    /// multiple files, i.e. which declare `inter_file_dependencies`, may also report on other
+    /// documents affected by this one in `related_documents`, each with its own full or unchanged
+    /// report.
+    ///
+    /// [`textDocument/diagnostic`]: https://microsoft.github.io/language-server-protocol/specification#textDocument_diagnostic
+    /// [`Printer::publish_diagnostics`]: ./struct.Printer.html#method.publish_diagnostics
+    fn diagnostic(
+        &self,
+        params: DocumentDiagnosticParams,
+    ) -> BoxFuture<DocumentDiagnosticReportResult> {
+        let _ = params;
+        Box::new(future::err(Error::method_not_found()))
+    }
+
+    /// The [`workspace/diagnostic`] request is sent from the client to the server to ask the server
+    /// to compute workspace wide diagnostics which previously were pushed from the server to the
+    /// client.
+    ///
+    /// [`workspace/diagnostic`]: https://microsoft.github.io/language-server-protocol/specification#workspace_diagnostic
+    fn workspace_diagnostic(
+        &self,
+        params: WorkspaceDiagnosticParams,
+    ) -> BoxFuture<WorkspaceDiagnosticReportResult> {
+        let _ = params;
+        Box::new(future::err(Error::method_not_found()))
+    }
 }
 
 impl<S: ?Sized + LanguageServer> LanguageServer for Box<S> {
@@ -292,6 +940,14 @@ impl<S: ?Sized + LanguageServer> LanguageServer for Box<S> {
         (**self).shutdown()
     }
 
+    fn work_done_progress_cancel(&self, printer: &Printer, params: WorkDoneProgressCancelParams) {
+        (**self).work_done_progress_cancel(printer, params);
+    }
+
+    fn progress(&self, printer: &Printer, params: ProgressParams) {
+        (**self).progress(printer, params);
+    }
+
     fn did_change_workspace_folders(&self, p: &Printer, params: DidChangeWorkspaceFoldersParams) {
         (**self).did_change_workspace_folders(p, params);
     }
@@ -308,14 +964,54 @@ impl<S: ?Sized + LanguageServer> LanguageServer for Box<S> {
         (**self).symbol(params)
     }
 
+    fn symbol_resolve(&self, params: WorkspaceSymbol) -> BoxFuture<WorkspaceSymbol> {
+        (**self).symbol_resolve(params)
+    }
+
     fn execute_command(&self, p: &Printer, params: ExecuteCommandParams) -> Self::ExecuteFuture {
         (**self).execute_command(p, params)
     }
 
+    fn will_create_files(&self, params: CreateFilesParams) -> BoxFuture<Option<WorkspaceEdit>> {
+        (**self).will_create_files(params)
+    }
+
+    fn did_create_files(&self, printer: &Printer, params: CreateFilesParams) {
+        (**self).did_create_files(printer, params);
+    }
+
+    fn will_rename_files(&self, params: RenameFilesParams) -> BoxFuture<Option<WorkspaceEdit>> {
+        (**self).will_rename_files(params)
+    }
+
+    fn did_rename_files(&self, printer: &Printer, params: RenameFilesParams) {
+        (**self).did_rename_files(printer, params);
+    }
+
+    fn will_delete_files(&self, params: DeleteFilesParams) -> BoxFuture<Option<WorkspaceEdit>> {
+        (**self).will_delete_files(params)
+    }
+
+    fn did_delete_files(&self, printer: &Printer, params: DeleteFilesParams) {
+        (**self).did_delete_files(printer, params);
+    }
+
     fn completion(&self, params: CompletionParams) -> Self::CompletionFuture {
         (**self).completion(params)
     }
 
+    fn completion_resolve(&self, params: CompletionItem) -> BoxFuture<CompletionItem> {
+        (**self).completion_resolve(params)
+    }
+
+    #[cfg(feature = "proposed")]
+    fn inline_completion(
+        &self,
+        params: InlineCompletionParams,
+    ) -> BoxFuture<Option<InlineCompletionResponse>> {
+        (**self).inline_completion(params)
+    }
+
     fn did_open(&self, printer: &Printer, params: DidOpenTextDocumentParams) {
         (**self).did_open(printer, params);
     }
@@ -332,11 +1028,203 @@ impl<S: ?Sized + LanguageServer> LanguageServer for Box<S> {
         (**self).did_close(printer, params);
     }
 
-    fn hover(&self, params: TextDocumentPositionParams) -> Self::HoverFuture {
+    fn hover(&self, params: HoverParams) -> Self::HoverFuture {
         (**self).hover(params)
     }
 
-    fn document_highlight(&self, params: TextDocumentPositionParams) -> Self::HighlightFuture {
+    fn goto_declaration(
+        &self,
+        params: GotoDeclarationParams,
+    ) -> BoxFuture<Option<GotoDeclarationResponse>> {
+        (**self).goto_declaration(params)
+    }
+
+    fn goto_type_definition(
+        &self,
+        params: GotoTypeDefinitionParams,
+    ) -> BoxFuture<Option<GotoTypeDefinitionResponse>> {
+        (**self).goto_type_definition(params)
+    }
+
+    fn goto_implementation(
+        &self,
+        params: GotoImplementationParams,
+    ) -> BoxFuture<Option<GotoImplementationResponse>> {
+        (**self).goto_implementation(params)
+    }
+
+    fn signature_help(&self, params: SignatureHelpParams) -> BoxFuture<Option<SignatureHelp>> {
+        (**self).signature_help(params)
+    }
+
+    fn document_highlight(&self, params: DocumentHighlightParams) -> Self::HighlightFuture {
         (**self).document_highlight(params)
     }
+
+    fn code_action(&self, params: CodeActionParams) -> BoxFuture<Option<CodeActionResponse>> {
+        (**self).code_action(params)
+    }
+
+    fn code_action_resolve(&self, params: CodeAction) -> BoxFuture<CodeAction> {
+        (**self).code_action_resolve(params)
+    }
+
+    fn code_lens(&self, params: CodeLensParams) -> BoxFuture<Option<Vec<CodeLens>>> {
+        (**self).code_lens(params)
+    }
+
+    fn code_lens_resolve(&self, params: CodeLens) -> BoxFuture<CodeLens> {
+        (**self).code_lens_resolve(params)
+    }
+
+    fn document_link(&self, params: DocumentLinkParams) -> BoxFuture<Option<Vec<DocumentLink>>> {
+        (**self).document_link(params)
+    }
+
+    fn document_link_resolve(&self, params: DocumentLink) -> BoxFuture<DocumentLink> {
+        (**self).document_link_resolve(params)
+    }
+
+    fn document_color(&self, params: DocumentColorParams) -> BoxFuture<Vec<ColorInformation>> {
+        (**self).document_color(params)
+    }
+
+    fn color_presentation(
+        &self,
+        params: ColorPresentationParams,
+    ) -> BoxFuture<Vec<ColorPresentation>> {
+        (**self).color_presentation(params)
+    }
+
+    fn on_type_formatting(
+        &self,
+        params: DocumentOnTypeFormattingParams,
+    ) -> BoxFuture<Option<Vec<TextEdit>>> {
+        (**self).on_type_formatting(params)
+    }
+
+    fn range_formatting(
+        &self,
+        params: DocumentRangeFormattingParams,
+    ) -> BoxFuture<Option<Vec<TextEdit>>> {
+        (**self).range_formatting(params)
+    }
+
+    fn folding_range(&self, params: FoldingRangeParams) -> BoxFuture<Option<Vec<FoldingRange>>> {
+        (**self).folding_range(params)
+    }
+
+    fn selection_range(
+        &self,
+        params: SelectionRangeParams,
+    ) -> BoxFuture<Option<Vec<SelectionRange>>> {
+        (**self).selection_range(params)
+    }
+
+    fn linked_editing_range(
+        &self,
+        params: LinkedEditingRangeParams,
+    ) -> BoxFuture<Option<LinkedEditingRanges>> {
+        (**self).linked_editing_range(params)
+    }
+
+    fn moniker(&self, params: MonikerParams) -> BoxFuture<Option<Vec<Moniker>>> {
+        (**self).moniker(params)
+    }
+
+    fn inlay_hint(&self, params: InlayHintParams) -> BoxFuture<Option<Vec<InlayHint>>> {
+        (**self).inlay_hint(params)
+    }
+
+    fn inlay_hint_resolve(&self, params: InlayHint) -> BoxFuture<InlayHint> {
+        (**self).inlay_hint_resolve(params)
+    }
+
+    fn semantic_tokens_full(
+        &self,
+        params: SemanticTokensParams,
+    ) -> BoxFuture<Option<SemanticTokensResult>> {
+        (**self).semantic_tokens_full(params)
+    }
+
+    fn semantic_tokens_full_delta(
+        &self,
+        params: SemanticTokensDeltaParams,
+    ) -> BoxFuture<Option<SemanticTokensFullDeltaResult>> {
+        (**self).semantic_tokens_full_delta(params)
+    }
+
+    fn semantic_tokens_range(
+        &self,
+        params: SemanticTokensRangeParams,
+    ) -> BoxFuture<Option<SemanticTokensRangeResult>> {
+        (**self).semantic_tokens_range(params)
+    }
+
+    fn rename(&self, params: RenameParams) -> BoxFuture<Option<WorkspaceEdit>> {
+        (**self).rename(params)
+    }
+
+    fn prepare_rename(
+        &self,
+        params: TextDocumentPositionParams,
+    ) -> BoxFuture<Option<PrepareRenameResponse>> {
+        (**self).prepare_rename(params)
+    }
+
+    fn prepare_call_hierarchy(
+        &self,
+        params: CallHierarchyPrepareParams,
+    ) -> BoxFuture<Option<Vec<CallHierarchyItem>>> {
+        (**self).prepare_call_hierarchy(params)
+    }
+
+    fn incoming_calls(
+        &self,
+        params: CallHierarchyIncomingCallsParams,
+    ) -> BoxFuture<Option<Vec<CallHierarchyIncomingCall>>> {
+        (**self).incoming_calls(params)
+    }
+
+    fn outgoing_calls(
+        &self,
+        params: CallHierarchyOutgoingCallsParams,
+    ) -> BoxFuture<Option<Vec<CallHierarchyOutgoingCall>>> {
+        (**self).outgoing_calls(params)
+    }
+
+    fn prepare_type_hierarchy(
+        &self,
+        params: TypeHierarchyPrepareParams,
+    ) -> BoxFuture<Option<Vec<TypeHierarchyItem>>> {
+        (**self).prepare_type_hierarchy(params)
+    }
+
+    fn supertypes(
+        &self,
+        params: TypeHierarchySupertypesParams,
+    ) -> BoxFuture<Option<Vec<TypeHierarchyItem>>> {
+        (**self).supertypes(params)
+    }
+
+    fn subtypes(
+        &self,
+        params: TypeHierarchySubtypesParams,
+    ) -> BoxFuture<Option<Vec<TypeHierarchyItem>>> {
+        (**self).subtypes(params)
+    }
+
+    fn diagnostic(
+        &self,
+        params: DocumentDiagnosticParams,
+    ) -> BoxFuture<DocumentDiagnosticReportResult> {
+        (**self).diagnostic(params)
+    }
+
+    fn workspace_diagnostic(
+        &self,
+        params: WorkspaceDiagnosticParams,
+    ) -> BoxFuture<WorkspaceDiagnosticReportResult> {
+        (**self).workspace_diagnostic(params)
+    }
 }
@@ -3,6 +3,7 @@
 use std::fmt::{Display, Formatter, Result as FmtResult};
 use std::str::FromStr;
 
+use bytes::Bytes;
 use jsonrpc_core::types::request::{MethodCall, Notification};
 use jsonrpc_core::types::response::Output;
 use serde::{Deserialize, Serialize};
@@ -44,7 +45,14 @@ impl Display for Incoming {
 
 impl From<String> for Incoming {
     fn from(s: String) -> Self {
-        Incoming::from_str(&s).unwrap_or_else(|_| Incoming::Invalid(s))
+        Incoming::from_str(&s).unwrap_or(Incoming::Invalid(s))
+    }
+}
+
+impl From<Bytes> for Incoming {
+    fn from(bytes: Bytes) -> Self {
+        serde_json::from_slice(&bytes)
+            .unwrap_or_else(|_| Incoming::Invalid(String::from_utf8_lossy(&bytes).into_owned()))
     }
 }
 
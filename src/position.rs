@@ -0,0 +1,253 @@
+//! Conversions between LSP positions and byte offsets into document text.
+
+use std::iter;
+
+use lsp_types::{Position, PositionEncodingKind};
+
+/// Converts a `Position` in `text` into a byte offset, counting characters in `encoding`.
+///
+/// Lines may be terminated by `\n`, `\r\n`, or `\r`. As per the specification, a character value
+/// greater than the length of the line defaults back to the end of the line. A character value
+/// pointing into the middle of a multi-unit character, e.g. between the two halves of a UTF-16
+/// surrogate pair, is rounded down to the start of that character. Unknown encodings are treated
+/// as UTF-16, the default encoding of the protocol.
+///
+/// Returns `None` if the line does not exist in `text`.
+pub fn position_to_offset(
+    text: &str,
+    position: Position,
+    encoding: &PositionEncodingKind,
+) -> Option<usize> {
+    let (start, end) = lines(text).nth(position.line as usize)?;
+
+    let mut units = 0;
+    for (index, c) in text[start..end].char_indices() {
+        units += char_len(c, encoding);
+        if units > position.character as usize {
+            return Some(start + index);
+        }
+    }
+
+    Some(end)
+}
+
+/// Converts a byte offset into `text` into a `Position`, counting characters in `encoding`.
+///
+/// Offsets pointing at a line terminator, including between the `\r` and `\n` of a `\r\n`
+/// sequence, map to the end of that line. Unknown encodings are treated as UTF-16, the default
+/// encoding of the protocol.
+///
+/// Returns `None` if `offset` is out of bounds or does not lie on a character boundary.
+pub fn offset_to_position(
+    text: &str,
+    offset: usize,
+    encoding: &PositionEncodingKind,
+) -> Option<Position> {
+    if !text.is_char_boundary(offset) {
+        return None;
+    }
+
+    let mut line = 0;
+    let mut line_start = 0;
+    let mut line_end = text.len();
+    for (number, (start, end)) in lines(text).enumerate() {
+        if start > offset {
+            break;
+        }
+
+        line = number;
+        line_start = start;
+        line_end = end;
+    }
+
+    let character = text[line_start..offset.min(line_end)]
+        .chars()
+        .map(|c| char_len(c, encoding))
+        .sum::<usize>();
+
+    Some(Position::new(line as u32, character as u32))
+}
+
+/// Returns the number of code units needed to represent `c` in the given encoding.
+fn char_len(c: char, encoding: &PositionEncodingKind) -> usize {
+    if *encoding == PositionEncodingKind::UTF8 {
+        c.len_utf8()
+    } else if *encoding == PositionEncodingKind::UTF32 {
+        1
+    } else {
+        c.len_utf16()
+    }
+}
+
+/// Returns the start and end byte offsets of the contents of each line, excluding terminators.
+fn lines(text: &str) -> impl Iterator<Item = (usize, usize)> + '_ {
+    let bytes = text.as_bytes();
+    let mut next_start = Some(0);
+
+    iter::from_fn(move || {
+        let start = next_start?;
+        match bytes[start..]
+            .iter()
+            .position(|&b| b == b'\n' || b == b'\r')
+        {
+            Some(len) => {
+                let end = start + len;
+                let terminator_len = match &bytes[end..] {
+                    [b'\r', b'\n', ..] => 2,
+                    _ => 1,
+                };
+                next_start = Some(end + terminator_len);
+                Some((start, end))
+            }
+            None => {
+                next_start = None;
+                Some((start, bytes.len()))
+            }
+        }
+    })
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    const ENCODINGS: [PositionEncodingKind; 3] = [
+        PositionEncodingKind::UTF8,
+        PositionEncodingKind::UTF16,
+        PositionEncodingKind::UTF32,
+    ];
+
+    #[test]
+    fn converts_ascii() {
+        let text = "foo\nbar baz\n";
+        for encoding in &ENCODINGS {
+            let position = Position::new(1, 4);
+            assert_eq!(position_to_offset(text, position, encoding), Some(8));
+            assert_eq!(offset_to_position(text, 8, encoding), Some(position));
+        }
+    }
+
+    #[test]
+    fn handles_line_terminators() {
+        let text = "a\r\nb\rc\nd";
+        for encoding in &ENCODINGS {
+            for &(offset, line, character) in &[(0, 0, 0), (3, 1, 0), (5, 2, 0), (7, 3, 0)] {
+                let position = Position::new(line, character);
+                assert_eq!(position_to_offset(text, position, encoding), Some(offset));
+                assert_eq!(offset_to_position(text, offset, encoding), Some(position));
+            }
+
+            // Both halves of the `\r\n` terminator belong to the end of the first line.
+            assert_eq!(
+                offset_to_position(text, 1, encoding),
+                Some(Position::new(0, 1))
+            );
+            assert_eq!(
+                offset_to_position(text, 2, encoding),
+                Some(Position::new(0, 1))
+            );
+        }
+    }
+
+    #[test]
+    fn handles_trailing_newline() {
+        let text = "foo\r\n";
+        let encoding = &PositionEncodingKind::UTF16;
+        assert_eq!(
+            position_to_offset(text, Position::new(1, 0), encoding),
+            Some(5)
+        );
+        assert_eq!(
+            offset_to_position(text, 5, encoding),
+            Some(Position::new(1, 0))
+        );
+        assert_eq!(
+            position_to_offset(text, Position::new(2, 0), encoding),
+            None
+        );
+    }
+
+    #[test]
+    fn counts_multibyte_characters() {
+        // `é` is 2 bytes in UTF-8 and a single UTF-16 code unit, while `😀` lies in the astral
+        // plane and takes 4 bytes in UTF-8 and a surrogate pair in UTF-16.
+        let text = "é😀x\r\né😀x";
+        let cases = [
+            (PositionEncodingKind::UTF8, 6),
+            (PositionEncodingKind::UTF16, 3),
+            (PositionEncodingKind::UTF32, 2),
+        ];
+
+        for (encoding, character) in &cases {
+            for &(line, line_start) in &[(0, 0), (1, 9)] {
+                let position = Position::new(line, *character);
+                let offset = line_start + 6;
+                assert_eq!(position_to_offset(text, position, encoding), Some(offset));
+                assert_eq!(offset_to_position(text, offset, encoding), Some(position));
+            }
+        }
+    }
+
+    #[test]
+    fn rounds_down_inside_characters() {
+        let text = "😀x";
+        let position = Position::new(0, 1);
+        let utf16 = &PositionEncodingKind::UTF16;
+        assert_eq!(position_to_offset(text, position, utf16), Some(0));
+        let utf8 = &PositionEncodingKind::UTF8;
+        assert_eq!(position_to_offset(text, position, utf8), Some(0));
+    }
+
+    #[test]
+    fn clamps_character_to_line_length() {
+        let text = "foo\r\nbar";
+        let encoding = &PositionEncodingKind::UTF16;
+        assert_eq!(
+            position_to_offset(text, Position::new(0, 10), encoding),
+            Some(3)
+        );
+        assert_eq!(
+            position_to_offset(text, Position::new(1, 10), encoding),
+            Some(8)
+        );
+    }
+
+    #[test]
+    fn rejects_invalid_offsets() {
+        let text = "é\nx";
+        let encoding = &PositionEncodingKind::UTF16;
+        assert_eq!(offset_to_position(text, 1, encoding), None);
+        assert_eq!(offset_to_position(text, 5, encoding), None);
+        assert_eq!(
+            offset_to_position(text, 4, encoding),
+            Some(Position::new(1, 1))
+        );
+    }
+
+    #[test]
+    fn defaults_to_utf16() {
+        let text = "😀x";
+        let unknown = PositionEncodingKind::new("utf-7");
+        assert_eq!(
+            position_to_offset(text, Position::new(0, 2), &unknown),
+            Some(4)
+        );
+        assert_eq!(
+            offset_to_position(text, 4, &unknown),
+            Some(Position::new(0, 2))
+        );
+    }
+
+    #[test]
+    fn handles_empty_text() {
+        let encoding = &PositionEncodingKind::UTF16;
+        assert_eq!(
+            position_to_offset("", Position::new(0, 0), encoding),
+            Some(0)
+        );
+        assert_eq!(
+            offset_to_position("", 0, encoding),
+            Some(Position::new(0, 0))
+        );
+    }
+}
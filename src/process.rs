@@ -0,0 +1,117 @@
+//! Monitoring of the client process which started the language server.
+
+use std::sync::{Arc, Weak};
+use std::thread;
+use std::time::Duration;
+
+use log::{error, info};
+
+use super::service::ExitSignal;
+
+/// Exits the language server once the client process identified in `initialize` has died.
+///
+/// As recommended by the specification, this prevents servers from lingering on after the editor
+/// which started them has crashed.
+#[derive(Debug)]
+pub(crate) struct ParentMonitor {
+    interval: Duration,
+    exit: Weak<ExitSignal>,
+}
+
+impl ParentMonitor {
+    pub fn new(interval: Duration, exit: &Arc<ExitSignal>) -> Self {
+        ParentMonitor {
+            interval,
+            exit: Arc::downgrade(exit),
+        }
+    }
+
+    /// Starts checking whether the process with the given ID is alive in a background thread.
+    ///
+    /// The thread stops as soon as the server exits or is dropped.
+    pub fn watch(&self, pid: u32) {
+        let (interval, exit) = (self.interval, self.exit.clone());
+        let result = thread::Builder::new()
+            .name("parent-monitor".into())
+            .spawn(move || loop {
+                thread::sleep(interval);
+                let exit = match exit.upgrade() {
+                    Some(exit) if !exit.has_exited() => exit,
+                    _ => break,
+                };
+
+                if !is_alive(pid) {
+                    info!("parent process {} has died, shutting down", pid);
+                    exit.exit();
+                    break;
+                }
+            });
+
+        if let Err(e) = result {
+            error!("failed to start monitoring parent process {}: {}", pid, e);
+        }
+    }
+}
+
+/// Returns whether the process with the given ID is still running.
+///
+/// If this cannot be determined, the process is assumed to be alive.
+#[cfg(unix)]
+fn is_alive(pid: u32) -> bool {
+    use std::path::Path;
+    use std::process::{Command, Stdio};
+
+    if Path::new("/proc/self").exists() {
+        return Path::new("/proc").join(pid.to_string()).exists();
+    }
+
+    Command::new("kill")
+        .args(["-0", &pid.to_string()])
+        .stderr(Stdio::null())
+        .status()
+        .map(|status| status.success())
+        .unwrap_or(true)
+}
+
+/// Returns whether the process with the given ID is still running.
+///
+/// If this cannot be determined, the process is assumed to be alive.
+#[cfg(windows)]
+fn is_alive(pid: u32) -> bool {
+    use std::process::Command;
+
+    let filter = format!("PID eq {}", pid);
+    Command::new("tasklist")
+        .args(["/FI", &filter, "/FO", "CSV", "/NH"])
+        .output()
+        .map(|output| {
+            let needle = format!(",\"{}\",", pid);
+            String::from_utf8_lossy(&output.stdout).contains(&needle)
+        })
+        .unwrap_or(true)
+}
+
+/// Returns whether the process with the given ID is still running.
+///
+/// Liveness cannot be determined on this platform, so the process is assumed to be alive.
+#[cfg(not(any(unix, windows)))]
+fn is_alive(_: u32) -> bool {
+    true
+}
+
+#[cfg(all(test, unix))]
+mod tests {
+    use std::process::{self, Command};
+
+    use super::*;
+
+    #[test]
+    fn detects_dead_process() {
+        assert!(is_alive(process::id()));
+
+        let mut child = Command::new("true").spawn().unwrap();
+        let pid = child.id();
+        child.wait().unwrap();
+        assert!(!is_alive(pid));
+    }
+}
@@ -0,0 +1,313 @@
+//! Encoding of semantic tokens against a legend.
+
+use std::fmt::{self, Display, Formatter};
+
+use lsp_types::{SemanticToken, SemanticTokenModifier, SemanticTokenType, SemanticTokensLegend};
+
+/// Maximum number of modifiers which fit into the bitset of an encoded token.
+const MAX_MODIFIERS: usize = 32;
+
+/// A semantic token at an absolute position in a text document.
+///
+/// Positions are expressed in the position encoding negotiated with the client, just like any
+/// other `Position`.
+#[derive(Clone, Debug, Eq, Hash, PartialEq)]
+pub struct AbsoluteToken {
+    /// Zero-based line of the start of the token.
+    pub line: u32,
+    /// Zero-based character offset of the start of the token within its line.
+    pub start: u32,
+    /// Length of the token in characters.
+    pub length: u32,
+    /// Type of the token.
+    pub token_type: SemanticTokenType,
+    /// Modifiers applying to the token, in no particular order.
+    pub token_modifiers: Vec<SemanticTokenModifier>,
+}
+
+/// Legend of semantic token types and modifiers, which encodes tokens for sending to the client.
+///
+/// The legend is advertised to the client in the `semanticTokensProvider` server capability via
+/// [`legend`](#method.legend). Encoded tokens refer to their type by its index into the legend
+/// and to their modifiers by a bitset of their indices, with positions relative to the previous
+/// token. Hence the same `TokenLegend` must be used for both.
+///
+/// # Example
+///
+/// ```rust
+/// # use tower_lsp::lsp_types::{SemanticTokenModifier, SemanticTokenType};
+/// # use tower_lsp::{AbsoluteToken, TokenLegend};
+/// let legend = TokenLegend::new()
+///     .token_type(SemanticTokenType::FUNCTION)
+///     .token_type(SemanticTokenType::VARIABLE)
+///     .token_modifier(SemanticTokenModifier::DECLARATION);
+///
+/// let data = legend.encode(vec![AbsoluteToken {
+///     line: 2,
+///     start: 4,
+///     length: 3,
+///     token_type: SemanticTokenType::VARIABLE,
+///     token_modifiers: vec![SemanticTokenModifier::DECLARATION],
+/// }]);
+/// assert_eq!(data.unwrap()[0].token_type, 1);
+/// ```
+#[derive(Clone, Debug, Default, Eq, PartialEq)]
+pub struct TokenLegend {
+    token_types: Vec<SemanticTokenType>,
+    token_modifiers: Vec<SemanticTokenModifier>,
+}
+
+impl TokenLegend {
+    /// Creates a new, empty legend.
+    pub fn new() -> Self {
+        TokenLegend::default()
+    }
+
+    /// Appends a token type to the legend, unless it is already part of it.
+    pub fn token_type(mut self, token_type: SemanticTokenType) -> Self {
+        if !self.token_types.contains(&token_type) {
+            self.token_types.push(token_type);
+        }
+        self
+    }
+
+    /// Appends a token modifier to the legend, unless it is already part of it.
+    ///
+    /// # Panics
+    ///
+    /// Panics if the legend already holds 32 modifiers, since encoded tokens cannot refer to any
+    /// further ones.
+    pub fn token_modifier(mut self, modifier: SemanticTokenModifier) -> Self {
+        if !self.token_modifiers.contains(&modifier) {
+            assert!(
+                self.token_modifiers.len() < MAX_MODIFIERS,
+                "semantic token legend cannot hold more than 32 modifiers"
+            );
+            self.token_modifiers.push(modifier);
+        }
+        self
+    }
+
+    /// Returns the legend to advertise in the server capabilities.
+    pub fn legend(&self) -> SemanticTokensLegend {
+        SemanticTokensLegend {
+            token_types: self.token_types.clone(),
+            token_modifiers: self.token_modifiers.clone(),
+        }
+    }
+
+    /// Encodes tokens into the relative format expected by the client.
+    ///
+    /// The tokens may be given in any order, since they are sorted by position before encoding.
+    ///
+    /// Returns an error if a token refers to a type or modifier missing from the legend.
+    pub fn encode(
+        &self,
+        mut tokens: Vec<AbsoluteToken>,
+    ) -> Result<Vec<SemanticToken>, UnknownToken> {
+        tokens.sort_by_key(|token| (token.line, token.start));
+
+        let (mut line, mut start) = (0, 0);
+        let mut data = Vec::with_capacity(tokens.len());
+        for token in tokens {
+            let token_type = self
+                .token_types
+                .iter()
+                .position(|t| *t == token.token_type)
+                .ok_or_else(|| UnknownToken::Type(token.token_type.clone()))?;
+
+            let mut token_modifiers_bitset = 0;
+            for modifier in &token.token_modifiers {
+                let index = self
+                    .token_modifiers
+                    .iter()
+                    .position(|m| m == modifier)
+                    .ok_or_else(|| UnknownToken::Modifier(modifier.clone()))?;
+                token_modifiers_bitset |= 1 << index;
+            }
+
+            let delta_line = token.line - line;
+            let delta_start = if delta_line == 0 {
+                token.start - start
+            } else {
+                token.start
+            };
+            line = token.line;
+            start = token.start;
+
+            data.push(SemanticToken {
+                delta_line,
+                delta_start,
+                length: token.length,
+                token_type: token_type as u32,
+                token_modifiers_bitset,
+            });
+        }
+
+        Ok(data)
+    }
+
+    /// Decodes tokens in the relative format back into absolute tokens.
+    ///
+    /// This is the inverse of [`encode`](#method.encode), e.g. for inspecting previously sent
+    /// tokens when computing a delta. Modifiers are returned in legend order.
+    ///
+    /// Returns `None` if a token refers to a type or modifier index missing from the legend.
+    pub fn decode(&self, data: &[SemanticToken]) -> Option<Vec<AbsoluteToken>> {
+        let (mut line, mut start) = (0, 0);
+        let mut tokens = Vec::with_capacity(data.len());
+        for token in data {
+            if token.delta_line > 0 {
+                line += token.delta_line;
+                start = token.delta_start;
+            } else {
+                start += token.delta_start;
+            }
+
+            let token_type = self.token_types.get(token.token_type as usize)?.clone();
+            let bitset = u64::from(token.token_modifiers_bitset);
+            if bitset >> self.token_modifiers.len() != 0 {
+                return None;
+            }
+
+            let token_modifiers = self
+                .token_modifiers
+                .iter()
+                .enumerate()
+                .filter(|(index, _)| bitset & (1 << index) != 0)
+                .map(|(_, modifier)| modifier.clone())
+                .collect();
+
+            tokens.push(AbsoluteToken {
+                line,
+                start,
+                length: token.length,
+                token_type,
+                token_modifiers,
+            });
+        }
+
+        Some(tokens)
+    }
+}
+
+/// Error returned when encoding a token whose type or modifier is missing from the legend.
+#[derive(Clone, Debug, Eq, PartialEq)]
+pub enum UnknownToken {
+    /// The token type is missing from the legend.
+    Type(SemanticTokenType),
+    /// The token modifier is missing from the legend.
+    Modifier(SemanticTokenModifier),
+}
+
+impl Display for UnknownToken {
+    fn fmt(&self, f: &mut Formatter) -> fmt::Result {
+        match self {
+            UnknownToken::Type(t) => write!(f, "unknown semantic token type `{}`", t.as_str()),
+            UnknownToken::Modifier(m) => {
+                write!(f, "unknown semantic token modifier `{}`", m.as_str())
+            }
+        }
+    }
+}
+
+impl std::error::Error for UnknownToken {}
+
+#[cfg(test)]
+mod tests {
+    use lsp_types::SemanticTokens;
+
+    use super::*;
+
+    fn legend() -> TokenLegend {
+        TokenLegend::new()
+            .token_type(SemanticTokenType::FUNCTION)
+            .token_type(SemanticTokenType::VARIABLE)
+            .token_type(SemanticTokenType::FUNCTION)
+            .token_modifier(SemanticTokenModifier::DECLARATION)
+            .token_modifier(SemanticTokenModifier::READONLY)
+    }
+
+    fn token(line: u32, start: u32, token_type: SemanticTokenType) -> AbsoluteToken {
+        AbsoluteToken {
+            line,
+            start,
+            length: 3,
+            token_type,
+            token_modifiers: Vec::new(),
+        }
+    }
+
+    #[test]
+    fn builds_legend() {
+        let legend = legend().legend();
+        assert_eq!(
+            legend.token_types,
+            vec![SemanticTokenType::FUNCTION, SemanticTokenType::VARIABLE]
+        );
+        assert_eq!(
+            legend.token_modifiers,
+            vec![
+                SemanticTokenModifier::DECLARATION,
+                SemanticTokenModifier::READONLY
+            ]
+        );
+    }
+
+    #[test]
+    fn round_trips_tokens() {
+        let legend = legend();
+        let mut first = token(2, 4, SemanticTokenType::VARIABLE);
+        first.token_modifiers = vec![
+            SemanticTokenModifier::READONLY,
+            SemanticTokenModifier::DECLARATION,
+        ];
+        let tokens = vec![
+            token(5, 1, SemanticTokenType::FUNCTION),
+            first,
+            token(2, 10, SemanticTokenType::FUNCTION),
+        ];
+
+        let data = legend.encode(tokens.clone()).unwrap();
+        let encoded = SemanticTokens {
+            result_id: None,
+            data: data.clone(),
+        };
+        assert_eq!(
+            serde_json::to_value(&encoded).unwrap(),
+            serde_json::json!({ "data": [2, 4, 3, 1, 3, 0, 6, 3, 0, 0, 3, 1, 3, 0, 0] })
+        );
+
+        let mut decoded = legend.decode(&data).unwrap();
+        decoded[0].token_modifiers.reverse();
+        let mut expected = tokens;
+        expected.sort_by_key(|token| (token.line, token.start));
+        assert_eq!(decoded, expected);
+    }
+
+    #[test]
+    fn rejects_unknown_tokens() {
+        let legend = legend();
+        let unknown = token(0, 0, SemanticTokenType::MACRO);
+        assert_eq!(
+            legend.encode(vec![unknown]),
+            Err(UnknownToken::Type(SemanticTokenType::MACRO))
+        );
+
+        let mut unknown = token(0, 0, SemanticTokenType::FUNCTION);
+        unknown.token_modifiers = vec![SemanticTokenModifier::STATIC];
+        assert_eq!(
+            legend.encode(vec![unknown]),
+            Err(UnknownToken::Modifier(SemanticTokenModifier::STATIC))
+        );
+
+        let data = [SemanticToken {
+            delta_line: 0,
+            delta_start: 0,
+            length: 1,
+            token_type: 0,
+            token_modifiers_bitset: 0b100,
+        }];
+        assert_eq!(legend.decode(&data), None);
+    }
+}
@@ -1,23 +1,35 @@
 //! Service abstraction for language servers.
 
+use std::any::Any;
+use std::collections::HashSet;
 use std::error::Error;
-use std::fmt::{Display, Formatter, Result as FmtResult};
+use std::fmt::{self, Debug, Display, Formatter, Result as FmtResult};
+use std::panic::{self, AssertUnwindSafe};
 use std::sync::atomic::{AtomicBool, Ordering};
 use std::sync::{Arc, Mutex};
+use std::time::Duration;
 
-use futures::future::{self, Future, Shared, SharedError, SharedItem};
+use futures::future::{self, Either, Future, Shared, SharedError, SharedItem};
 use futures::sync::oneshot::{self, Canceled};
-use futures::{Async, Poll};
-use jsonrpc_core::IoHandler;
-use log::{debug, info, trace};
+use futures::{Async, IntoFuture, Poll};
+use jsonrpc_core::types::{Call, Id, MethodCall, Output, Params, Version};
+use jsonrpc_core::{BoxFuture, Error as RpcError, ErrorCode, IoHandler};
+use log::{error, info, trace};
 use lsp_types::notification::{Exit, Notification};
+use serde_json::Value;
 use tower_service::Service;
 
-use super::delegate::{Delegate, LanguageServerCore, MessageStream};
+use super::delegate::{
+    not_initialized_error, shut_down_error, Command, Delegate, Extensions, MessageStream, Printer,
+    Validator,
+};
 use super::message::Incoming;
+use super::process::ParentMonitor;
 use super::LanguageServer;
 
-/// Error that occurs when attempting to call the language server after it has already exited.
+/// Error that occurs when sending a request to the language server after it has already exited.
+///
+/// Notifications received after exit are dropped silently instead.
 #[derive(Clone, Debug, PartialEq)]
 pub struct ExitedError;
 
@@ -60,19 +72,158 @@ impl Future for ExitReceiver {
     }
 }
 
+type FallbackFn = dyn Fn(&str, Params) -> BoxFuture<Value> + Send + Sync;
+type PanicFn = dyn Fn(&str, &str) -> RpcError + Send + Sync;
+type RewriteFn = dyn Fn(Value) -> Value + Send + Sync;
+
+/// Stage of the lifecycle of a language server, as driven by the client.
+#[derive(Clone, Copy, Debug, Eq, Hash, PartialEq)]
+pub enum ServerState {
+    /// The `initialize` request has not been answered successfully yet.
+    Uninitialized,
+    /// The server has been initialized and processes requests normally.
+    Initialized,
+    /// The `shutdown` request has been received, and the server awaits the `exit` notification.
+    ShuttingDown,
+    /// The `exit` notification has been received, or the server has otherwise exited.
+    Exited,
+}
+
+/// Shared handle for querying the lifecycle state of an `LspService`.
+///
+/// This is useful for code outside of the service, e.g. a health check or middleware wrapping the
+/// service, which needs to make decisions based on the state of the server. The handle stays valid
+/// after the service has been dropped, reporting its last state.
+#[derive(Clone, Debug)]
+pub struct Lifecycle {
+    initialized: Arc<AtomicBool>,
+    shut_down: Arc<AtomicBool>,
+    stopped: Arc<AtomicBool>,
+}
+
+impl Lifecycle {
+    /// Returns the current lifecycle state of the server.
+    pub fn state(&self) -> ServerState {
+        if self.stopped.load(Ordering::SeqCst) {
+            ServerState::Exited
+        } else if self.shut_down.load(Ordering::SeqCst) {
+            ServerState::ShuttingDown
+        } else if self.initialized.load(Ordering::SeqCst) {
+            ServerState::Initialized
+        } else {
+            ServerState::Uninitialized
+        }
+    }
+}
+
+/// Resolves the `ExitReceiver` and stops the `LspService` once the language server exits.
+#[derive(Debug)]
+pub(crate) struct ExitSignal {
+    tx: Mutex<Option<oneshot::Sender<()>>>,
+    stopped: Arc<AtomicBool>,
+}
+
+impl ExitSignal {
+    /// Exits the language server, returning `false` if it had already exited before.
+    pub fn exit(&self) -> bool {
+        match self.tx.lock().unwrap_or_else(|tx| tx.into_inner()).take() {
+            Some(tx) => {
+                self.stopped.store(true, Ordering::SeqCst);
+                let _ = tx.send(());
+                true
+            }
+            None => false,
+        }
+    }
+
+    /// Returns whether the language server has already exited.
+    pub fn has_exited(&self) -> bool {
+        self.stopped.load(Ordering::SeqCst)
+    }
+}
+
+/// Handler invoked for incoming messages whose method has not been registered.
+#[derive(Clone)]
+struct Fallback(Arc<FallbackFn>);
+
+impl Fallback {
+    /// Forwards the request to the handler, answering it with the result.
+    fn forward<E>(&self, call: MethodCall) -> impl Future<Item = Option<String>, Error = E> {
+        let (id, version) = (call.id, call.jsonrpc);
+        (self.0)(&call.method, call.params).then(move |result| {
+            let output = Output::from(result, id, version);
+            Ok(Some(serde_json::to_string(&output).unwrap()))
+        })
+    }
+}
+
+impl Debug for Fallback {
+    fn fmt(&self, f: &mut Formatter) -> fmt::Result {
+        f.debug_tuple(stringify!(Fallback)).field(&"_").finish()
+    }
+}
+
+/// Hook converting the panic of a request handler into the error sent back to the client.
+struct PanicHandler(Box<PanicFn>);
+
+impl Debug for PanicHandler {
+    fn fmt(&self, f: &mut Formatter) -> fmt::Result {
+        f.debug_tuple(stringify!(PanicHandler)).field(&"_").finish()
+    }
+}
+
+/// Hook transforming incoming requests and notifications before they are dispatched.
+struct Rewriter(Box<RewriteFn>);
+
+impl Debug for Rewriter {
+    fn fmt(&self, f: &mut Formatter) -> fmt::Result {
+        f.debug_tuple(stringify!(Rewriter)).field(&"_").finish()
+    }
+}
+
+impl Rewriter {
+    /// Applies the hook to requests and notifications, leaving all other messages untouched.
+    ///
+    /// Messages which are no longer valid after being rewritten are treated as invalid requests.
+    fn rewrite(&self, message: Incoming) -> Incoming {
+        match message {
+            Incoming::Request(_) | Incoming::Notification(_) => {
+                let value = serde_json::to_value(&message).expect("messages are serializable");
+                let value = (self.0)(value);
+                serde_json::from_value(value.clone())
+                    .unwrap_or_else(|_| Incoming::Invalid(value.to_string()))
+            }
+            other => other,
+        }
+    }
+}
+
 /// Service abstraction for the Language Server Protocol.
 ///
 /// This service takes a JSON-RPC request as input and produces a JSON-RPC response as output. If
-/// the incoming request is a notification, then the corresponding response string will be empty.
+/// the incoming request is a notification, then no response will be produced.
 ///
 /// This implements [`tower_service::Service`] in order to remain independent from the underlying
 /// transport and to facilitate further abstraction with middleware.
 ///
+/// Panics in handlers are caught and logged, turning into error responses for requests, so that
+/// a single faulty handler does not take down the whole server.
+///
+/// All session state is owned by the service and its `Printer`s, none of it is process-global.
+/// Once a session has exited, a host process may therefore start a fresh one by creating a new
+/// `LspService`, e.g. when embedding several consecutive sessions or in test harnesses.
+///
 /// [`tower_service::Service`]: https://docs.rs/tower-service/0.2.0/tower_service/trait.Service.html
 #[derive(Debug)]
 pub struct LspService {
     handler: IoHandler,
+    methods: HashSet<String>,
+    printer: Printer,
+    fallback: Option<Fallback>,
+    rewriter: Option<Rewriter>,
+    panic_handler: Option<Arc<PanicHandler>>,
     exit_rx: ExitReceiver,
+    lifecycle: Lifecycle,
     stopped: Arc<AtomicBool>,
 }
 
@@ -83,7 +234,7 @@ impl LspService {
     where
         T: LanguageServer,
     {
-        Self::with_handler(server, IoHandler::new())
+        Self::build(server).finish()
     }
 
     /// Creates a new `LspService` with the given server backend a custom `IoHandler`.
@@ -92,44 +243,460 @@ impl LspService {
         T: LanguageServer,
         U: Into<IoHandler>,
     {
-        let (delegate, messages) = Delegate::new(server);
+        Self::build(server).handler(handler).finish()
+    }
+
+    /// Starts building a new `LspService` with the given server backend.
+    ///
+    /// This allows for registering additional methods and configuring the service in one place
+    /// before creating it with [`LspServiceBuilder::finish`].
+    ///
+    /// [`LspServiceBuilder::finish`]: ./struct.LspServiceBuilder.html#method.finish
+    pub fn build<T>(server: T) -> LspServiceBuilder<T>
+    where
+        T: LanguageServer,
+    {
+        LspServiceBuilder {
+            server,
+            handler: IoHandler::new(),
+            fallback: None,
+            rewriter: None,
+            panic_handler: None,
+            validator: None,
+            commands: Vec::new(),
+            parent_monitor_interval: None,
+            buffer_until_initialized: false,
+            compact_responses: false,
+            max_queued_messages: None,
+            request_id_prefix: None,
+            extensions: Extensions::new(),
+        }
+    }
+
+    /// Returns a close handle which signals when the [`exit`] notification has been received.
+    ///
+    /// [`exit`]: https://microsoft.github.io/language-server-protocol/specification#exit
+    pub fn close_handle(&self) -> ExitReceiver {
+        self.exit_rx.clone()
+    }
+
+    /// Returns the current lifecycle state of the server.
+    pub fn state(&self) -> ServerState {
+        self.lifecycle.state()
+    }
+
+    /// Returns a shared handle for querying the lifecycle state of the server from elsewhere.
+    pub fn lifecycle(&self) -> Lifecycle {
+        self.lifecycle.clone()
+    }
+
+    pub(crate) fn printer(&self) -> Printer {
+        self.printer.clone()
+    }
+
+    /// Dispatches the request to the fallback handler, if one is registered and no other handler
+    /// exists for its method or the backend does not implement it.
+    fn dispatch(&self, request: Incoming) -> <Self as Service<Incoming>>::Future {
+        if let Incoming::Response(r) = request {
+            self.printer.handle_response(r);
+            Box::new(future::ok(None))
+        } else if let Some(response) = self.call_fallback(&request) {
+            response
+        } else if let Some(response) = invalid_request(&request) {
+            Box::new(future::ok(Some(response)))
+        } else if let (Some(fallback), Incoming::Request(call)) = (&self.fallback, &request) {
+            // Standard methods the backend does not implement are answered with `MethodNotFound`
+            // by their default handlers, so such requests are forwarded as well.
+            let (fallback, call) = (fallback.clone(), call.clone());
+            Box::new(
+                self.handler
+                    .handle_call(Call::MethodCall(call.clone()))
+                    .map_err(|_| unreachable!())
+                    .and_then(move |output| match output {
+                        Some(Output::Failure(ref failure))
+                            if failure.error.code == ErrorCode::MethodNotFound =>
+                        {
+                            trace!("forwarding declined request to fallback: {}", call.method);
+                            Either::A(fallback.forward(call))
+                        }
+                        output => {
+                            let response = output.map(|o| serde_json::to_string(&o).unwrap());
+                            Either::B(future::ok(response))
+                        }
+                    }),
+            )
+        } else {
+            Box::new(
+                self.handler
+                    .handle_request(&request.to_string())
+                    .map_err(|_| unreachable!())
+                    .map(move |result| {
+                        if result.is_none() {
+                            trace!("request produced no response: {}", request);
+                        }
+
+                        result
+                    }),
+            )
+        }
+    }
+
+    fn call_fallback(&self, request: &Incoming) -> Option<<Self as Service<Incoming>>::Future> {
+        let fallback = self.fallback.as_ref()?;
+        let method = match *request {
+            Incoming::Request(ref call) => &call.method,
+            Incoming::Notification(ref n) => &n.method,
+            _ => return None,
+        };
+
+        if self.methods.contains(method) {
+            return None;
+        }
+
+        // Forwarded messages are subject to the same lifecycle rules as those handled by the
+        // language server itself.
+        let rejection = match self.lifecycle.state() {
+            ServerState::Initialized => None,
+            ServerState::Uninitialized => Some(not_initialized_error()),
+            ServerState::ShuttingDown | ServerState::Exited => Some(shut_down_error()),
+        };
+
+        trace!("forwarding unhandled message to fallback: {}", request);
+        match request.clone() {
+            Incoming::Request(call) => {
+                if let Some(error) = rejection {
+                    let output = Output::from(Err(error), call.id, call.jsonrpc);
+                    return Some(Box::new(future::ok(Some(
+                        serde_json::to_string(&output).unwrap(),
+                    ))));
+                }
+
+                Some(Box::new(fallback.forward(call)))
+            }
+            Incoming::Notification(n) => {
+                if rejection.is_some() {
+                    trace!("dropping `{}` notification outside of session", n.method);
+                    return Some(Box::new(future::ok(None)));
+                }
+
+                let method = n.method.clone();
+                let response = (fallback.0)(&n.method, n.params).then(move |result| {
+                    if let Err(e) = result {
+                        error!("fallback for notification {} failed: {}", method, e);
+                    }
+
+                    Ok(None)
+                });
+                Some(Box::new(response))
+            }
+            _ => unreachable!(),
+        }
+    }
+}
+
+/// Builder for configuring and creating an [`LspService`].
+///
+/// Created by [`LspService::build`].
+///
+/// Middleware such as timeouts or concurrency limits are not configured here. Instead, wrap the
+/// finished `LspService` in the desired Tower layers before passing it to `Server::serve`.
+///
+/// [`LspService`]: ./struct.LspService.html
+/// [`LspService::build`]: ./struct.LspService.html#method.build
+#[derive(Debug)]
+pub struct LspServiceBuilder<T> {
+    server: T,
+    handler: IoHandler,
+    fallback: Option<Fallback>,
+    rewriter: Option<Rewriter>,
+    panic_handler: Option<PanicHandler>,
+    validator: Option<Validator>,
+    commands: Vec<(String, Command)>,
+    parent_monitor_interval: Option<Duration>,
+    buffer_until_initialized: bool,
+    compact_responses: bool,
+    max_queued_messages: Option<usize>,
+    request_id_prefix: Option<String>,
+    extensions: Extensions,
+}
+
+impl<T: LanguageServer> LspServiceBuilder<T> {
+    /// Merges in all the methods of the given `IoHandler`.
+    ///
+    /// Methods defined by the Language Server Protocol always take precedence over methods of the
+    /// same name registered here.
+    pub fn handler<U: Into<IoHandler>>(mut self, handler: U) -> Self {
+        self.handler.extend_with(handler.into());
+        self
+    }
+
+    /// Registers a custom JSON-RPC request handler with the given method name.
+    ///
+    /// This is useful for implementing non-standard extensions to the protocol, as well as standard
+    /// methods which are too recent to be modeled by `lsp_types` yet, such as
+    /// `workspace/textDocumentContent` for serving the content of virtual documents.
+    pub fn custom_method<F, I>(mut self, name: &str, method: F) -> Self
+    where
+        F: Fn(Params) -> I + Send + Sync + 'static,
+        I: IntoFuture<Item = Value, Error = RpcError>,
+        I::Future: Send + 'static,
+    {
+        self.handler
+            .add_method(name, move |params| method(params).into_future());
+        self
+    }
+
+    /// Registers a custom JSON-RPC notification handler with the given method name.
+    ///
+    /// This is useful for implementing non-standard extensions to the protocol.
+    pub fn custom_notification<F>(mut self, name: &str, notification: F) -> Self
+    where
+        F: Fn(Params) + Send + Sync + 'static,
+    {
+        self.handler.add_notification(name, notification);
+        self
+    }
+
+    /// Registers a handler for all incoming requests and notifications with no other handler.
+    ///
+    /// The handler receives the method name and raw parameters of the message. It is only invoked
+    /// for methods not handled by the language server backend or registered on this builder, which
+    /// makes it suitable for forwarding messages to another server. Results returned for
+    /// notifications are discarded.
+    ///
+    /// Requests for standard methods which the backend leaves at their default implementation,
+    /// i.e. which it answers with a `MethodNotFound` error, are forwarded to the handler as well.
+    ///
+    /// Just like methods of the backend, requests received before the server has been initialized
+    /// or after it has been shut down are rejected with an error rather than being forwarded, and
+    /// such notifications are dropped.
+    pub fn fallback<F, I>(mut self, fallback: F) -> Self
+    where
+        F: Fn(&str, Params) -> I + Send + Sync + 'static,
+        I: IntoFuture<Item = Value, Error = RpcError>,
+        I::Future: Send + 'static,
+    {
+        let fallback = move |method: &str, params| -> BoxFuture<Value> {
+            Box::new(fallback(method, params).into_future())
+        };
+        self.fallback = Some(Fallback(Arc::new(fallback)));
+        self
+    }
+
+    /// Registers a hook which transforms every incoming request and notification before it is
+    /// dispatched to its handler.
+    ///
+    /// The hook receives the entire decoded message as JSON and returns the message to dispatch in
+    /// its place, e.g. to remap a deprecated method name or to normalize URIs for clients speaking
+    /// a slightly different dialect of the protocol. Responses from the client are not passed to
+    /// the hook. Messages which are no longer valid JSON-RPC after being rewritten are answered
+    /// with an `Invalid Request` error. Should the hook panic, the message is answered just like
+    /// one whose handler panicked.
+    pub fn rewriter<F>(mut self, rewriter: F) -> Self
+    where
+        F: Fn(Value) -> Value + Send + Sync + 'static,
+    {
+        self.rewriter = Some(Rewriter(Box::new(rewriter)));
+        self
+    }
+
+    /// Registers a hook which converts the panic of a request handler into the error response sent
+    /// back to the client.
+    ///
+    /// The hook receives the method name of the request and the panic message. Panics are always
+    /// caught and logged, so a single faulty handler cannot take down the whole server. Without
+    /// this hook, requests are answered with a generic `InternalError`, while panicking
+    /// notifications are always dropped.
+    pub fn panic_handler<F>(mut self, handler: F) -> Self
+    where
+        F: Fn(&str, &str) -> RpcError + Send + Sync + 'static,
+    {
+        self.panic_handler = Some(PanicHandler(Box::new(handler)));
+        self
+    }
+
+    /// Registers a hook which validates the raw parameters of every request and notification
+    /// before it is handled by the language server backend.
+    ///
+    /// The hook receives the method name and parameters, and is only invoked once the parameters
+    /// have been successfully deserialized. Returning an error skips the handler entirely: requests
+    /// are answered with the returned error, while notifications are dropped and the error is
+    /// logged. Custom methods and the fallback handler are not validated.
+    pub fn validator<F>(mut self, validator: F) -> Self
+    where
+        F: Fn(&str, &Value) -> Result<(), RpcError> + Send + Sync + 'static,
+    {
+        self.validator = Some(Validator::new(validator));
+        self
+    }
+
+    /// Registers a handler for the command with the given name, as executed by the client with the
+    /// [`workspace/executeCommand`] request.
+    ///
+    /// The handler receives the arguments of the command, and its result is sent back as the
+    /// response. Commands without a registered handler are passed on to
+    /// `LanguageServer::execute_command()`. Commands must still be announced by the server in the
+    /// `executeCommandProvider` capability returned from `initialize`.
+    ///
+    /// [`workspace/executeCommand`]: https://microsoft.github.io/language-server-protocol/specification#workspace_executeCommand
+    pub fn command<F, I>(mut self, name: &str, command: F) -> Self
+    where
+        F: Fn(&Printer, Vec<Value>) -> I + Send + Sync + 'static,
+        I: IntoFuture<Item = Option<Value>, Error = RpcError>,
+        I::Future: Send + 'static,
+    {
+        let command = move |printer: &Printer, arguments| -> BoxFuture<Option<Value>> {
+            Box::new(command(printer, arguments).into_future())
+        };
+        self.commands.push((name.to_owned(), Command::new(command)));
+        self
+    }
+
+    /// Exits the language server if the client process dies, checking at the given interval.
+    ///
+    /// The client process is identified by the `processId` sent in the [`initialize`] request.
+    /// Monitoring is disabled if the client sends a `null` process ID. Process liveness can only be
+    /// determined on Unix and Windows; on other platforms, the client is assumed to be alive.
+    ///
+    /// [`initialize`]: https://microsoft.github.io/language-server-protocol/specification#initialize
+    pub fn monitor_parent_process(mut self, interval: Duration) -> Self {
+        self.parent_monitor_interval = Some(interval);
+        self
+    }
+
+    /// Holds back all messages sent through the `Printer` until the [`initialized`] notification
+    /// has been received from the client, then delivers them in order.
+    ///
+    /// Some clients ignore or reject messages sent before they have finished initializing. With
+    /// this option, diagnostics published during `initialize` are also delivered this way instead
+    /// of being suppressed.
+    ///
+    /// [`initialized`]: https://microsoft.github.io/language-server-protocol/specification#initialized
+    pub fn buffer_until_initialized(mut self) -> Self {
+        self.buffer_until_initialized = true;
+        self
+    }
+
+    /// Omits object fields which are `null` from the results of requests, shrinking verbose
+    /// responses such as large completion lists.
+    ///
+    /// Most such fields stem from `Option::None` values which the protocol allows to be left out
+    /// entirely. A result of `null` itself is still sent, as are `null` elements of arrays and
+    /// fields required to be present even when `null`, such as the `version` of the text document
+    /// in a workspace edit. Opaque values, e.g. the `data` of a code action or the result of a
+    /// command, are sent verbatim. Messages sent through the `Printer` are left as they are.
+    pub fn compact_responses(mut self) -> Self {
+        self.compact_responses = true;
+        self
+    }
+
+    /// Limits the number of messages sent through the `Printer` which may be queued for the client
+    /// at once, e.g. while the client is slow to read them.
+    ///
+    /// Sending never blocks, since the `Printer` is used from within request handlers: once the
+    /// limit is reached, further messages are dropped and logged as a warning until the queue has
+    /// been drained below the limit. Messages held back by [`buffer_until_initialized`] count
+    /// towards the limit as well. Requests dropped this way, e.g. `workspace/configuration`, fail
+    /// with an `InternalError`. Defaults to no limit, trading memory for never dropping messages.
+    ///
+    /// [`buffer_until_initialized`]: #method.buffer_until_initialized
+    pub fn max_queued_messages(mut self, max: usize) -> Self {
+        self.max_queued_messages = Some(max);
+        self
+    }
+
+    /// Sends requests to the client with string IDs starting with `prefix`, e.g. `"server-0"`,
+    /// instead of plain numeric IDs.
+    ///
+    /// This is useful for clients which multiplex several servers over a single connection, or
+    /// which otherwise require string IDs to correlate responses. Either way, IDs are allocated
+    /// from a single atomic counter, so they never collide between concurrently sent requests.
+    pub fn request_id_prefix(mut self, prefix: &str) -> Self {
+        self.request_id_prefix = Some(prefix.to_owned());
+        self
+    }
 
-        let mut handler = handler.into();
-        handler.extend_with(delegate.to_delegate());
+    /// Registers shared state, such as a database handle or a parser cache, for access from
+    /// handlers through [`Printer::extension`].
+    ///
+    /// At most one value of each type is registered, so registering another value of the same type
+    /// replaces the previous one. Wrap values in a newtype to register several of the same type.
+    ///
+    /// [`Printer::extension`]: ./struct.Printer.html#method.extension
+    pub fn extension<E: Send + Sync + 'static>(mut self, value: E) -> Self {
+        self.extensions.insert(value);
+        self
+    }
 
+    /// Creates the `LspService`, also returning a stream of notifications from the server back to
+    /// the client.
+    pub fn finish(self) -> (LspService, MessageStream) {
         let (tx, rx) = oneshot::channel();
-        let exit_tx = Mutex::new(Some(tx));
         let exit_rx = ExitReceiver(rx.shared());
-
         let stopped = Arc::new(AtomicBool::new(false));
-        let stopped_arc = stopped.clone();
+        let exit = Arc::new(ExitSignal {
+            tx: Mutex::new(Some(tx)),
+            stopped: stopped.clone(),
+        });
+
+        let (mut delegate, messages) = Delegate::new(self.server);
+        if let Some(validator) = self.validator {
+            delegate.set_validator(validator);
+        }
+        for (name, command) in self.commands {
+            delegate.add_command(name, command);
+        }
+        if let Some(interval) = self.parent_monitor_interval {
+            delegate.set_parent_monitor(ParentMonitor::new(interval, &exit));
+        }
+        if self.buffer_until_initialized {
+            delegate.buffer_until_initialized();
+        }
+        if self.compact_responses {
+            delegate.compact_results();
+        }
+        if let Some(max) = self.max_queued_messages {
+            delegate.set_max_queued_messages(max);
+        }
+        if let Some(prefix) = self.request_id_prefix {
+            delegate.set_request_id_prefix(prefix);
+        }
+        delegate.set_extensions(self.extensions);
+
+        let printer = delegate.printer();
+        let (initialized, shut_down) = delegate.lifecycle_flags();
+        let lifecycle = Lifecycle {
+            initialized,
+            shut_down,
+            stopped: stopped.clone(),
+        };
+        let mut handler = self.handler;
+        handler.extend_with(delegate.into_methods());
         handler.add_notification(Exit::METHOD, move |_| {
-            if let Some(tx) = exit_tx.lock().unwrap_or_else(|tx| tx.into_inner()).take() {
+            if exit.exit() {
                 info!("exit notification received, shutting down");
-                stopped_arc.store(true, Ordering::SeqCst);
-                tx.send(()).unwrap();
             }
         });
 
+        let methods = handler.iter().map(|(name, _)| name.clone()).collect();
         let service = LspService {
             handler,
+            methods,
+            printer,
+            fallback: self.fallback,
+            rewriter: self.rewriter,
+            panic_handler: self.panic_handler.map(Arc::new),
             exit_rx,
+            lifecycle,
             stopped,
         };
 
         (service, messages)
     }
-
-    /// Returns a close handle which signals when the [`exit`] notification has been received.
-    ///
-    /// [`exit`]: https://microsoft.github.io/language-server-protocol/specification#exit
-    pub fn close_handle(&self) -> ExitReceiver {
-        self.exit_rx.clone()
-    }
 }
 
 impl Service<Incoming> for LspService {
-    type Response = String;
+    type Response = Option<String>;
     type Error = ExitedError;
     type Future = Box<dyn Future<Item = Self::Response, Error = Self::Error> + Send>;
 
@@ -143,53 +710,119 @@ impl Service<Incoming> for LspService {
 
     fn call(&mut self, request: Incoming) -> Self::Future {
         if self.stopped.load(Ordering::SeqCst) {
-            Box::new(future::err(ExitedError))
-        } else {
-            if let Incoming::Response(r) = request {
-                // FIXME: Currently, we are dropping responses to requests created in `Printer`.
-                // We need some way to route them back to the `Printer`. See this issue for more:
-                //
-                // https://github.com/ebkalderon/tower-lsp/issues/13
-                debug!("dropping client response, as per GitHub issue #13: {:?}", r);
-                Box::new(future::ok(String::new()))
-            } else {
-                Box::new(
-                    self.handler
-                        .handle_request(&request.to_string())
-                        .map_err(|_| unreachable!())
-                        .map(move |result| {
-                            result.unwrap_or_else(|| {
-                                trace!("request produced no response: {}", request);
-                                String::new()
-                            })
-                        }),
-                )
+            // The server is terminating, so trailing notifications sent by the client during
+            // teardown are dropped silently. Requests still fail, since their caller awaits an
+            // outcome.
+            if let Incoming::Notification(ref n) = request {
+                trace!("dropping `{}` notification received after exit", n.method);
+                return Box::new(future::ok(None));
             }
+            return Box::new(future::err(ExitedError));
         }
+
+        let (method, id) = match request {
+            Incoming::Request(ref call) => {
+                (call.method.clone(), Some((call.id.clone(), call.jsonrpc)))
+            }
+            Incoming::Notification(ref n) => (n.method.clone(), None),
+            _ => return self.dispatch(request),
+        };
+
+        // The rewrite hook may panic as well as handlers, which may do so both while creating their
+        // response future and while polling it. Panics are reported against the original message.
+        let panic_handler = self.panic_handler.clone();
+        let dispatched = panic::catch_unwind(AssertUnwindSafe(|| {
+            let request = match self.rewriter {
+                Some(ref rewriter) => rewriter.rewrite(request),
+                None => request,
+            };
+            self.dispatch(request)
+        }));
+        let response = future::result(dispatched)
+            .and_then(|response| AssertUnwindSafe(response).catch_unwind())
+            .then(move |result| match result {
+                Ok(response) => response,
+                Err(payload) => {
+                    let message = panic_message(&*payload);
+                    error!("handler for `{}` panicked: {}", method, message);
+                    Ok(id.map(|(id, version)| {
+                        let error = match panic_handler {
+                            Some(ref handler) => (handler.0)(&method, message),
+                            None => RpcError::internal_error(),
+                        };
+                        let output = Output::from(Err(error), id, version);
+                        serde_json::to_string(&output).unwrap()
+                    }))
+                }
+            });
+
+        Box::new(response)
+    }
+}
+
+/// Extracts the message from the payload of a panic, if any.
+fn panic_message(payload: &(dyn Any + Send)) -> &str {
+    match payload.downcast_ref::<&str>() {
+        Some(message) => message,
+        None => match payload.downcast_ref::<String>() {
+            Some(message) => message,
+            None => "Box<Any>",
+        },
     }
 }
 
+/// Builds an error response for a message which is a JSON object, but not a valid JSON-RPC message.
+///
+/// The `id` of the message is echoed back whenever it is a valid request ID, so that clients can
+/// correlate the error with their request. Only if it cannot be recovered is a `null` ID used.
+/// Messages which are not JSON objects at all, e.g. malformed JSON, are left to `jsonrpc_core`.
+fn invalid_request(message: &Incoming) -> Option<String> {
+    let object = match *message {
+        Incoming::Invalid(ref raw) => match serde_json::from_str(raw) {
+            Ok(Value::Object(object)) => object,
+            _ => return None,
+        },
+        _ => return None,
+    };
+
+    let id = match object.get("id") {
+        Some(Value::Number(n)) => n.as_u64().map_or(Id::Null, Id::Num),
+        Some(Value::String(s)) => Id::Str(s.clone()),
+        _ => Id::Null,
+    };
+
+    trace!("invalid request: {}", message);
+    let output = Output::from(Err(RpcError::invalid_request()), id, Some(Version::V2));
+    Some(serde_json::to_string(&output).unwrap())
+}
+
 #[cfg(test)]
 mod tests {
+    use futures::Stream;
     use jsonrpc_core::{BoxFuture, Result};
+    use lsp_types::request::{
+        GotoDeclarationParams, GotoDeclarationResponse, GotoImplementationParams,
+        GotoImplementationResponse, GotoTypeDefinitionParams, GotoTypeDefinitionResponse,
+    };
     use lsp_types::*;
-    use serde_json::Value;
+    use serde_json::json;
 
     use super::*;
-    use crate::Printer;
+    use crate::{AbsoluteToken, Printer, TokenLegend};
 
     #[derive(Debug, Default)]
     struct Mock;
 
     impl LanguageServer for Mock {
         type ShutdownFuture = BoxFuture<()>;
-        type SymbolFuture = BoxFuture<Option<Vec<SymbolInformation>>>;
+        type SymbolFuture = BoxFuture<Option<WorkspaceSymbolResponse>>;
         type ExecuteFuture = BoxFuture<Option<Value>>;
         type CompletionFuture = BoxFuture<Option<CompletionResponse>>;
         type HighlightFuture = BoxFuture<Option<Vec<DocumentHighlight>>>;
         type HoverFuture = BoxFuture<Option<Hover>>;
 
-        fn initialize(&self, _: &Printer, _: InitializeParams) -> Result<InitializeResult> {
+        fn initialize(&self, p: &Printer, _: InitializeParams) -> Result<InitializeResult> {
+            p.publish_diagnostics("file:///foo.rs".parse().unwrap(), Vec::new(), None);
             Ok(InitializeResult::default())
         }
 
@@ -201,36 +834,1799 @@ mod tests {
             Box::new(future::ok(None))
         }
 
-        fn execute_command(&self, _: &Printer, _: ExecuteCommandParams) -> Self::ExecuteFuture {
-            Box::new(future::ok(None))
+        fn symbol_resolve(&self, mut symbol: WorkspaceSymbol) -> BoxFuture<WorkspaceSymbol> {
+            if let OneOf::Right(WorkspaceLocation { uri }) = symbol.location {
+                let range = Range::new(Position::new(1, 4), Position::new(1, 7));
+                symbol.location = OneOf::Left(Location::new(uri, range));
+            }
+            Box::new(future::ok(symbol))
         }
 
-        fn completion(&self, _: CompletionParams) -> Self::CompletionFuture {
+        fn execute_command(&self, p: &Printer, _: ExecuteCommandParams) -> Self::ExecuteFuture {
+            p.log_trace("executed", Some("details".into()));
             Box::new(future::ok(None))
         }
 
-        fn hover(&self, _: TextDocumentPositionParams) -> Self::HoverFuture {
+        fn completion(&self, params: CompletionParams) -> Self::CompletionFuture {
+            let context = params
+                .context
+                .filter(|context| context.trigger_kind == CompletionTriggerKind::TRIGGER_CHARACTER);
+            let items = context.and_then(|c| c.trigger_character).map(|c| {
+                let item = CompletionItem::new_simple(c, "triggered".into());
+                CompletionResponse::Array(vec![item])
+            });
+            Box::new(future::ok(items))
+        }
+
+        fn hover(&self, _: HoverParams) -> Self::HoverFuture {
             Box::new(future::ok(None))
         }
 
-        fn document_highlight(&self, _: TextDocumentPositionParams) -> Self::HighlightFuture {
+        fn code_action(&self, params: CodeActionParams) -> BoxFuture<Option<CodeActionResponse>> {
+            let action = CodeAction {
+                title: "Remove unused import".into(),
+                data: Some(json!({
+                    "uri": params.text_document.uri,
+                    "range": params.range,
+                    "nested": {"ids": [1, 2, 3], "hint": null},
+                })),
+                ..CodeAction::default()
+            };
+            Box::new(future::ok(Some(vec![action.into()])))
+        }
+
+        fn code_action_resolve(&self, mut action: CodeAction) -> BoxFuture<CodeAction> {
+            let edit = action.data.as_ref().and_then(|data| {
+                let uri = serde_json::from_value(data["uri"].clone()).ok()?;
+                let range = serde_json::from_value(data["range"].clone()).ok()?;
+                let changes = vec![(uri, vec![TextEdit::new(range, String::new())])];
+                Some(WorkspaceEdit::new(changes.into_iter().collect()))
+            });
+            action.edit = edit;
+            Box::new(future::ok(action))
+        }
+
+        fn goto_declaration(
+            &self,
+            params: GotoDeclarationParams,
+        ) -> BoxFuture<Option<GotoDeclarationResponse>> {
+            let TextDocumentPositionParams {
+                text_document,
+                position,
+            } = params.text_document_position_params;
+            let location = Location::new(text_document.uri, Range::new(position, position));
+            Box::new(future::ok(Some(location.into())))
+        }
+
+        fn goto_type_definition(
+            &self,
+            params: GotoTypeDefinitionParams,
+        ) -> BoxFuture<Option<GotoTypeDefinitionResponse>> {
+            let uri = params.text_document_position_params.text_document.uri;
+            let start = Position::new(0, 0);
+            let location = Location::new(uri, Range::new(start, start));
+            Box::new(future::ok(Some(location.into())))
+        }
+
+        fn goto_implementation(
+            &self,
+            params: GotoImplementationParams,
+        ) -> BoxFuture<Option<GotoImplementationResponse>> {
+            let uri = params.text_document_position_params.text_document.uri;
+            let locations = (0..2)
+                .map(|line| {
+                    let start = Position::new(line, 0);
+                    Location::new(uri.clone(), Range::new(start, start))
+                })
+                .collect::<Vec<_>>();
+            Box::new(future::ok(Some(locations.into())))
+        }
+
+        fn document_highlight(&self, _: DocumentHighlightParams) -> Self::HighlightFuture {
             Box::new(future::ok(None))
         }
-    }
 
-    #[test]
-    fn exit_notification() {
-        let (mut service, _) = LspService::new(Mock::default());
+        fn prepare_rename(
+            &self,
+            params: TextDocumentPositionParams,
+        ) -> BoxFuture<Option<PrepareRenameResponse>> {
+            let range = Range::new(params.position, params.position);
+            let response = match params.position.line {
+                0 => PrepareRenameResponse::DefaultBehavior {
+                    default_behavior: true,
+                },
+                2 => PrepareRenameResponse::RangeWithPlaceholder {
+                    range,
+                    placeholder: "foo".into(),
+                },
+                _ => PrepareRenameResponse::Range(range),
+            };
+            Box::new(future::ok(Some(response)))
+        }
 
-        let initialized: Incoming = r#"{"jsonrpc":"2.0","method":"initialized"}"#.parse().unwrap();
-        assert_eq!(service.poll_ready(), Ok(Async::Ready(())));
-        assert_eq!(service.call(initialized.clone()).wait(), Ok("".to_owned()));
+        fn completion_resolve(&self, mut item: CompletionItem) -> BoxFuture<CompletionItem> {
+            item.detail = Some("resolved".into());
+            Box::new(future::ok(item))
+        }
 
-        let exit: Incoming = r#"{"jsonrpc":"2.0","method":"exit"}"#.parse().unwrap();
-        assert_eq!(service.poll_ready(), Ok(Async::Ready(())));
-        assert_eq!(service.call(exit).wait(), Ok("".to_owned()));
+        fn will_rename_files(&self, _: RenameFilesParams) -> BoxFuture<Option<WorkspaceEdit>> {
+            Box::new(future::ok(Some(WorkspaceEdit::default())))
+        }
 
-        assert_eq!(service.poll_ready(), Ok(Async::NotReady));
-        assert_eq!(service.call(initialized).wait(), Err(ExitedError));
+        fn signature_help(&self, params: SignatureHelpParams) -> BoxFuture<Option<SignatureHelp>> {
+            let help = match params.context {
+                Some(ref c) if c.is_retrigger => c.active_signature_help.clone(),
+                _ => Some(SignatureHelp {
+                    signatures: Vec::new(),
+                    active_signature: Some(0),
+                    active_parameter: None,
+                }),
+            };
+            Box::new(future::ok(help))
+        }
+
+        fn did_change_configuration(&self, p: &Printer, params: DidChangeConfigurationParams) {
+            p.log_message(MessageType::INFO, params.settings);
+        }
+
+        fn progress(&self, p: &Printer, params: ProgressParams) {
+            if let ProgressParamsValue::WorkDone(WorkDoneProgress::Report(report)) = params.value {
+                p.log_message(MessageType::INFO, format!("{:?}%", report.percentage));
+            }
+        }
+
+        fn did_save(&self, _: &Printer, _: DidSaveTextDocumentParams) {
+            panic!("failed to save");
+        }
+
+        fn code_lens(&self, _: CodeLensParams) -> BoxFuture<Option<Vec<CodeLens>>> {
+            panic!("failed to compute code lenses");
+        }
+
+        fn code_lens_resolve(&self, _: CodeLens) -> BoxFuture<CodeLens> {
+            Box::new(future::lazy(|| -> Result<CodeLens> {
+                panic!("failed to resolve code lens")
+            }))
+        }
+
+        fn document_link_resolve(&self, mut link: DocumentLink) -> BoxFuture<DocumentLink> {
+            link.target = Some("file:///target.rs".parse().unwrap());
+            Box::new(future::ok(link))
+        }
+
+        fn selection_range(
+            &self,
+            params: SelectionRangeParams,
+        ) -> BoxFuture<Option<Vec<SelectionRange>>> {
+            let ranges = params
+                .positions
+                .into_iter()
+                .map(|position| SelectionRange {
+                    range: Range::new(position, position),
+                    parent: None,
+                })
+                .collect();
+            Box::new(future::ok(Some(ranges)))
+        }
+
+        fn linked_editing_range(
+            &self,
+            params: LinkedEditingRangeParams,
+        ) -> BoxFuture<Option<LinkedEditingRanges>> {
+            let position = params.text_document_position_params.position;
+            let ranges = vec![
+                Range::new(position, position),
+                Range::new(
+                    Position::new(position.line + 1, 0),
+                    Position::new(position.line + 1, 0),
+                ),
+            ];
+            Box::new(future::ok(Some(LinkedEditingRanges {
+                ranges,
+                word_pattern: None,
+            })))
+        }
+
+        fn moniker(&self, _: MonikerParams) -> BoxFuture<Option<Vec<Moniker>>> {
+            Box::new(future::ok(Some(vec![Moniker {
+                scheme: "tsc".into(),
+                identifier: "foo".into(),
+                unique: UniquenessLevel::Scheme,
+                kind: Some(MonikerKind::Export),
+            }])))
+        }
+
+        fn document_color(&self, _: DocumentColorParams) -> BoxFuture<Vec<ColorInformation>> {
+            Box::new(future::ok(vec![ColorInformation {
+                range: Range::new(Position::new(0, 7), Position::new(0, 14)),
+                color: RED,
+            }]))
+        }
+
+        fn color_presentation(
+            &self,
+            params: ColorPresentationParams,
+        ) -> BoxFuture<Vec<ColorPresentation>> {
+            let label = if params.color == RED { "#ff0000" } else { "" };
+            Box::new(future::ok(vec![ColorPresentation {
+                label: label.into(),
+                ..ColorPresentation::default()
+            }]))
+        }
+
+        fn on_type_formatting(
+            &self,
+            params: DocumentOnTypeFormattingParams,
+        ) -> BoxFuture<Option<Vec<TextEdit>>> {
+            let position = params.text_document_position.position;
+            let edits = match params.ch.as_str() {
+                "\n" => Some(vec![TextEdit::new(
+                    Range::new(position, position),
+                    "    ".into(),
+                )]),
+                _ => None,
+            };
+            Box::new(future::ok(edits))
+        }
+
+        fn range_formatting(
+            &self,
+            params: DocumentRangeFormattingParams,
+        ) -> BoxFuture<Option<Vec<TextEdit>>> {
+            let edit = TextEdit::new(params.range, "formatted".into());
+            Box::new(future::ok(Some(vec![edit])))
+        }
+
+        fn prepare_type_hierarchy(
+            &self,
+            params: TypeHierarchyPrepareParams,
+        ) -> BoxFuture<Option<Vec<TypeHierarchyItem>>> {
+            let TextDocumentPositionParams {
+                text_document,
+                position,
+            } = params.text_document_position_params;
+            let items = match position.line {
+                0 => None,
+                1 => Some(vec![type_hierarchy_item("Foo", text_document.uri)]),
+                _ => Some(Vec::new()),
+            };
+            Box::new(future::ok(items))
+        }
+
+        fn prepare_call_hierarchy(
+            &self,
+            params: CallHierarchyPrepareParams,
+        ) -> BoxFuture<Option<Vec<CallHierarchyItem>>> {
+            let position = params.text_document_position_params.position;
+            let items = if position.line == 0 {
+                None
+            } else {
+                Some(Vec::new())
+            };
+            Box::new(future::ok(items))
+        }
+
+        fn supertypes(
+            &self,
+            params: TypeHierarchySupertypesParams,
+        ) -> BoxFuture<Option<Vec<TypeHierarchyItem>>> {
+            let uri = params.item.uri;
+            Box::new(future::ok(Some(vec![type_hierarchy_item("Base", uri)])))
+        }
+
+        fn subtypes(
+            &self,
+            _: TypeHierarchySubtypesParams,
+        ) -> BoxFuture<Option<Vec<TypeHierarchyItem>>> {
+            Box::new(future::ok(None))
+        }
+
+        fn inlay_hint(&self, params: InlayHintParams) -> BoxFuture<Option<Vec<InlayHint>>> {
+            Box::new(future::ok(Some(vec![InlayHint {
+                position: params.range.start,
+                label: InlayHintLabel::String(": i32".into()),
+                kind: Some(InlayHintKind::TYPE),
+                text_edits: None,
+                tooltip: None,
+                padding_left: None,
+                padding_right: None,
+                data: None,
+            }])))
+        }
+
+        fn inlay_hint_resolve(&self, mut hint: InlayHint) -> BoxFuture<InlayHint> {
+            hint.tooltip = Some(InlayHintTooltip::String("32-bit signed integer".into()));
+            Box::new(future::ok(hint))
+        }
+
+        fn semantic_tokens_full(
+            &self,
+            _: SemanticTokensParams,
+        ) -> BoxFuture<Option<SemanticTokensResult>> {
+            let legend = TokenLegend::new().token_type(SemanticTokenType::KEYWORD);
+            let token = AbsoluteToken {
+                line: 1,
+                start: 4,
+                length: 2,
+                token_type: SemanticTokenType::KEYWORD,
+                token_modifiers: Vec::new(),
+            };
+            let data = legend.encode(vec![token]).unwrap();
+            let tokens = SemanticTokens {
+                result_id: Some("1".into()),
+                data,
+            };
+            Box::new(future::ok(Some(SemanticTokensResult::Tokens(tokens))))
+        }
+
+        fn diagnostic(
+            &self,
+            params: DocumentDiagnosticParams,
+        ) -> BoxFuture<DocumentDiagnosticReportResult> {
+            if params.text_document.uri.path() != "/main.rs" {
+                let report = RelatedFullDocumentDiagnosticReport::default();
+                let report = DocumentDiagnosticReport::Full(report);
+                return Box::new(future::ok(DocumentDiagnosticReportResult::Report(report)));
+            }
+
+            // Diagnostics of `main.rs` depend on `lib.rs`, which is reported alongside it.
+            let lib = "file:///lib.rs".parse().unwrap();
+            let report = if params.previous_result_id.as_deref() == Some("1") {
+                let related =
+                    DocumentDiagnosticReportKind::Unchanged(UnchangedDocumentDiagnosticReport {
+                        result_id: "2".into(),
+                    });
+                DocumentDiagnosticReport::Unchanged(RelatedUnchangedDocumentDiagnosticReport {
+                    related_documents: Some(vec![(lib, related)].into_iter().collect()),
+                    unchanged_document_diagnostic_report: UnchangedDocumentDiagnosticReport {
+                        result_id: "1".into(),
+                    },
+                })
+            } else {
+                let range = Range::new(Position::new(0, 0), Position::new(0, 3));
+                let related = DocumentDiagnosticReportKind::Full(FullDocumentDiagnosticReport {
+                    result_id: Some("2".into()),
+                    items: vec![Diagnostic::new_simple(range, "unused".into())],
+                });
+                DocumentDiagnosticReport::Full(RelatedFullDocumentDiagnosticReport {
+                    related_documents: Some(vec![(lib, related)].into_iter().collect()),
+                    full_document_diagnostic_report: FullDocumentDiagnosticReport {
+                        result_id: Some("1".into()),
+                        items: Vec::new(),
+                    },
+                })
+            };
+            Box::new(future::ok(DocumentDiagnosticReportResult::Report(report)))
+        }
+
+        #[cfg(feature = "proposed")]
+        fn inline_completion(
+            &self,
+            _: InlineCompletionParams,
+        ) -> BoxFuture<Option<InlineCompletionResponse>> {
+            let item = InlineCompletionItem {
+                insert_text: "ghost".into(),
+                filter_text: None,
+                range: None,
+                command: None,
+                insert_text_format: None,
+            };
+            Box::new(future::ok(Some(InlineCompletionResponse::Array(vec![
+                item,
+            ]))))
+        }
+    }
+
+    fn request(service: &mut LspService, method: &str, params: &str, id: u64) -> Option<String> {
+        let request = format!(
+            r#"{{"jsonrpc":"2.0","method":"{}","params":{},"id":{}}}"#,
+            method, params, id
+        );
+        service.call(request.parse().unwrap()).wait().unwrap()
+    }
+
+    fn initialize(service: &mut LspService) {
+        let initialize: Incoming =
+            r#"{"jsonrpc":"2.0","method":"initialize","params":{"capabilities":{}},"id":1}"#
+                .parse()
+                .unwrap();
+        let response = service.call(initialize).wait().unwrap();
+        assert!(response.unwrap().contains(r#""result""#));
+    }
+
+    #[test]
+    fn exit_notification() {
+        let (mut service, _) = LspService::new(Mock);
+
+        let initialized: Incoming = r#"{"jsonrpc":"2.0","method":"initialized"}"#.parse().unwrap();
+        assert_eq!(service.poll_ready(), Ok(Async::Ready(())));
+        assert_eq!(service.call(initialized.clone()).wait(), Ok(None));
+
+        let exit: Incoming = r#"{"jsonrpc":"2.0","method":"exit"}"#.parse().unwrap();
+        assert_eq!(service.poll_ready(), Ok(Async::Ready(())));
+        assert_eq!(service.call(exit).wait(), Ok(None));
+
+        assert_eq!(service.poll_ready(), Ok(Async::NotReady));
+        assert_eq!(service.call(initialized).wait(), Ok(None));
+
+        let shutdown: Incoming = r#"{"jsonrpc":"2.0","method":"shutdown","id":1}"#.parse().unwrap();
+        assert_eq!(service.call(shutdown).wait(), Err(ExitedError));
+    }
+
+    #[test]
+    fn drops_notifications_after_exit() {
+        let (mut service, messages) = LspService::new(Mock);
+        let exit_rx = service.close_handle();
+        initialize(&mut service);
+
+        let exit: Incoming = r#"{"jsonrpc":"2.0","method":"exit"}"#.parse().unwrap();
+        assert_eq!(service.call(exit).wait(), Ok(None));
+
+        let trailing = r#"{"jsonrpc":"2.0","method":"$/progress","params":{"token":"t","value":{"kind":"report","percentage":50}}}"#;
+        assert_eq!(service.call(trailing.parse().unwrap()).wait(), Ok(None));
+        assert_eq!(service.state(), ServerState::Exited);
+        assert!(exit_rx.wait().is_ok());
+
+        drop(service);
+        assert_eq!(messages.collect().wait(), Ok(Vec::new()));
+    }
+
+    #[test]
+    fn runs_consecutive_sessions() {
+        let mut sessions = Vec::new();
+        for _ in 0..2 {
+            let (mut service, messages) = LspService::new(Mock);
+            let exit_rx = service.close_handle();
+            initialize(&mut service);
+
+            let initialized: Incoming =
+                r#"{"jsonrpc":"2.0","method":"initialized"}"#.parse().unwrap();
+            assert_eq!(service.call(initialized).wait(), Ok(None));
+
+            let shutdown: Incoming =
+                r#"{"jsonrpc":"2.0","method":"shutdown","id":2}"#.parse().unwrap();
+            let expected = r#"{"jsonrpc":"2.0","result":null,"id":2}"#;
+            assert_eq!(service.call(shutdown).wait(), Ok(Some(expected.to_owned())));
+
+            let exit: Incoming = r#"{"jsonrpc":"2.0","method":"exit"}"#.parse().unwrap();
+            assert_eq!(service.call(exit).wait(), Ok(None));
+            assert!(exit_rx.wait().is_ok());
+            assert_eq!(service.poll_ready(), Ok(Async::NotReady));
+
+            drop(service);
+            sessions.push(messages.collect().wait().unwrap());
+        }
+
+        // The second session must not observe any state left behind by the first one.
+        assert_eq!(sessions[0], sessions[1]);
+    }
+
+    /// Server recording when its cleanup on `shutdown` has run, which fails if `fail` is set.
+    #[derive(Debug, Default)]
+    struct Cleanup {
+        events: Arc<Mutex<Vec<&'static str>>>,
+        fail: bool,
+    }
+
+    impl LanguageServer for Cleanup {
+        type ShutdownFuture = BoxFuture<()>;
+        type SymbolFuture = BoxFuture<Option<WorkspaceSymbolResponse>>;
+        type ExecuteFuture = BoxFuture<Option<Value>>;
+        type CompletionFuture = BoxFuture<Option<CompletionResponse>>;
+        type HighlightFuture = BoxFuture<Option<Vec<DocumentHighlight>>>;
+        type HoverFuture = BoxFuture<Option<Hover>>;
+
+        fn initialize(&self, _: &Printer, _: InitializeParams) -> Result<InitializeResult> {
+            Ok(InitializeResult::default())
+        }
+
+        fn shutdown(&self) -> Self::ShutdownFuture {
+            let (events, fail) = (self.events.clone(), self.fail);
+            Box::new(future::lazy(move || {
+                events.lock().unwrap().push("cleanup");
+                if fail {
+                    Err(RpcError::internal_error())
+                } else {
+                    Ok(())
+                }
+            }))
+        }
+
+        fn symbol(&self, params: WorkspaceSymbolParams) -> Self::SymbolFuture {
+            Mock.symbol(params)
+        }
+
+        fn execute_command(
+            &self,
+            p: &Printer,
+            params: ExecuteCommandParams,
+        ) -> Self::ExecuteFuture {
+            Mock.execute_command(p, params)
+        }
+
+        fn completion(&self, params: CompletionParams) -> Self::CompletionFuture {
+            Mock.completion(params)
+        }
+
+        fn hover(&self, params: HoverParams) -> Self::HoverFuture {
+            Mock.hover(params)
+        }
+
+        fn document_highlight(&self, params: DocumentHighlightParams) -> Self::HighlightFuture {
+            Mock.document_highlight(params)
+        }
+    }
+
+    #[test]
+    fn responds_to_shutdown_after_cleanup() {
+        for &fail in &[false, true] {
+            let events = Arc::new(Mutex::new(Vec::new()));
+            let server = Cleanup {
+                events: events.clone(),
+                fail,
+            };
+            let (mut service, _) = LspService::new(server);
+            initialize(&mut service);
+
+            let shutdown: Incoming =
+                r#"{"jsonrpc":"2.0","method":"shutdown","id":2}"#.parse().unwrap();
+            let response = service.call(shutdown).wait().unwrap().unwrap();
+            events.lock().unwrap().push("response");
+            assert_eq!(*events.lock().unwrap(), ["cleanup", "response"]);
+            assert_eq!(response.contains(r#""code":-32603"#), fail);
+
+            // The server is shut down even if its cleanup failed.
+            let params = r#"{"query":"foo"}"#;
+            let response = request(&mut service, "workspace/symbol", params, 3);
+            assert!(response.unwrap().contains(r#""code":-32600"#));
+        }
+    }
+
+    #[test]
+    fn reports_lifecycle_state() {
+        let (mut service, _) = LspService::new(Mock);
+        let lifecycle = service.lifecycle();
+        assert_eq!(service.state(), ServerState::Uninitialized);
+
+        initialize(&mut service);
+        assert_eq!(lifecycle.state(), ServerState::Initialized);
+
+        let shutdown: Incoming = r#"{"jsonrpc":"2.0","method":"shutdown","id":2}"#.parse().unwrap();
+        assert!(service.call(shutdown).wait().is_ok());
+        assert_eq!(lifecycle.state(), ServerState::ShuttingDown);
+
+        let exit: Incoming = r#"{"jsonrpc":"2.0","method":"exit"}"#.parse().unwrap();
+        assert_eq!(service.call(exit).wait(), Ok(None));
+        assert_eq!(service.state(), ServerState::Exited);
+
+        drop(service);
+        assert_eq!(lifecycle.state(), ServerState::Exited);
+    }
+
+    #[test]
+    fn rejects_requests_after_shutdown() {
+        let (mut service, _) = LspService::new(Mock);
+        initialize(&mut service);
+
+        let shutdown: Incoming = r#"{"jsonrpc":"2.0","method":"shutdown","id":2}"#.parse().unwrap();
+        assert_eq!(
+            service.call(shutdown).wait(),
+            Ok(Some(r#"{"jsonrpc":"2.0","result":null,"id":2}"#.to_owned()))
+        );
+
+        let params = r#"{"query":"foo"}"#;
+        let response = request(&mut service, "workspace/symbol", params, 3);
+        assert!(response.unwrap().contains(r#""code":-32600"#));
+
+        let shutdown: Incoming = r#"{"jsonrpc":"2.0","method":"shutdown","id":4}"#.parse().unwrap();
+        let response = service.call(shutdown).wait().unwrap();
+        assert!(response.unwrap().contains(r#""code":-32600"#));
+
+        let exit: Incoming = r#"{"jsonrpc":"2.0","method":"exit"}"#.parse().unwrap();
+        assert_eq!(service.call(exit).wait(), Ok(None));
+        assert_eq!(service.poll_ready(), Ok(Async::NotReady));
+    }
+
+    #[test]
+    fn routes_rename_methods() {
+        let (mut service, _) = LspService::new(Mock);
+        initialize(&mut service);
+
+        let position =
+            r#""textDocument":{"uri":"file:///foo.rs"},"position":{"line":1,"character":2}"#;
+        let prepare: Incoming = format!(
+            r#"{{"jsonrpc":"2.0","method":"textDocument/prepareRename","params":{{{}}},"id":2}}"#,
+            position
+        )
+        .parse()
+        .unwrap();
+        let range = r#"{"end":{"character":2,"line":1},"start":{"character":2,"line":1}}"#;
+        let expected = format!(r#"{{"jsonrpc":"2.0","result":{},"id":2}}"#, range);
+        assert_eq!(service.call(prepare).wait(), Ok(Some(expected)));
+
+        let rename: Incoming = format!(
+            r#"{{"jsonrpc":"2.0","method":"textDocument/rename","params":{{{},"newName":"bar"}},"id":3}}"#,
+            position
+        )
+        .parse()
+        .unwrap();
+        let response = service.call(rename).wait().unwrap().unwrap();
+        assert!(response.contains(r#""code":-32601"#));
+    }
+
+    #[test]
+    fn builds_with_custom_methods() {
+        let (mut service, _) = LspService::build(Mock)
+            .custom_method("custom/echo", |params: Params| params.parse::<Value>())
+            .custom_notification("custom/notify", |_| {})
+            .finish();
+
+        let response = request(&mut service, "custom/echo", "[1,2]", 1);
+        assert_eq!(
+            response,
+            Some(r#"{"jsonrpc":"2.0","result":[1,2],"id":1}"#.to_owned())
+        );
+
+        let notify: Incoming = r#"{"jsonrpc":"2.0","method":"custom/notify"}"#.parse().unwrap();
+        assert_eq!(service.call(notify).wait(), Ok(None));
+    }
+
+    #[test]
+    fn serves_virtual_document_content() {
+        let (mut service, _) = LspService::build(Mock)
+            .custom_method("workspace/textDocumentContent", |params: Params| {
+                let params: Value = params.parse()?;
+                let uri = params["uri"].as_str().unwrap_or_default();
+                Ok(json!({ "text": format!("// generated from {}", uri) }))
+            })
+            .finish();
+
+        initialize(&mut service);
+        let response = request(
+            &mut service,
+            "workspace/textDocumentContent",
+            r#"{"uri":"generated:///lib.rs"}"#,
+            2,
+        );
+        assert_eq!(
+            response,
+            Some(
+                r#"{"jsonrpc":"2.0","result":{"text":"// generated from generated:///lib.rs"},"id":2}"#
+                    .to_owned()
+            )
+        );
+    }
+
+    #[test]
+    fn forwards_unhandled_to_fallback() {
+        let forwarded = Arc::new(Mutex::new(Vec::new()));
+        let log = forwarded.clone();
+        let (mut service, _) = LspService::build(Mock)
+            .custom_method("custom/echo", |params: Params| params.parse::<Value>())
+            .fallback(move |method: &str, params: Params| {
+                log.lock().unwrap().push(method.to_owned());
+                params.parse::<Value>()
+            })
+            .finish();
+        initialize(&mut service);
+
+        let response = request(&mut service, "upstream/method", "[1]", 2);
+        assert_eq!(
+            response,
+            Some(r#"{"jsonrpc":"2.0","result":[1],"id":2}"#.to_owned())
+        );
+
+        let notify: Incoming = r#"{"jsonrpc":"2.0","method":"upstream/notify"}"#.parse().unwrap();
+        assert_eq!(service.call(notify).wait(), Ok(None));
+
+        let response = request(&mut service, "custom/echo", "[3]", 3);
+        assert_eq!(
+            response,
+            Some(r#"{"jsonrpc":"2.0","result":[3],"id":3}"#.to_owned())
+        );
+
+        let position =
+            r#"{"textDocument":{"uri":"file:///foo.rs"},"position":{"line":0,"character":0}}"#;
+        request(&mut service, "textDocument/hover", position, 4);
+
+        // `Mock` does not implement renaming, so the request is declined by its default handler.
+        let rename = concat!(
+            r#"{"newName":"bar","position":{"character":0,"line":0},"#,
+            r#""textDocument":{"uri":"file:///foo.rs"}}"#
+        );
+        let response = request(&mut service, "textDocument/rename", rename, 5);
+        let expected = format!(r#"{{"jsonrpc":"2.0","result":{},"id":5}}"#, rename);
+        assert_eq!(response, Some(expected));
+
+        assert_eq!(
+            *forwarded.lock().unwrap(),
+            vec![
+                "upstream/method".to_owned(),
+                "upstream/notify".to_owned(),
+                "textDocument/rename".to_owned(),
+            ]
+        );
+    }
+
+    #[test]
+    fn applies_lifecycle_to_fallback() {
+        let forwarded = Arc::new(Mutex::new(Vec::new()));
+        let log = forwarded.clone();
+        let (mut service, _) = LspService::build(Mock)
+            .fallback(move |method: &str, params: Params| {
+                log.lock().unwrap().push(method.to_owned());
+                params.parse::<Value>()
+            })
+            .finish();
+
+        let response = request(&mut service, "upstream/method", "[1]", 1);
+        assert_eq!(
+            response,
+            Some(
+                r#"{"jsonrpc":"2.0","error":{"code":-32002,"message":"Server not initialized"},"id":1}"#
+                    .to_owned()
+            )
+        );
+        let notify: Incoming = r#"{"jsonrpc":"2.0","method":"upstream/notify"}"#.parse().unwrap();
+        assert_eq!(service.call(notify.clone()).wait(), Ok(None));
+
+        initialize(&mut service);
+        let shutdown: Incoming = r#"{"jsonrpc":"2.0","method":"shutdown","id":2}"#.parse().unwrap();
+        service.call(shutdown).wait().unwrap();
+
+        let response = request(&mut service, "upstream/method", "[1]", 3);
+        assert_eq!(
+            response,
+            Some(
+                r#"{"jsonrpc":"2.0","error":{"code":-32600,"message":"Server has been shut down"},"id":3}"#
+                    .to_owned()
+            )
+        );
+        assert_eq!(service.call(notify).wait(), Ok(None));
+        assert!(forwarded.lock().unwrap().is_empty());
+    }
+
+    #[test]
+    fn passes_extensions_to_handlers() {
+        struct Database(Mutex<Vec<Value>>);
+
+        let (mut service, _) = LspService::build(Mock)
+            .extension(Database(Mutex::new(Vec::new())))
+            .command("db.insert", |printer: &Printer, arguments| {
+                let database = printer.extension::<Database>().unwrap();
+                let mut rows = database.0.lock().unwrap();
+                rows.extend(arguments);
+                Ok(Some(json!(rows.len())))
+            })
+            .finish();
+        initialize(&mut service);
+
+        let params = r#"{"command":"db.insert","arguments":["foo","bar"]}"#;
+        let response = request(&mut service, "workspace/executeCommand", params, 2);
+        assert_eq!(
+            response,
+            Some(r#"{"jsonrpc":"2.0","result":2,"id":2}"#.to_owned())
+        );
+
+        let printer = service.printer();
+        assert!(printer.extension::<String>().is_none());
+        printer.insert_extension("cache".to_owned());
+        assert_eq!(printer.extension::<String>().unwrap().as_str(), "cache");
+    }
+
+    #[test]
+    fn tracks_trace_value() {
+        let (mut service, messages) = LspService::new(Mock);
+
+        let initialize: Incoming = r#"{"jsonrpc":"2.0","method":"initialize","params":{"capabilities":{},"trace":"messages"},"id":1}"#
+            .parse()
+            .unwrap();
+        service.call(initialize).wait().unwrap();
+
+        let params = r#"{"command":"foo","arguments":[]}"#;
+        request(&mut service, "workspace/executeCommand", params, 2);
+
+        let set_trace: Incoming =
+            r#"{"jsonrpc":"2.0","method":"$/setTrace","params":{"value":"verbose"}}"#
+                .parse()
+                .unwrap();
+        assert_eq!(service.call(set_trace).wait(), Ok(None));
+        request(&mut service, "workspace/executeCommand", params, 3);
+
+        drop(service);
+        let messages = messages.collect().wait().unwrap();
+        assert_eq!(
+            messages,
+            vec![
+                r#"{"jsonrpc":"2.0","method":"$/logTrace","params":{"message":"executed"}}"#,
+                r#"{"jsonrpc":"2.0","method":"$/logTrace","params":{"message":"executed","verbose":"details"}}"#,
+            ]
+        );
+    }
+
+    #[test]
+    fn responds_out_of_order() {
+        let (tx, rx) = oneshot::channel();
+        let rx = Mutex::new(Some(rx));
+        let (mut service, _) = LspService::build(Mock)
+            .custom_method("custom/slow", move |_| {
+                let rx = rx.lock().unwrap().take().expect("called more than once");
+                rx.map_err(|_| RpcError::internal_error())
+            })
+            .custom_method("custom/echo", |params: Params| params.parse::<Value>())
+            .finish();
+
+        let slow: Incoming = r#"{"jsonrpc":"2.0","method":"custom/slow","id":1}"#
+            .parse()
+            .unwrap();
+        let slow = service.call(slow);
+        let fast = request(&mut service, "custom/echo", "[2]", 2);
+        assert_eq!(
+            fast,
+            Some(r#"{"jsonrpc":"2.0","result":[2],"id":2}"#.to_owned())
+        );
+
+        tx.send(Value::from(1)).unwrap();
+        assert_eq!(
+            slow.wait(),
+            Ok(Some(r#"{"jsonrpc":"2.0","result":1,"id":1}"#.to_owned()))
+        );
+    }
+
+    #[test]
+    fn serializes_prepare_rename_responses() {
+        let (mut service, _) = LspService::new(Mock);
+        initialize(&mut service);
+
+        let params = |line| {
+            format!(
+                r#"{{"textDocument":{{"uri":"file:///foo.rs"}},"position":{{"line":{},"character":2}}}}"#,
+                line
+            )
+        };
+
+        let response = request(&mut service, "textDocument/prepareRename", &params(0), 2);
+        let expected = r#"{"jsonrpc":"2.0","result":{"defaultBehavior":true},"id":2}"#;
+        assert_eq!(response, Some(expected.to_owned()));
+
+        let response = request(&mut service, "textDocument/prepareRename", &params(2), 3);
+        let range = r#"{"end":{"character":2,"line":2},"start":{"character":2,"line":2}}"#;
+        let expected = format!(
+            r#"{{"jsonrpc":"2.0","result":{{"placeholder":"foo","range":{}}},"id":3}}"#,
+            range
+        );
+        assert_eq!(response, Some(expected));
+    }
+
+    #[test]
+    fn passes_completion_context() {
+        let (mut service, _) = LspService::new(Mock);
+        initialize(&mut service);
+
+        let params = r#"{"textDocument":{"uri":"file:///foo.rs"},"position":{"line":0,"character":4},"context":{"triggerKind":2,"triggerCharacter":"."}}"#;
+        let response = request(&mut service, "textDocument/completion", params, 2);
+        let expected = r#"{"jsonrpc":"2.0","result":[{"detail":"triggered","label":"."}],"id":2}"#;
+        assert_eq!(response, Some(expected.to_owned()));
+
+        let params = r#"{"textDocument":{"uri":"file:///foo.rs"},"position":{"line":0,"character":4},"context":{"triggerKind":1}}"#;
+        let response = request(&mut service, "textDocument/completion", params, 3);
+        assert_eq!(
+            response,
+            Some(r#"{"jsonrpc":"2.0","result":null,"id":3}"#.to_owned())
+        );
+    }
+
+    #[test]
+    fn resolves_completion_item() {
+        let (mut service, _) = LspService::new(Mock);
+        initialize(&mut service);
+
+        let response = request(
+            &mut service,
+            "completionItem/resolve",
+            r#"{"label":"foo"}"#,
+            2,
+        );
+        let expected = r#"{"jsonrpc":"2.0","result":{"detail":"resolved","label":"foo"},"id":2}"#;
+        assert_eq!(response, Some(expected.to_owned()));
+    }
+
+    #[test]
+    fn routes_will_rename_files() {
+        let (mut service, _) = LspService::new(Mock);
+        initialize(&mut service);
+
+        let params = r#"{"files":[{"oldUri":"file:///a.rs","newUri":"file:///b.rs"}]}"#;
+        let response = request(&mut service, "workspace/willRenameFiles", params, 2);
+        assert_eq!(
+            response,
+            Some(r#"{"jsonrpc":"2.0","result":{},"id":2}"#.to_owned())
+        );
+
+        let response = request(
+            &mut service,
+            "workspace/willDeleteFiles",
+            r#"{"files":[]}"#,
+            3,
+        );
+        assert!(response.unwrap().contains(r#""code":-32601"#));
+    }
+
+    #[cfg(feature = "proposed")]
+    #[test]
+    fn routes_inline_completion() {
+        let (mut service, _) = LspService::new(Mock);
+
+        let params = r#"{"textDocument":{"uri":"file:///foo.rs"},"position":{"line":0,"character":0},"context":{"triggerKind":1}}"#;
+        let response = request(&mut service, "textDocument/inlineCompletion", params, 1);
+        assert!(response.unwrap().contains(r#""code":-32002"#));
+
+        initialize(&mut service);
+        let response = request(&mut service, "textDocument/inlineCompletion", params, 2);
+        assert_eq!(
+            response,
+            Some(r#"{"jsonrpc":"2.0","result":[{"insertText":"ghost"}],"id":2}"#.to_owned())
+        );
+    }
+
+    #[test]
+    fn routes_pull_diagnostics() {
+        let (mut service, _) = LspService::new(Mock);
+        initialize(&mut service);
+
+        let params = r#"{"textDocument":{"uri":"file:///foo.rs"}}"#;
+        let response = request(&mut service, "textDocument/diagnostic", params, 2);
+        assert_eq!(
+            response,
+            Some(r#"{"jsonrpc":"2.0","result":{"items":[],"kind":"full"},"id":2}"#.to_owned())
+        );
+
+        let params = r#"{"previousResultIds":[]}"#;
+        let response = request(&mut service, "workspace/diagnostic", params, 3);
+        assert!(response.unwrap().contains(r#""code":-32601"#));
+    }
+
+    #[test]
+    fn reports_related_document_diagnostics() {
+        let (mut service, _) = LspService::new(Mock);
+        initialize(&mut service);
+
+        let params = r#"{"textDocument":{"uri":"file:///main.rs"}}"#;
+        let response = request(&mut service, "textDocument/diagnostic", params, 2).unwrap();
+        let response: Value = serde_json::from_str(&response).unwrap();
+        let range = json!({
+            "start": { "line": 0, "character": 0 },
+            "end": { "line": 0, "character": 3 },
+        });
+        let expected = json!({
+            "kind": "full",
+            "resultId": "1",
+            "items": [],
+            "relatedDocuments": {
+                "file:///lib.rs": {
+                    "kind": "full",
+                    "resultId": "2",
+                    "items": [{ "range": range, "message": "unused" }],
+                },
+            },
+        });
+        assert_eq!(response["result"], expected);
+
+        let params = r#"{"textDocument":{"uri":"file:///main.rs"},"previousResultId":"1"}"#;
+        let response = request(&mut service, "textDocument/diagnostic", params, 3).unwrap();
+        let response: Value = serde_json::from_str(&response).unwrap();
+        let expected = json!({
+            "kind": "unchanged",
+            "resultId": "1",
+            "relatedDocuments": {
+                "file:///lib.rs": { "kind": "unchanged", "resultId": "2" },
+            },
+        });
+        assert_eq!(response["result"], expected);
+    }
+
+    #[test]
+    fn resolves_document_link() {
+        let (mut service, _) = LspService::new(Mock);
+        initialize(&mut service);
+
+        let params = r#"{"textDocument":{"uri":"file:///foo.rs"}}"#;
+        let response = request(&mut service, "textDocument/documentLink", params, 2);
+        assert!(response.unwrap().contains(r#""code":-32601"#));
+
+        let range = r#"{"end":{"character":3,"line":0},"start":{"character":0,"line":0}}"#;
+        let params = format!(r#"{{"range":{}}}"#, range);
+        let response = request(&mut service, "documentLink/resolve", &params, 3);
+        let expected = format!(
+            r#"{{"jsonrpc":"2.0","result":{{"range":{},"target":"file:///target.rs"}},"id":3}}"#,
+            range
+        );
+        assert_eq!(response, Some(expected));
+    }
+
+    #[test]
+    fn routes_folding_and_selection_ranges() {
+        let (mut service, _) = LspService::new(Mock);
+        initialize(&mut service);
+
+        let params = r#"{"textDocument":{"uri":"file:///foo.rs"}}"#;
+        let response = request(&mut service, "textDocument/foldingRange", params, 2);
+        assert!(response.unwrap().contains(r#""code":-32601"#));
+
+        let params =
+            r#"{"textDocument":{"uri":"file:///foo.rs"},"positions":[{"line":1,"character":2}]}"#;
+        let response = request(&mut service, "textDocument/selectionRange", params, 3);
+        let range = r#"{"end":{"character":2,"line":1},"start":{"character":2,"line":1}}"#;
+        let expected = format!(
+            r#"{{"jsonrpc":"2.0","result":[{{"range":{}}}],"id":3}}"#,
+            range
+        );
+        assert_eq!(response, Some(expected));
+    }
+
+    #[test]
+    fn recovers_id_of_invalid_requests() {
+        let (mut service, _) = LspService::new(Mock);
+        initialize(&mut service);
+
+        let messages = [
+            (r#"{"jsonrpc":"2.0","method":1,"id":2}"#, "2"),
+            (r#"{"jsonrpc":"2.0","params":[],"id":"abc"}"#, r#""abc""#),
+            (r#"{"jsonrpc":"2.0","method":"foo","id":{"a":1}}"#, "null"),
+            (r#"{"jsonrpc":"2.0","method":"foo","id":-1}"#, "null"),
+        ];
+
+        for &(message, id) in &messages {
+            let message = Incoming::from(message.to_owned());
+            let expected = format!(
+                r#"{{"jsonrpc":"2.0","error":{{"code":-32600,"message":"Invalid request"}},"id":{}}}"#,
+                id
+            );
+            assert_eq!(service.call(message).wait(), Ok(Some(expected)));
+        }
+
+        let malformed = Incoming::from(r#"{"jsonrpc":"2.0","#.to_owned());
+        let expected =
+            r#"{"jsonrpc":"2.0","error":{"code":-32700,"message":"Parse error"},"id":null}"#;
+        assert_eq!(
+            service.call(malformed).wait(),
+            Ok(Some(expected.to_owned()))
+        );
+    }
+
+    #[test]
+    fn routes_linked_editing_range() {
+        let (mut service, _) = LspService::new(Mock);
+        initialize(&mut service);
+
+        let params =
+            r#"{"textDocument":{"uri":"file:///foo.html"},"position":{"line":1,"character":2}}"#;
+        let response = request(&mut service, "textDocument/linkedEditingRange", params, 2);
+        let first = r#"{"end":{"character":2,"line":1},"start":{"character":2,"line":1}}"#;
+        let second = r#"{"end":{"character":0,"line":2},"start":{"character":0,"line":2}}"#;
+        let expected = format!(
+            r#"{{"jsonrpc":"2.0","result":{{"ranges":[{},{}]}},"id":2}}"#,
+            first, second
+        );
+        assert_eq!(response, Some(expected));
+    }
+
+    #[test]
+    fn dispatches_registered_commands() {
+        let (mut service, _) = LspService::build(Mock)
+            .command("math.add", |_, arguments| {
+                let sum = arguments.iter().filter_map(Value::as_i64).sum::<i64>();
+                Ok(Some(Value::from(sum)))
+            })
+            .finish();
+        initialize(&mut service);
+
+        let params = r#"{"command":"math.add","arguments":[1,2]}"#;
+        let response = request(&mut service, "workspace/executeCommand", params, 2);
+        let expected = r#"{"jsonrpc":"2.0","result":3,"id":2}"#;
+        assert_eq!(response, Some(expected.to_owned()));
+
+        let params = r#"{"command":"math.sub","arguments":[1,2]}"#;
+        let response = request(&mut service, "workspace/executeCommand", params, 3);
+        let expected = r#"{"jsonrpc":"2.0","result":null,"id":3}"#;
+        assert_eq!(response, Some(expected.to_owned()));
+    }
+
+    #[test]
+    fn cancels_work_done_progress() {
+        let tokens = Arc::new(Mutex::new(Vec::new()));
+        let registered = tokens.clone();
+        let (mut service, _) = LspService::build(Mock)
+            .command("index", move |p, _| {
+                let token = NumberOrString::String("indexing".into());
+                registered
+                    .lock()
+                    .unwrap()
+                    .push(p.progress_cancellation(token));
+                Ok(None)
+            })
+            .finish();
+        initialize(&mut service);
+
+        let params = r#"{"command":"index"}"#;
+        request(&mut service, "workspace/executeCommand", params, 2);
+        let token = tokens.lock().unwrap().pop().unwrap();
+        assert!(!token.is_cancelled());
+
+        let notification: Incoming =
+            r#"{"jsonrpc":"2.0","method":"window/workDoneProgress/cancel","params":{"token":"indexing"}}"#
+                .parse()
+                .unwrap();
+        assert_eq!(service.call(notification).wait(), Ok(None));
+        assert!(token.is_cancelled());
+    }
+
+    #[test]
+    fn routes_did_change_configuration() {
+        let (mut service, messages) = LspService::new(Mock);
+        initialize(&mut service);
+
+        let notification: Incoming =
+            r#"{"jsonrpc":"2.0","method":"workspace/didChangeConfiguration","params":{"settings":{"foo":1}}}"#
+                .parse()
+                .unwrap();
+        assert_eq!(service.call(notification).wait(), Ok(None));
+        drop(service);
+
+        let messages = messages.collect().wait().unwrap();
+        let expected = r#"{"jsonrpc":"2.0","method":"window/logMessage","params":{"message":"{\"foo\":1}","type":3}}"#;
+        assert_eq!(messages.last().map(String::as_str), Some(expected));
+    }
+
+    #[test]
+    fn routes_inbound_progress() {
+        let (mut service, messages) = LspService::new(Mock);
+        initialize(&mut service);
+
+        let value = r#"{"kind":"report","percentage":50}"#;
+        let notification: Incoming = format!(
+            r#"{{"jsonrpc":"2.0","method":"$/progress","params":{{"token":"sub","value":{}}}}}"#,
+            value
+        )
+        .parse()
+        .unwrap();
+        assert_eq!(service.call(notification).wait(), Ok(None));
+        drop(service);
+
+        let messages = messages.collect().wait().unwrap();
+        let expected = r#"{"jsonrpc":"2.0","method":"window/logMessage","params":{"message":"Some(50)%","type":3}}"#;
+        assert_eq!(messages.last().map(String::as_str), Some(expected));
+    }
+
+    #[test]
+    fn routes_responses_to_printer() {
+        let (mut service, messages) = LspService::build(Mock)
+            .command("settings", |p, _| {
+                let item = ConfigurationItem {
+                    scope_uri: None,
+                    section: Some("foo".into()),
+                };
+                p.configuration(vec![item])
+                    .map(|values| Some(Value::from(values)))
+            })
+            .finish();
+        initialize(&mut service);
+
+        let params = r#"{"command":"settings"}"#;
+        let command: Incoming = format!(
+            r#"{{"jsonrpc":"2.0","method":"workspace/executeCommand","params":{},"id":2}}"#,
+            params
+        )
+        .parse()
+        .unwrap();
+        let pending = service.call(command);
+        let pending = std::thread::spawn(move || pending.wait());
+
+        let (request, _) = messages
+            .skip_while(|m| Ok(!m.contains("workspace/configuration")))
+            .into_future()
+            .wait()
+            .ok()
+            .unwrap();
+        let request: Value = serde_json::from_str(&request.unwrap()).unwrap();
+        assert_eq!(request["params"]["items"][0]["section"], "foo");
+
+        let response: Incoming = format!(
+            r#"{{"jsonrpc":"2.0","result":[{{"bar":true}}],"id":{}}}"#,
+            request["id"]
+        )
+        .parse()
+        .unwrap();
+        assert_eq!(service.call(response).wait(), Ok(None));
+
+        let expected = r#"{"jsonrpc":"2.0","result":[{"bar":true}],"id":2}"#;
+        assert_eq!(pending.join().unwrap(), Ok(Some(expected.to_owned())));
+    }
+
+    #[test]
+    fn holds_back_requests_until_initialized() {
+        let (mut service, messages) = LspService::new(Mock);
+        let item = ConfigurationItem {
+            scope_uri: None,
+            section: Some("foo".into()),
+        };
+        let pending = service.printer().configuration(vec![item]);
+        let pending = std::thread::spawn(move || pending.wait());
+
+        initialize(&mut service);
+        let initialized: Incoming = r#"{"jsonrpc":"2.0","method":"initialized","params":{}}"#
+            .parse()
+            .unwrap();
+        assert_eq!(service.call(initialized).wait(), Ok(None));
+
+        let (request, _) = messages.into_future().wait().ok().unwrap();
+        let request: Value = serde_json::from_str(&request.unwrap()).unwrap();
+        assert_eq!(request["method"], "workspace/configuration");
+        assert_eq!(request["params"]["items"][0]["section"], "foo");
+
+        let response: Incoming = format!(
+            r#"{{"jsonrpc":"2.0","result":[{{"bar":true}}],"id":{}}}"#,
+            request["id"]
+        )
+        .parse()
+        .unwrap();
+        assert_eq!(service.call(response).wait(), Ok(None));
+        assert_eq!(pending.join().unwrap(), Ok(vec![json!({ "bar": true })]));
+    }
+
+    #[test]
+    fn converts_panics_into_errors() {
+        let (mut service, _) = LspService::new(Mock);
+        initialize(&mut service);
+
+        let params = r#"{"textDocument":{"uri":"file:///foo.rs"}}"#;
+        let response = request(&mut service, "textDocument/codeLens", params, 2);
+        let expected =
+            r#"{"jsonrpc":"2.0","error":{"code":-32603,"message":"Internal error"},"id":2}"#;
+        assert_eq!(response, Some(expected.to_owned()));
+
+        let notification: Incoming =
+            r#"{"jsonrpc":"2.0","method":"textDocument/didSave","params":{"textDocument":{"uri":"file:///foo.rs"}}}"#
+                .parse()
+                .unwrap();
+        assert_eq!(service.call(notification).wait(), Ok(None));
+
+        let (mut service, _) = LspService::build(Mock)
+            .panic_handler(|method, message| {
+                RpcError::invalid_params(format!("{} panicked: {}", method, message))
+            })
+            .finish();
+        initialize(&mut service);
+
+        let params =
+            r#"{"range":{"start":{"line":0,"character":0},"end":{"line":0,"character":0}}}"#;
+        let response = request(&mut service, "codeLens/resolve", params, 3);
+        let expected = r#"{"jsonrpc":"2.0","error":{"code":-32602,"message":"codeLens/resolve panicked: failed to resolve code lens"},"id":3}"#;
+        assert_eq!(response, Some(expected.to_owned()));
+    }
+
+    #[test]
+    fn routes_moniker() {
+        let (mut service, _) = LspService::new(Mock);
+        initialize(&mut service);
+
+        let params =
+            r#"{"textDocument":{"uri":"file:///foo.rs"},"position":{"line":1,"character":2}}"#;
+        let response = request(&mut service, "textDocument/moniker", params, 2);
+        let moniker = r#"{"identifier":"foo","kind":"export","scheme":"tsc","unique":"scheme"}"#;
+        let expected = format!(r#"{{"jsonrpc":"2.0","result":[{}],"id":2}}"#, moniker);
+        assert_eq!(response, Some(expected));
+    }
+
+    const RED: Color = Color {
+        red: 1.0,
+        green: 0.0,
+        blue: 0.0,
+        alpha: 1.0,
+    };
+
+    fn type_hierarchy_item(name: &str, uri: Url) -> TypeHierarchyItem {
+        TypeHierarchyItem {
+            name: name.into(),
+            kind: SymbolKind::STRUCT,
+            tags: None,
+            detail: None,
+            uri,
+            range: Range::default(),
+            selection_range: Range::default(),
+            data: None,
+        }
+    }
+
+    #[test]
+    fn routes_color_requests() {
+        let (mut service, _) = LspService::new(Mock);
+        initialize(&mut service);
+
+        let color = r#"{"alpha":1.0,"blue":0.0,"green":0.0,"red":1.0}"#;
+        let range = r#"{"end":{"character":14,"line":0},"start":{"character":7,"line":0}}"#;
+
+        let params = r#"{"textDocument":{"uri":"file:///foo.css"}}"#;
+        let response = request(&mut service, "textDocument/documentColor", params, 2);
+        let expected = format!(
+            r#"{{"jsonrpc":"2.0","result":[{{"color":{},"range":{}}}],"id":2}}"#,
+            color, range
+        );
+        assert_eq!(response, Some(expected));
+
+        let params = format!(
+            r#"{{"textDocument":{{"uri":"file:///foo.css"}},"color":{},"range":{}}}"#,
+            color, range
+        );
+        let response = request(&mut service, "textDocument/colorPresentation", &params, 3);
+        let expected = r##"{"jsonrpc":"2.0","result":[{"label":"#ff0000"}],"id":3}"##;
+        assert_eq!(response, Some(expected.to_owned()));
+    }
+
+    #[test]
+    fn distinguishes_null_from_empty_hierarchies() {
+        let (mut service, _) = LspService::new(Mock);
+        initialize(&mut service);
+
+        let methods = [
+            "textDocument/prepareCallHierarchy",
+            "textDocument/prepareTypeHierarchy",
+        ];
+        for method in &methods {
+            for &(line, result) in &[(0, "null"), (2, "[]")] {
+                let params = format!(
+                    r#"{{"textDocument":{{"uri":"file:///foo.rs"}},"position":{{"line":{},"character":0}}}}"#,
+                    line
+                );
+                let response = request(&mut service, method, &params, 2);
+                let expected = format!(r#"{{"jsonrpc":"2.0","result":{},"id":2}}"#, result);
+                assert_eq!(response, Some(expected));
+            }
+        }
+    }
+
+    #[test]
+    fn routes_type_hierarchy() {
+        let (mut service, _) = LspService::new(Mock);
+        initialize(&mut service);
+
+        let range = r#"{"end":{"character":0,"line":0},"start":{"character":0,"line":0}}"#;
+        let item = |name| {
+            format!(
+                r#"{{"kind":23,"name":"{}","range":{},"selectionRange":{},"uri":"file:///foo.rs"}}"#,
+                name, range, range
+            )
+        };
+
+        let params =
+            r#"{"textDocument":{"uri":"file:///foo.rs"},"position":{"line":1,"character":2}}"#;
+        let response = request(&mut service, "textDocument/prepareTypeHierarchy", params, 2);
+        let expected = format!(r#"{{"jsonrpc":"2.0","result":[{}],"id":2}}"#, item("Foo"));
+        assert_eq!(response, Some(expected));
+
+        let params = format!(r#"{{"item":{}}}"#, item("Foo"));
+        let response = request(&mut service, "typeHierarchy/supertypes", &params, 3);
+        let expected = format!(r#"{{"jsonrpc":"2.0","result":[{}],"id":3}}"#, item("Base"));
+        assert_eq!(response, Some(expected));
+
+        let response = request(&mut service, "typeHierarchy/subtypes", &params, 4);
+        let expected = r#"{"jsonrpc":"2.0","result":null,"id":4}"#;
+        assert_eq!(response, Some(expected.to_owned()));
+    }
+
+    #[test]
+    fn resolves_workspace_symbols() {
+        let (mut service, _) = LspService::new(Mock);
+        initialize(&mut service);
+
+        let params = r#"{"name":"foo","kind":12,"location":{"uri":"file:///foo.rs"}}"#;
+        let response = request(&mut service, "workspaceSymbol/resolve", params, 2);
+        let range = r#"{"end":{"character":7,"line":1},"start":{"character":4,"line":1}}"#;
+        let symbol = format!(
+            r#"{{"kind":12,"location":{{"range":{},"uri":"file:///foo.rs"}},"name":"foo"}}"#,
+            range
+        );
+        let expected = format!(r#"{{"jsonrpc":"2.0","result":{},"id":2}}"#, symbol);
+        assert_eq!(response, Some(expected));
+    }
+
+    #[test]
+    fn routes_declaration_separately_from_definition() {
+        let (mut service, _) = LspService::new(Mock);
+        initialize(&mut service);
+
+        let params =
+            r#"{"textDocument":{"uri":"file:///foo.h"},"position":{"line":2,"character":5}}"#;
+        let response = request(&mut service, "textDocument/declaration", params, 2);
+        let range = r#"{"end":{"character":5,"line":2},"start":{"character":5,"line":2}}"#;
+        let expected = format!(
+            r#"{{"jsonrpc":"2.0","result":{{"range":{},"uri":"file:///foo.h"}},"id":2}}"#,
+            range
+        );
+        assert_eq!(response, Some(expected));
+
+        let response = request(&mut service, "textDocument/definition", params, 3);
+        let expected =
+            r#"{"jsonrpc":"2.0","error":{"code":-32601,"message":"Method not found"},"id":3}"#;
+        assert_eq!(response, Some(expected.to_owned()));
+    }
+
+    #[test]
+    fn routes_type_definition_and_implementation() {
+        let (mut service, _) = LspService::new(Mock);
+        initialize(&mut service);
+
+        let params =
+            r#"{"textDocument":{"uri":"file:///foo.rs"},"position":{"line":2,"character":5}}"#;
+        let response = request(&mut service, "textDocument/typeDefinition", params, 2).unwrap();
+        let response: Value = serde_json::from_str(&response).unwrap();
+        assert_eq!(response["result"]["range"]["start"]["line"], 0);
+
+        let response = request(&mut service, "textDocument/implementation", params, 3).unwrap();
+        let response: Value = serde_json::from_str(&response).unwrap();
+        let lines: Vec<_> = response["result"]
+            .as_array()
+            .unwrap()
+            .iter()
+            .map(|location| location["range"]["start"]["line"].clone())
+            .collect();
+        assert_eq!(lines, vec![json!(0), json!(1)]);
+    }
+
+    #[test]
+    fn serializes_none_results_as_null() {
+        let (mut service, _) = LspService::new(Mock);
+        initialize(&mut service);
+
+        // JSON-RPC responses must carry either a `result` or an `error`, so `None` must not be
+        // serialized by omitting the `result` field.
+        let params =
+            r#"{"textDocument":{"uri":"file:///foo.rs"},"position":{"line":1,"character":2}}"#;
+        let response = request(&mut service, "textDocument/hover", params, 2).unwrap();
+        assert_eq!(response, r#"{"jsonrpc":"2.0","result":null,"id":2}"#);
+
+        let response: Value = serde_json::from_str(&response).unwrap();
+        let response = response.as_object().unwrap();
+        assert_eq!(response.get("result"), Some(&Value::Null));
+        assert!(!response.contains_key("error"));
+    }
+
+    #[test]
+    fn compacts_responses() {
+        let (mut service, _) = LspService::build(Mock)
+            .command("compact", |_: &Printer, _| {
+                Ok(Some(json!({ "kept": null })))
+            })
+            .compact_responses()
+            .finish();
+        initialize(&mut service);
+
+        let range = r#"{"start":{"line":0,"character":0},"end":{"line":1,"character":0}}"#;
+        let params = format!(
+            r#"{{"textDocument":{{"uri":"file:///foo.rs"}},"range":{},"context":{{"diagnostics":[]}}}}"#,
+            range
+        );
+        let response = request(&mut service, "textDocument/codeAction", &params, 2).unwrap();
+        let response: Value = serde_json::from_str(&response).unwrap();
+        let action = response["result"][0].clone();
+        assert_eq!(action["data"]["nested"]["hint"], Value::Null);
+        assert!(action["data"]["nested"]
+            .as_object()
+            .unwrap()
+            .contains_key("hint"));
+        assert!(!action.as_object().unwrap().contains_key("diagnostics"));
+
+        let response = request(&mut service, "codeAction/resolve", &action.to_string(), 3).unwrap();
+        let response: Value = serde_json::from_str(&response).unwrap();
+        assert_eq!(response["result"]["data"], action["data"]);
+
+        let params = r#"{"command":"compact","arguments":[]}"#;
+        let response = request(&mut service, "workspace/executeCommand", params, 4);
+        assert_eq!(
+            response,
+            Some(r#"{"jsonrpc":"2.0","result":{"kept":null},"id":4}"#.to_owned())
+        );
+
+        let params =
+            r#"{"textDocument":{"uri":"file:///foo.rs"},"position":{"line":1,"character":2}}"#;
+        let response = request(&mut service, "textDocument/hover", params, 5);
+        assert_eq!(
+            response,
+            Some(r#"{"jsonrpc":"2.0","result":null,"id":5}"#.to_owned())
+        );
+    }
+
+    #[test]
+    fn preserves_code_action_data_when_resolving() {
+        let (mut service, _) = LspService::new(Mock);
+        initialize(&mut service);
+
+        let range = r#"{"start":{"line":0,"character":0},"end":{"line":1,"character":0}}"#;
+        let params = format!(
+            r#"{{"textDocument":{{"uri":"file:///foo.rs"}},"range":{},"context":{{"diagnostics":[]}}}}"#,
+            range
+        );
+        let response = request(&mut service, "textDocument/codeAction", &params, 2).unwrap();
+        let response: Value = serde_json::from_str(&response).unwrap();
+        let action = response["result"][0].clone();
+        let data = action["data"].clone();
+        assert_eq!(data["nested"], json!({"ids": [1, 2, 3], "hint": null}));
+
+        let response = request(&mut service, "codeAction/resolve", &action.to_string(), 3).unwrap();
+        let response: Value = serde_json::from_str(&response).unwrap();
+        let resolved = &response["result"];
+        assert_eq!(resolved["data"], data);
+        assert_eq!(resolved["title"], action["title"]);
+
+        let edits = &resolved["edit"]["changes"]["file:///foo.rs"];
+        assert_eq!(edits[0]["range"], data["range"]);
+    }
+
+    #[test]
+    fn routes_on_type_formatting() {
+        let (mut service, _) = LspService::new(Mock);
+        initialize(&mut service);
+
+        let position =
+            r#""textDocument":{"uri":"file:///foo.rs"},"position":{"line":1,"character":0}"#;
+        let options = r#""options":{"tabSize":4,"insertSpaces":true}"#;
+        let params = format!(r#"{{{},"ch":"\n",{}}}"#, position, options);
+        let response = request(&mut service, "textDocument/onTypeFormatting", &params, 2);
+        let range = r#"{"end":{"character":0,"line":1},"start":{"character":0,"line":1}}"#;
+        let expected = format!(
+            r#"{{"jsonrpc":"2.0","result":[{{"newText":"    ","range":{}}}],"id":2}}"#,
+            range
+        );
+        assert_eq!(response, Some(expected));
+
+        let params = format!(r#"{{{},"ch":"}}",{}}}"#, position, options);
+        let response = request(&mut service, "textDocument/onTypeFormatting", &params, 3);
+        let expected = r#"{"jsonrpc":"2.0","result":null,"id":3}"#;
+        assert_eq!(response, Some(expected.to_owned()));
+    }
+
+    #[test]
+    fn routes_range_formatting() {
+        let (mut service, _) = LspService::new(Mock);
+        initialize(&mut service);
+
+        let range = r#"{"end":{"character":3,"line":4},"start":{"character":0,"line":2}}"#;
+        let params = format!(
+            r#"{{"textDocument":{{"uri":"file:///foo.rs"}},"range":{},"options":{{"tabSize":4,"insertSpaces":true}}}}"#,
+            range
+        );
+        let response = request(&mut service, "textDocument/rangeFormatting", &params, 2);
+        let expected = format!(
+            r#"{{"jsonrpc":"2.0","result":[{{"newText":"formatted","range":{}}}],"id":2}}"#,
+            range
+        );
+        assert_eq!(response, Some(expected));
+    }
+
+    #[test]
+    fn routes_inlay_hints() {
+        let (mut service, _) = LspService::new(Mock);
+        initialize(&mut service);
+
+        let range = r#"{"start":{"line":1,"character":2},"end":{"line":3,"character":0}}"#;
+        let params = format!(
+            r#"{{"textDocument":{{"uri":"file:///foo.rs"}},"range":{}}}"#,
+            range
+        );
+        let response = request(&mut service, "textDocument/inlayHint", &params, 2);
+        let hint = r#"{"kind":1,"label":": i32","position":{"character":2,"line":1}}"#;
+        let expected = format!(r#"{{"jsonrpc":"2.0","result":[{}],"id":2}}"#, hint);
+        assert_eq!(response, Some(expected));
+
+        let response = request(&mut service, "inlayHint/resolve", hint, 3);
+        let resolved = r#"{"kind":1,"label":": i32","position":{"character":2,"line":1},"tooltip":"32-bit signed integer"}"#;
+        let expected = format!(r#"{{"jsonrpc":"2.0","result":{},"id":3}}"#, resolved);
+        assert_eq!(response, Some(expected));
+    }
+
+    #[test]
+    fn routes_semantic_tokens() {
+        let (mut service, _) = LspService::new(Mock);
+        initialize(&mut service);
+
+        let params = r#"{"textDocument":{"uri":"file:///foo.rs"}}"#;
+        let response = request(&mut service, "textDocument/semanticTokens/full", params, 2);
+        let expected = r#"{"jsonrpc":"2.0","result":{"data":[1,4,2,0,0],"resultId":"1"},"id":2}"#;
+        assert_eq!(response, Some(expected.to_owned()));
+
+        let range = r#""range":{"start":{"line":0,"character":0},"end":{"line":9,"character":0}}"#;
+        let params = format!(r#"{{"textDocument":{{"uri":"file:///foo.rs"}},{}}}"#, range);
+        let response = request(
+            &mut service,
+            "textDocument/semanticTokens/range",
+            &params,
+            3,
+        );
+        let expected =
+            r#"{"jsonrpc":"2.0","error":{"code":-32601,"message":"Method not found"},"id":3}"#;
+        assert_eq!(response, Some(expected.to_owned()));
+    }
+
+    #[test]
+    fn rejects_invalid_params_with_validator() {
+        let (mut service, _) = LspService::build(Mock)
+            .validator(|_, params| match params.pointer("/textDocument/uri") {
+                Some(Value::String(uri)) if !uri.starts_with("file:///workspace/") => {
+                    Err(RpcError::invalid_params("outside of workspace"))
+                }
+                _ => Ok(()),
+            })
+            .finish();
+        initialize(&mut service);
+
+        let position = r#""position":{"line":1,"character":2}"#;
+        let params = format!(
+            r#"{{"textDocument":{{"uri":"file:///etc/foo"}},{}}}"#,
+            position
+        );
+        let response = request(&mut service, "textDocument/prepareRename", &params, 2);
+        assert_eq!(
+            response,
+            Some(r#"{"jsonrpc":"2.0","error":{"code":-32602,"message":"outside of workspace"},"id":2}"#.to_owned())
+        );
+
+        let params = format!(
+            r#"{{"textDocument":{{"uri":"file:///workspace/foo"}},{}}}"#,
+            position
+        );
+        let response = request(&mut service, "textDocument/prepareRename", &params, 3);
+        assert!(response.unwrap().contains(r#""result""#));
+    }
+
+    #[test]
+    fn rewrites_incoming_messages() {
+        let (mut service, messages) = LspService::build(Mock)
+            .rewriter(|mut message| {
+                match message["method"].as_str() {
+                    Some("textDocument/legacyHover") => {
+                        message["method"] = "textDocument/hover".into()
+                    }
+                    Some("$/legacySettings") => {
+                        message["method"] = "workspace/didChangeConfiguration".into();
+                        message["params"] = json!({ "settings": message["params"].take() });
+                    }
+                    Some("$/broken") => message = json!({"jsonrpc": "2.0", "id": message["id"]}),
+                    _ => {}
+                }
+                message
+            })
+            .finish();
+        initialize(&mut service);
+
+        let params =
+            r#"{"textDocument":{"uri":"file:///foo.rs"},"position":{"line":1,"character":2}}"#;
+        let response = request(&mut service, "textDocument/legacyHover", params, 2);
+        let expected = r#"{"jsonrpc":"2.0","result":null,"id":2}"#;
+        assert_eq!(response, Some(expected.to_owned()));
+
+        let settings: Incoming =
+            r#"{"jsonrpc":"2.0","method":"$/legacySettings","params":{"foo":1}}"#
+                .parse()
+                .unwrap();
+        assert_eq!(service.call(settings).wait(), Ok(None));
+        let (message, _) = messages.into_future().wait().ok().unwrap();
+        assert!(message.unwrap().contains(r#"{\"foo\":1}"#));
+
+        let response = request(&mut service, "$/broken", "null", 3);
+        let expected =
+            r#"{"jsonrpc":"2.0","error":{"code":-32600,"message":"Invalid request"},"id":3}"#;
+        assert_eq!(response, Some(expected.to_owned()));
+    }
+
+    #[test]
+    fn recovers_from_panicking_rewriter() {
+        let (mut service, _) = LspService::build(Mock)
+            .rewriter(|message| {
+                if message["method"]
+                    .as_str()
+                    .is_some_and(|m| m.starts_with("$/panic"))
+                {
+                    panic!("cannot rewrite message");
+                }
+                message
+            })
+            .finish();
+        initialize(&mut service);
+
+        let response = request(&mut service, "$/panic", "null", 2);
+        let expected =
+            r#"{"jsonrpc":"2.0","error":{"code":-32603,"message":"Internal error"},"id":2}"#;
+        assert_eq!(response, Some(expected.to_owned()));
+
+        let notify: Incoming = r#"{"jsonrpc":"2.0","method":"$/panicNotify"}"#.parse().unwrap();
+        assert_eq!(service.call(notify).wait(), Ok(None));
+
+        let params =
+            r#"{"textDocument":{"uri":"file:///foo.rs"},"position":{"line":1,"character":2}}"#;
+        let response = request(&mut service, "textDocument/hover", params, 3);
+        let expected = r#"{"jsonrpc":"2.0","result":null,"id":3}"#;
+        assert_eq!(response, Some(expected.to_owned()));
+    }
+
+    #[cfg(unix)]
+    #[test]
+    fn exits_when_parent_process_dies() {
+        let mut parent = std::process::Command::new("true").spawn().unwrap();
+        let pid = parent.id();
+        parent.wait().unwrap();
+
+        let (mut service, _) = LspService::build(Mock)
+            .monitor_parent_process(Duration::from_millis(10))
+            .finish();
+
+        let initialize: Incoming = format!(
+            r#"{{"jsonrpc":"2.0","method":"initialize","params":{{"capabilities":{{}},"processId":{}}},"id":1}}"#,
+            pid
+        )
+        .parse()
+        .unwrap();
+        service.call(initialize).wait().unwrap();
+
+        for _ in 0..500 {
+            if service.poll_ready() == Ok(Async::NotReady) {
+                return;
+            }
+            std::thread::sleep(Duration::from_millis(10));
+        }
+
+        panic!("service did not exit after parent process died");
+    }
+
+    #[test]
+    fn buffers_messages_until_initialized() {
+        let (mut service, mut messages) =
+            LspService::build(Mock).buffer_until_initialized().finish();
+        initialize(&mut service);
+
+        let pending = future::lazy(|| Ok::<_, ()>(messages.poll())).wait();
+        assert_eq!(pending, Ok(Ok(Async::NotReady)));
+
+        let initialized: Incoming = r#"{"jsonrpc":"2.0","method":"initialized","params":{}}"#
+            .parse()
+            .unwrap();
+        assert_eq!(service.call(initialized).wait(), Ok(None));
+
+        drop(service);
+        assert_eq!(
+            messages.collect().wait(),
+            Ok(vec![
+                r#"{"jsonrpc":"2.0","method":"textDocument/publishDiagnostics","params":{"diagnostics":[],"uri":"file:///foo.rs"}}"#.to_owned()
+            ])
+        );
+    }
+
+    #[test]
+    fn passes_signature_help_context() {
+        let (mut service, _) = LspService::new(Mock);
+        initialize(&mut service);
+
+        let position =
+            r#""textDocument":{"uri":"file:///foo.rs"},"position":{"line":0,"character":4}"#;
+        let active =
+            r#"{"activeParameter":1,"activeSignature":2,"signatures":[{"label":"foo(a, b)"}]}"#;
+        let context = format!(
+            r#""context":{{"triggerKind":3,"isRetrigger":true,"triggerCharacter":",","activeSignatureHelp":{}}}"#,
+            active
+        );
+        let params = format!("{{{},{}}}", position, context);
+        let response = request(&mut service, "textDocument/signatureHelp", &params, 2);
+        let expected = format!(r#"{{"jsonrpc":"2.0","result":{},"id":2}}"#, active);
+        assert_eq!(response, Some(expected));
+
+        let context = r#""context":{"triggerKind":2,"isRetrigger":false,"triggerCharacter":"("}"#;
+        let params = format!("{{{},{}}}", position, context);
+        let response = request(&mut service, "textDocument/signatureHelp", &params, 3);
+        assert_eq!(
+            response,
+            Some(
+                r#"{"jsonrpc":"2.0","result":{"activeSignature":0,"signatures":[]},"id":3}"#
+                    .to_owned()
+            )
+        );
     }
 }
@@ -1,17 +1,36 @@
 //! Asynchronous `tower` server with an stdio transport.
 
 use std::error::Error;
+#[cfg(feature = "runtime")]
+use std::io;
+use std::io::Write;
+use std::sync::atomic::{AtomicBool, Ordering};
+use std::sync::Arc;
 
-use futures::future::{Empty, IntoStream};
-use futures::sync::mpsc;
-use futures::{future, Future, Poll, Sink, Stream};
-use log::error;
-use tokio_codec::{FramedRead, FramedWrite};
+use bytes::{Bytes, BytesMut};
+use futures::future::{Either, Empty, IntoStream};
+use futures::sync::{mpsc, oneshot};
+#[cfg(feature = "runtime")]
+use futures::Async;
+use futures::{future, stream, try_ready, Future, Poll, Sink, Stream};
+use jsonrpc_core::types::{Id, Output, Version};
+use log::{debug, error, warn};
+use tokio_codec::{Framed, FramedParts, FramedWrite};
 use tokio_io::{AsyncRead, AsyncWrite};
 use tower_service::Service;
 
-use super::codec::LanguageServerCodec;
+use super::codec::{CodecConfig, LanguageServerCodec, ParseError, WireLog};
+#[cfg(feature = "metrics")]
+use super::codec::{CodecMetrics, Metrics};
+use super::error::request_failed_error;
 use super::message::Incoming;
+#[cfg(feature = "runtime")]
+use super::MessageStream;
+use super::{LspService, Printer};
+
+const DEFAULT_READ_BUFFER_CAPACITY: usize = 8 * 1024;
+
+type IncomingStream = Box<dyn Stream<Item = Incoming, Error = ParseError> + Send>;
 
 /// Server for processing requests and responses on `stdin` and `stdout`.
 #[derive(Debug)]
@@ -19,6 +38,15 @@ pub struct Server<I, O, S = Nothing> {
     stdin: I,
     stdout: O,
     interleave: S,
+    validate_utf8: bool,
+    read_buffer_capacity: usize,
+    max_message_len: Option<usize>,
+    skip_oversized: bool,
+    pretty_json: bool,
+    wire_log: Option<WireLog>,
+    trace: Option<Printer>,
+    #[cfg(feature = "metrics")]
+    metrics: Option<Metrics>,
 }
 
 impl<I, O> Server<I, O, Nothing>
@@ -32,8 +60,52 @@ where
             stdin,
             stdout,
             interleave: Nothing::new(),
+            validate_utf8: true,
+            read_buffer_capacity: DEFAULT_READ_BUFFER_CAPACITY,
+            max_message_len: None,
+            skip_oversized: false,
+            pretty_json: false,
+            wire_log: None,
+            trace: None,
+            #[cfg(feature = "metrics")]
+            metrics: None,
         }
     }
+
+    /// Returns the decoded `stdin` stream and the framed `stdout` sink instead of spawning a
+    /// service.
+    ///
+    /// This is an escape hatch for driving the protocol by hand, e.g. to inject heartbeats or to
+    /// multiplex several services over the same transport. Decoded messages may be passed to a
+    /// service, and its responses sent into the sink. Since messages of the service to the client
+    /// have to be forwarded into the sink by hand as well, this is not available once a stream
+    /// has been [`interleave`](#method.interleave)d.
+    ///
+    /// All options of this builder apply to both halves. Frames exceeding the maximum message
+    /// length are yielded as `ParseError::MessageTooLarge` errors, after which the stream may be
+    /// polled again to carry on with the next message. If
+    /// [`skip_oversized_messages`](#method.skip_oversized_messages) is enabled, they are skipped
+    /// with a warning instead. Unlike with [`serve`](#method.serve), no error response is sent
+    /// for such requests, as the sink is in the hands of the caller.
+    pub fn into_framed(
+        self,
+    ) -> (
+        impl Stream<Item = Incoming, Error = ParseError> + Send,
+        FramedWrite<O, LanguageServerCodec<String>>,
+    ) {
+        let skip_oversized = self.skip_oversized;
+        let (reader, writer, _) = self.into_parts();
+        let reader = reader
+            .then(move |result| match result {
+                Err(ParseError::MessageTooLarge(len, _)) if skip_oversized => {
+                    warn!("skipped message of {} bytes exceeding maximum length", len);
+                    Ok(None)
+                }
+                result => result.map(Some),
+            })
+            .filter_map(|message| message);
+        (reader, writer)
+    }
 }
 
 impl<I, O, S> Server<I, O, S>
@@ -51,21 +123,119 @@ where
             stdin: self.stdin,
             stdout: self.stdout,
             interleave: stream,
+            validate_utf8: self.validate_utf8,
+            read_buffer_capacity: self.read_buffer_capacity,
+            max_message_len: self.max_message_len,
+            skip_oversized: self.skip_oversized,
+            pretty_json: self.pretty_json,
+            wire_log: self.wire_log,
+            trace: self.trace,
+            #[cfg(feature = "metrics")]
+            metrics: self.metrics,
         }
     }
 
+    /// Skips the explicit UTF-8 validation of incoming message bodies.
+    ///
+    /// Message bodies are always validated by the JSON parser anyway, so this check is redundant
+    /// for trusted transports, e.g. a local pipe to the editor. Invalid UTF-8 in the input will
+    /// still be rejected, only later on.
+    pub fn skip_utf8_validation(mut self) -> Self {
+        self.validate_utf8 = false;
+        self
+    }
+
+    /// Sets the initial capacity of the buffer for reading messages from `stdin`, in bytes.
+    ///
+    /// The buffer grows as needed to fit larger messages, so this only avoids repeated
+    /// reallocations for servers which expect to receive large messages. Defaults to 8 KiB, which
+    /// is also the minimum.
+    pub fn read_buffer_capacity(mut self, capacity: usize) -> Self {
+        self.read_buffer_capacity = capacity;
+        self
+    }
+
+    /// Sets the maximum permitted length of a message body read from `stdin`, in bytes.
+    ///
+    /// By default, receiving a longer message is a fatal decoding error which stops the server,
+    /// unless [`skip_oversized_messages`](#method.skip_oversized_messages) is enabled. There is no
+    /// limit by default.
+    pub fn max_message_len(mut self, len: usize) -> Self {
+        self.max_message_len = Some(len);
+        self
+    }
+
+    /// Skips messages exceeding the [`max_message_len`](#method.max_message_len) instead of
+    /// stopping the server.
+    ///
+    /// The bytes of an oversized message are discarded without buffering them. If the message was
+    /// a request whose ID could be recovered, a [`RequestFailed`] error is sent in response.
+    ///
+    /// [`RequestFailed`]: https://microsoft.github.io/language-server-protocol/specification#errorCodes
+    pub fn skip_oversized_messages(mut self) -> Self {
+        self.skip_oversized = true;
+        self
+    }
+
+    /// Pretty-prints the JSON of every message written to `stdout`, for debugging.
+    ///
+    /// This makes the output, e.g. as logged with [`log_wire_bytes`](#method.log_wire_bytes),
+    /// easier for humans to read at the cost of extra processing and larger messages, so it should
+    /// not be enabled in production.
+    pub fn pretty_json(mut self) -> Self {
+        self.pretty_json = true;
+        self
+    }
+
+    /// Copies the raw bytes of every message read from `stdin` and written to `stdout` into the
+    /// given writer, e.g. a log file, for debugging protocol issues.
+    ///
+    /// Each message is prefixed with a direction marker: `<-- ` for received messages and `--> `
+    /// for sent messages, and is followed by a newline.
+    pub fn log_wire_bytes<W: Write + Send + 'static>(mut self, writer: W) -> Self {
+        self.wire_log = Some(WireLog::new(writer));
+        self
+    }
+
+    /// Logs a hex dump of the bytes around the failure when a message cannot be decoded, while
+    /// the client has set the trace level of the given service to verbose.
+    ///
+    /// This helps diagnosing framing quirks of specific clients in the field. At lower trace
+    /// levels, decoding errors are logged tersely and no dump is formatted.
+    pub fn trace_decode_errors(mut self, service: &LspService) -> Self {
+        self.trace = Some(service.printer());
+        self
+    }
+
+    /// Reports the time spent decoding each message read from `stdin` and encoding each message
+    /// written to `stdout` to the given metrics hook.
+    ///
+    /// Only available with the `metrics` feature enabled.
+    #[cfg(feature = "metrics")]
+    pub fn record_metrics(mut self, metrics: Arc<dyn CodecMetrics>) -> Self {
+        self.metrics = Some(Metrics(metrics));
+        self
+    }
+
     /// Spawns the service with messages read through `stdin` and responses printed to `stdout`.
+    ///
+    /// Any service producing an optional response string is accepted, so the base `LspService`
+    /// may be wrapped in arbitrary Tower middleware (e.g. timeouts or rate limits) beforehand. The
+    /// service is polled for readiness before each message is passed to it, and serving stops
+    /// once it fails to become ready.
+    ///
+    /// Once `stdin` is closed or fails, requests which are still being processed are cancelled by
+    /// dropping their response futures, as the client is no longer around to receive them.
     pub fn serve<T>(self, service: T) -> impl Future<Item = (), Error = ()> + Send
     where
-        T: Service<Incoming, Response = String> + Send + 'static,
+        T: Service<Incoming, Response = Option<String>> + Send + 'static,
         T::Error: Into<Box<dyn Error + Send + Sync>>,
         T::Future: Send,
     {
         let (sender, receiver) = mpsc::channel(1);
 
-        let framed_stdin = FramedRead::new(self.stdin, LanguageServerCodec::default());
-        let framed_stdout = FramedWrite::new(self.stdout, LanguageServerCodec::default());
-        let interleave = self.interleave;
+        let skip_oversized = self.skip_oversized;
+        let (framed_stdin, framed_stdout, interleave) = self.into_parts();
 
         future::lazy(move || {
             let printer = receiver
@@ -76,22 +246,278 @@ where
 
             tokio_executor::spawn(printer);
 
-            framed_stdin
-                .map(Incoming::from)
-                .map_err(|e| error!("failed to decode message: {}", e))
-                .fold(service, move |mut service, line| {
+            // Read messages in a separate task, so that the end of the input is detected even while
+            // a request is still being processed.
+            let (message_tx, message_rx) = mpsc::channel(0);
+            let (disconnect_tx, disconnect_rx) = oneshot::channel();
+            let (stop_tx, stop_rx) = oneshot::channel::<()>();
+            let decode_failed = Arc::new(AtomicBool::new(false));
+
+            let failed = decode_failed.clone();
+            let oversized_tx = sender.clone();
+            let reader = framed_stdin
+                .then(move |result| match result {
+                    Err(ParseError::MessageTooLarge(len, id)) if skip_oversized => {
+                        warn!("skipped message of {} bytes exceeding maximum length", len);
+                        let response = id.map(|id| message_too_large(len, id));
+                        let sent = oversized_tx.clone().send_all(stream::iter_ok(response));
+                        Either::A(sent.then(|_| Ok(None)))
+                    }
+                    result => Either::B(future::result(result.map(Some))),
+                })
+                .filter_map(|message| message)
+                .map_err(move |e| {
+                    error!("failed to decode message: {}", e);
+                    failed.store(true, Ordering::SeqCst);
+                })
+                .forward(message_tx.sink_map_err(|_| ()))
+                .select2(stop_rx)
+                .then(move |_| disconnect_tx.send(()));
+
+            tokio_executor::spawn(reader);
+
+            let disconnected = disconnect_rx.shared();
+            message_rx
+                .fold(service, move |service, message| {
                     let sender = sender.clone();
-                    service
-                        .call(line)
-                        .map_err(|e| error!("{}", e.into()))
-                        .and_then(move |resp| sender.send(resp).map_err(|_| unreachable!()))
-                        .then(move |_| Ok(service))
+                    let disconnected = disconnected.clone();
+                    let mut service = Some(service);
+                    let ready = future::poll_fn(move || {
+                        // Layers such as rate or concurrency limits reject calls made before the
+                        // service has reported readiness.
+                        try_ready!(service.as_mut().expect("polled after ready").poll_ready());
+
+                        Ok(service.take().unwrap().into())
+                    });
+                    ready
+                        .map_err(|e: T::Error| {
+                            error!("service failed, stopping server: {}", e.into())
+                        })
+                        .and_then(move |mut service| {
+                            let response = service.call(message).select2(disconnected);
+                            response.then(move |result| match result {
+                                Ok(Either::A((Some(resp), _))) => {
+                                    Either::A(sender.send(resp).then(|result| {
+                                        // The output stream only stops receiving once
+                                        // writing to `stdout` has failed, so there is no
+                                        // point in processing further messages.
+                                        result.map(|_| service).map_err(|_| {
+                                            error!("output stream closed, stopping server");
+                                        })
+                                    }))
+                                }
+                                Ok(Either::A((None, _))) => Either::B(future::ok(service)),
+                                Ok(Either::B(_)) | Err(Either::B(_)) => {
+                                    debug!("input stream closed, cancelling pending request");
+                                    Either::B(future::ok(service))
+                                }
+                                Err(Either::A((e, _))) => {
+                                    error!("{}", e.into());
+                                    Either::B(future::ok(service))
+                                }
+                            })
+                        })
+                })
+                .then(move |result| {
+                    // Stop reading messages if processing ends before the input does.
+                    drop(stop_tx);
+                    match result {
+                        Ok(_) if !decode_failed.load(Ordering::SeqCst) => Ok(()),
+                        _ => Err(()),
+                    }
                 })
-                .map(|_| ())
+        })
+    }
+
+    /// Spawns the service like [`serve`](#method.serve) as a task on the default executor,
+    /// returning a handle for monitoring it.
+    ///
+    /// This allows hosts embedding the server to carry on with their own control flow, while
+    /// still being able to check on the server and to wait for it to finish. Dropping the handle
+    /// does not stop the server.
+    ///
+    /// # Panics
+    ///
+    /// Panics if called outside of the context of an executor, e.g. a Tokio runtime.
+    pub fn spawn<T>(self, service: T) -> ServerHandle
+    where
+        T: Service<Incoming, Response = Option<String>> + Send + 'static,
+        T::Error: Into<Box<dyn Error + Send + Sync>>,
+        T::Future: Send,
+    {
+        let running = Arc::new(AtomicBool::new(true));
+        let (tx, rx) = oneshot::channel();
+
+        let still_running = running.clone();
+        tokio_executor::spawn(self.serve(service).then(move |result| {
+            still_running.store(false, Ordering::SeqCst);
+            let _ = tx.send(result);
+            Ok(())
+        }));
+
+        ServerHandle { running, done: rx }
+    }
+
+    /// Splits the server into the decoded messages read from `stdin`, the framed `stdout` sink and
+    /// the interleaved stream.
+    fn into_parts(
+        self,
+    ) -> (
+        IncomingStream,
+        FramedWrite<O, LanguageServerCodec<String>>,
+        S,
+    ) {
+        let encoder = self.new_codec::<String>();
+        let reader: IncomingStream = if self.validate_utf8 {
+            let codec = self.new_codec::<String>();
+            Box::new(framed_read(self.stdin, codec, self.read_buffer_capacity).map(Incoming::from))
+        } else {
+            let codec = self.new_codec::<Bytes>();
+            Box::new(framed_read(self.stdin, codec, self.read_buffer_capacity).map(Incoming::from))
+        };
+        let writer = FramedWrite::new(self.stdout, encoder);
+        (reader, writer, self.interleave)
+    }
+
+    fn new_codec<T>(&self) -> LanguageServerCodec<T> {
+        let config = CodecConfig::default()
+            .max_message_len(self.max_message_len)
+            .pretty_json(self.pretty_json);
+        let mut codec = LanguageServerCodec::with_config(config);
+        if let Some(ref wire_log) = self.wire_log {
+            codec.set_wire_log(wire_log.clone());
+        }
+        if let Some(ref printer) = self.trace {
+            codec.set_trace(printer.clone());
+        }
+
+        #[cfg(feature = "metrics")]
+        {
+            if let Some(ref metrics) = self.metrics {
+                codec.set_metrics(metrics.0.clone());
+            }
+        }
+
+        codec
+    }
+}
+
+/// Handle to a server spawned with [`Server::spawn`].
+///
+/// [`Server::spawn`]: ./struct.Server.html#method.spawn
+#[derive(Debug)]
+pub struct ServerHandle {
+    running: Arc<AtomicBool>,
+    done: oneshot::Receiver<Result<(), ()>>,
+}
+
+impl ServerHandle {
+    /// Returns whether the server is still running.
+    pub fn is_running(&self) -> bool {
+        self.running.load(Ordering::SeqCst)
+    }
+
+    /// Returns a future which resolves once the server has stopped.
+    ///
+    /// Like the future returned by [`Server::serve`], this fails if a message could not be decoded.
+    /// It also fails if the server task was dropped before completing, e.g. because the executor
+    /// was shut down.
+    ///
+    /// [`Server::serve`]: ./struct.Server.html#method.serve
+    pub fn join(self) -> impl Future<Item = (), Error = ()> + Send {
+        self.done.then(|result| match result {
+            Ok(result) => result,
+            Err(_) => Err(()),
         })
     }
 }
 
+/// Returns a `RequestFailed` error response for a request which was skipped for being too large.
+fn message_too_large(len: usize, id: Id) -> String {
+    let message = format!("message of {} bytes exceeds maximum length", len);
+    let output = Output::from(Err(request_failed_error(message)), id, Some(Version::V2));
+    serde_json::to_string(&output).unwrap()
+}
+
+/// Wraps `io` in a stream of frames decoded by `codec`, with a read buffer of the given capacity.
+///
+/// Unlike `FramedRead`, `Framed` accepts a preallocated read buffer and acts as a plain stream as
+/// long as it is not used as a sink.
+fn framed_read<I, C>(io: I, codec: C, capacity: usize) -> Framed<I, C> {
+    let mut parts = FramedParts::new(io, codec);
+    parts.read_buf = BytesMut::with_capacity(capacity);
+    Framed::from_parts(parts)
+}
+
+/// Serves the language server on the `stdin` and `stdout` of the current process until the client
+/// sends the [`exit`] notification.
+///
+/// This is a shorthand for the most common setup of a `Server`, interleaving the `messages` of
+/// the service with its responses. Only available with the `runtime` feature enabled, and must be
+/// run on the default Tokio runtime.
+///
+/// Every chunk of output is written while holding the lock on `stdout`, so that frames are never
+/// interleaved with output from other writers in the same process, e.g. a stray `println!`.
+///
+/// # Example
+///
+/// ```rust,no_run
+/// # use tower_lsp::{LanguageServer, LspService};
+/// # fn run<T: LanguageServer>(backend: T) {
+/// let (service, messages) = LspService::new(backend);
+/// tokio::run(tower_lsp::serve_stdio(service, messages));
+/// # }
+/// ```
+///
+/// [`exit`]: https://microsoft.github.io/language-server-protocol/specification#exit
+#[cfg(feature = "runtime")]
+pub fn serve_stdio(
+    service: LspService,
+    messages: MessageStream,
+) -> impl Future<Item = (), Error = ()> + Send {
+    let handle = service.close_handle();
+    let server = Server::new(tokio::io::stdin(), LockedStdout)
+        .interleave(messages)
+        .serve(service);
+    handle.run_until_exit(server)
+}
+
+/// Writes to `stdout`, holding its lock until each chunk has been written and flushed.
+#[cfg(feature = "runtime")]
+#[derive(Debug)]
+struct LockedStdout;
+
+#[cfg(feature = "runtime")]
+impl Write for LockedStdout {
+    fn write(&mut self, buf: &[u8]) -> io::Result<usize> {
+        let write = || {
+            let stdout = io::stdout();
+            let mut stdout = stdout.lock();
+            stdout.write_all(buf)?;
+            stdout.flush()?;
+            Ok(buf.len())
+        };
+
+        // Let the thread pool know that this may block, if running on one.
+        match tokio_threadpool::blocking(write) {
+            Ok(Async::Ready(result)) => result,
+            Ok(Async::NotReady) => Err(io::ErrorKind::WouldBlock.into()),
+            Err(_) => write(),
+        }
+    }
+
+    fn flush(&mut self) -> io::Result<()> {
+        Ok(())
+    }
+}
+
+#[cfg(feature = "runtime")]
+impl AsyncWrite for LockedStdout {
+    fn shutdown(&mut self) -> Poll<(), io::Error> {
+        Ok(Async::Ready(()))
+    }
+}
+
 #[doc(hidden)]
 #[derive(Debug)]
 pub struct Nothing(IntoStream<Empty<String, ()>>);
@@ -113,18 +539,28 @@ impl Stream for Nothing {
 
 #[cfg(test)]
 mod tests {
-    use std::io::Cursor;
+    use std::io::{self, Cursor, Read};
+    use std::time::Duration;
 
-    use futures::{future::FutureResult, stream, Async};
+    use futures::{future::FutureResult, stream, task, Async};
+    use jsonrpc_core::{BoxFuture, Result};
+    use lsp_types::{
+        CompletionParams, CompletionResponse, ConfigurationItem, DocumentHighlight,
+        DocumentHighlightParams, ExecuteCommandParams, Hover, HoverParams, InitializeParams,
+        InitializeResult, WorkspaceSymbolParams, WorkspaceSymbolResponse,
+    };
+    use serde_json::{json, Value};
     use tokio::runtime::current_thread;
+    use tower::ServiceBuilder;
 
     use super::*;
+    use crate::{read_message, LanguageServer};
 
     #[derive(Debug)]
     struct MockService;
 
     impl Service<Incoming> for MockService {
-        type Response = String;
+        type Response = Option<String>;
         type Error = String;
         type Future = FutureResult<Self::Response, Self::Error>;
 
@@ -133,11 +569,53 @@ mod tests {
         }
 
         fn call(&mut self, request: Incoming) -> Self::Future {
-            future::ok(request.to_string())
+            future::ok(Some(request.to_string()))
         }
     }
 
-    fn mock_stdio() -> (Cursor<Box<[u8]>>, Cursor<Box<[u8]>>) {
+    #[derive(Debug)]
+    struct MockServer;
+
+    impl LanguageServer for MockServer {
+        type ShutdownFuture = BoxFuture<()>;
+        type SymbolFuture = BoxFuture<Option<WorkspaceSymbolResponse>>;
+        type ExecuteFuture = BoxFuture<Option<Value>>;
+        type CompletionFuture = BoxFuture<Option<CompletionResponse>>;
+        type HoverFuture = BoxFuture<Option<Hover>>;
+        type HighlightFuture = BoxFuture<Option<Vec<DocumentHighlight>>>;
+
+        fn initialize(&self, _: &Printer, _: InitializeParams) -> Result<InitializeResult> {
+            Ok(InitializeResult::default())
+        }
+
+        fn shutdown(&self) -> Self::ShutdownFuture {
+            Box::new(future::ok(()))
+        }
+
+        fn symbol(&self, _: WorkspaceSymbolParams) -> Self::SymbolFuture {
+            Box::new(future::ok(None))
+        }
+
+        fn execute_command(&self, _: &Printer, _: ExecuteCommandParams) -> Self::ExecuteFuture {
+            Box::new(future::ok(None))
+        }
+
+        fn completion(&self, _: CompletionParams) -> Self::CompletionFuture {
+            Box::new(future::ok(None))
+        }
+
+        fn hover(&self, _: HoverParams) -> Self::HoverFuture {
+            Box::new(future::ok(None))
+        }
+
+        fn document_highlight(&self, _: DocumentHighlightParams) -> Self::HighlightFuture {
+            Box::new(future::ok(None))
+        }
+    }
+
+    type MockStdio = Cursor<Box<[u8]>>;
+
+    fn mock_stdio() -> (MockStdio, MockStdio) {
         let message = r#"{"jsonrpc":"2.0","method":"initialized"}"#;
         let stdin = format!("Content-Length: {}\r\n\r\n{}", message.len(), message);
         (
@@ -159,16 +637,360 @@ mod tests {
         current_thread::block_on_all(server).expect("failed to decode/encode message");
     }
 
+    #[test]
+    fn spawns_server_with_handle() {
+        let (stdin, stdout) = mock_stdio();
+        let server = Server::new(stdin, stdout);
+
+        let joined = current_thread::block_on_all(future::lazy(move || {
+            let handle = server.spawn(MockService);
+            assert!(handle.is_running());
+            handle.join()
+        }));
+        assert_eq!(joined, Ok(()));
+    }
+
+    #[test]
+    fn serves_with_large_read_buffer() {
+        let body = "x".repeat(32 * 1024);
+        let message = format!(r#"{{"jsonrpc":"2.0","method":"{}"}}"#, body);
+        let stdin = format!("Content-Length: {}\r\n\r\n{}", message.len(), message);
+        let stdin = Cursor::new(stdin.into_bytes());
+
+        // An empty interleaved stream lets the printer task finish once the input is exhausted.
+        let stdout = Cursor::new(Vec::new());
+        let server = Server::new(stdin, stdout)
+            .interleave(stream::iter_ok(Vec::new()))
+            .read_buffer_capacity(64 * 1024)
+            .serve(MockService);
+
+        current_thread::block_on_all(server).expect("failed to decode/encode message");
+    }
+
+    #[test]
+    fn exposes_framed_transport() {
+        let (stdin, _) = mock_stdio();
+        let stdout = Cursor::new(Vec::new());
+        let (reader, writer) = Server::new(stdin, stdout).into_framed();
+
+        let message = r#"{"jsonrpc":"2.0","method":"initialized"}"#.to_owned();
+        let messages = reader.collect().wait().unwrap();
+        assert_eq!(messages, vec![Incoming::from(message.clone())]);
+
+        let writer = writer.send(message.clone()).wait().unwrap();
+        let written = String::from_utf8(writer.get_ref().get_ref().clone()).unwrap();
+        let expected = format!("Content-Length: {}\r\n\r\n{}", message.len(), message);
+        assert_eq!(written, expected);
+    }
+
     #[test]
     fn interleaves_messages() {
         let message = r#"{"jsonrpc":"2.0","method":"initialized"}"#.to_owned();
         let messages = stream::iter_ok(vec![message]);
 
-        let (stdin, stdout) = mock_stdio();
+        // Unlike the other tests, the output must be writable, as the interleaved message may be
+        // written before the response is enqueued.
+        let (stdin, _) = mock_stdio();
+        let stdout = Cursor::new(Vec::new());
         let server = Server::new(stdin, stdout)
             .interleave(messages)
             .serve(MockService);
 
         current_thread::block_on_all(server).expect("failed to decode/encode message");
     }
+
+    /// Reader which yields to the executor before returning each chunk of its data.
+    struct YieldingReader {
+        data: Cursor<Vec<u8>>,
+        chunk_len: usize,
+        yielded: bool,
+    }
+
+    impl Read for YieldingReader {
+        fn read(&mut self, buf: &mut [u8]) -> io::Result<usize> {
+            if !self.yielded {
+                self.yielded = true;
+                task::current().notify();
+                return Err(io::ErrorKind::WouldBlock.into());
+            }
+
+            self.yielded = false;
+            let len = buf.len().min(self.chunk_len);
+            self.data.read(&mut buf[..len])
+        }
+    }
+
+    impl AsyncRead for YieldingReader {}
+
+    /// Writer whose reading end has been closed.
+    struct BrokenPipe;
+
+    impl Write for BrokenPipe {
+        fn write(&mut self, _: &[u8]) -> io::Result<usize> {
+            Err(io::ErrorKind::BrokenPipe.into())
+        }
+
+        fn flush(&mut self) -> io::Result<()> {
+            Err(io::ErrorKind::BrokenPipe.into())
+        }
+    }
+
+    impl AsyncWrite for BrokenPipe {
+        fn shutdown(&mut self) -> Poll<(), io::Error> {
+            Ok(Async::Ready(()))
+        }
+    }
+
+    #[test]
+    fn stops_when_output_closes() {
+        let message = r#"{"jsonrpc":"2.0","method":"initialized"}"#;
+        let frame = format!("Content-Length: {}\r\n\r\n{}", message.len(), message);
+        let stdin = YieldingReader {
+            data: Cursor::new(frame.repeat(3).into_bytes()),
+            chunk_len: frame.len(),
+            yielded: false,
+        };
+
+        let server = Server::new(stdin, BrokenPipe).serve(MockService);
+        assert_eq!(current_thread::block_on_all(server), Err(()));
+    }
+
+    /// Service whose responses never resolve, recording when they are dropped.
+    #[derive(Debug, Default)]
+    struct PendingService(Arc<AtomicBool>);
+
+    #[derive(Debug)]
+    struct PendingResponse(Arc<AtomicBool>);
+
+    impl Future for PendingResponse {
+        type Item = Option<String>;
+        type Error = String;
+
+        fn poll(&mut self) -> Poll<Self::Item, Self::Error> {
+            Ok(Async::NotReady)
+        }
+    }
+
+    impl Drop for PendingResponse {
+        fn drop(&mut self) {
+            self.0.store(true, Ordering::SeqCst);
+        }
+    }
+
+    impl Service<Incoming> for PendingService {
+        type Response = Option<String>;
+        type Error = String;
+        type Future = PendingResponse;
+
+        fn poll_ready(&mut self) -> Poll<(), Self::Error> {
+            Ok(Async::Ready(()))
+        }
+
+        fn call(&mut self, _: Incoming) -> Self::Future {
+            PendingResponse(self.0.clone())
+        }
+    }
+
+    #[test]
+    fn cancels_pending_request_on_disconnect() {
+        let service = PendingService::default();
+        let dropped = service.0.clone();
+
+        let (stdin, stdout) = mock_stdio();
+        let server = Server::new(stdin, stdout).serve(service);
+
+        let mut runtime = current_thread::Runtime::new().unwrap();
+        runtime.block_on(server).unwrap();
+        assert!(dropped.load(Ordering::SeqCst));
+    }
+
+    #[test]
+    fn serves_with_middleware() {
+        let service = ServiceBuilder::new()
+            .timeout(Duration::from_secs(1))
+            .service(MockService);
+
+        let (stdin, stdout) = mock_stdio();
+        let server = Server::new(stdin, stdout).serve(service);
+        current_thread::block_on_all(server).expect("failed to decode/encode message");
+    }
+
+    #[test]
+    fn polls_middleware_for_readiness() {
+        let service = ServiceBuilder::new()
+            .concurrency_limit(1)
+            .rate_limit(1, Duration::from_millis(10))
+            .service(MockService);
+
+        let message = r#"{"jsonrpc":"2.0","method":"initialized"}"#;
+        let frame = format!("Content-Length: {}\r\n\r\n{}", message.len(), message);
+        let stdin = Cursor::new(frame.repeat(3).into_bytes());
+        let stdout = SharedBuffer::default();
+        let server = Server::new(stdin, stdout.clone())
+            .interleave(stream::iter_ok(Vec::new()))
+            .serve(service);
+        current_thread::block_on_all(server).expect("failed to decode/encode message");
+
+        let output = stdout.0.lock().unwrap().clone();
+        let mut output = output.as_slice();
+        for _ in 0..3 {
+            let echoed = read_message(&mut output).unwrap();
+            assert_eq!(
+                echoed,
+                r#"{"jsonrpc":"2.0","method":"initialized","params":null}"#
+            );
+        }
+        assert!(output.is_empty());
+    }
+
+    #[test]
+    fn delivers_requests_sent_before_serving() {
+        let (service, messages) = LspService::new(MockServer);
+        let item = ConfigurationItem {
+            scope_uri: None,
+            section: Some("foo".into()),
+        };
+        drop(service.printer().configuration(vec![item]));
+
+        let frames: String = [
+            r#"{"jsonrpc":"2.0","method":"initialize","params":{"capabilities":{}},"id":1}"#,
+            r#"{"jsonrpc":"2.0","method":"initialized","params":{}}"#,
+        ]
+        .iter()
+        .map(|message| format!("Content-Length: {}\r\n\r\n{}", message.len(), message))
+        .collect();
+        let stdout = SharedBuffer::default();
+        let server = Server::new(Cursor::new(frames.into_bytes()), stdout.clone())
+            .interleave(messages)
+            .serve(service);
+        current_thread::block_on_all(server).expect("failed to decode/encode message");
+
+        let output = stdout.0.lock().unwrap().clone();
+        let mut output = output.as_slice();
+        let response: Value = serde_json::from_str(&read_message(&mut output).unwrap()).unwrap();
+        assert_eq!(response["id"], json!(1));
+        let request: Value = serde_json::from_str(&read_message(&mut output).unwrap()).unwrap();
+        assert_eq!(request["method"], json!("workspace/configuration"));
+        assert!(output.is_empty());
+    }
+
+    #[test]
+    fn serves_without_utf8_validation() {
+        let (stdin, stdout) = mock_stdio();
+        let server = Server::new(stdin, stdout)
+            .skip_utf8_validation()
+            .serve(MockService);
+
+        current_thread::block_on_all(server).expect("failed to decode/encode message");
+    }
+
+    /// Writer whose contents can be inspected after it has been moved into the server.
+    #[derive(Clone, Debug, Default)]
+    struct SharedBuffer(Arc<std::sync::Mutex<Vec<u8>>>);
+
+    impl Write for SharedBuffer {
+        fn write(&mut self, buf: &[u8]) -> io::Result<usize> {
+            self.0.lock().unwrap().write(buf)
+        }
+
+        fn flush(&mut self) -> io::Result<()> {
+            Ok(())
+        }
+    }
+
+    impl AsyncWrite for SharedBuffer {
+        fn shutdown(&mut self) -> Poll<(), io::Error> {
+            Ok(Async::Ready(()))
+        }
+    }
+
+    #[test]
+    fn yields_oversized_messages_from_framed_transport() {
+        let stdout = Cursor::new(Vec::new());
+        let (reader, _) = Server::new(oversized_stdin(), stdout)
+            .max_message_len(256)
+            .into_framed();
+
+        let (error, reader) = match reader.into_future().wait() {
+            Err((error, reader)) => (error, reader),
+            Ok((message, _)) => panic!("unexpected message: {:?}", message),
+        };
+        assert!(matches!(
+            error,
+            ParseError::MessageTooLarge(1091, Some(Id::Num(1)))
+        ));
+
+        let messages = reader.collect().wait().unwrap();
+        let message = r#"{"jsonrpc":"2.0","method":"initialized"}"#.to_owned();
+        assert_eq!(messages, vec![Incoming::from(message)]);
+    }
+
+    #[test]
+    fn applies_options_to_framed_transport() {
+        let stdout = Cursor::new(Vec::new());
+        let (reader, _) = Server::new(oversized_stdin(), stdout)
+            .max_message_len(256)
+            .skip_oversized_messages()
+            .into_framed();
+        let messages = reader.collect().wait().unwrap();
+        let message = r#"{"jsonrpc":"2.0","method":"initialized"}"#.to_owned();
+        assert_eq!(messages, vec![Incoming::from(message)]);
+
+        let body = b"{\"jsonrpc\":\"2.0\",\"method\":\"\xff\"}";
+        let mut frame = format!("Content-Length: {}\r\n\r\n", body.len()).into_bytes();
+        frame.extend_from_slice(body);
+        let stdout = Cursor::new(Vec::new());
+        let (reader, _) = Server::new(Cursor::new(frame), stdout)
+            .skip_utf8_validation()
+            .into_framed();
+        let messages = reader.collect().wait().unwrap();
+        let expected = Incoming::Invalid("{\"jsonrpc\":\"2.0\",\"method\":\"\u{fffd}\"}".into());
+        assert_eq!(messages, vec![expected]);
+    }
+
+    fn oversized_stdin() -> Cursor<Vec<u8>> {
+        let large = format!(
+            r#"{{"jsonrpc":"2.0","method":"foo","params":{{"id":2,"text":"{}"}},"id":1}}"#,
+            "x".repeat(1024)
+        );
+        let small = r#"{"jsonrpc":"2.0","method":"initialized"}"#;
+        let frames: String = [large.as_str(), small]
+            .iter()
+            .map(|message| format!("Content-Length: {}\r\n\r\n{}", message.len(), message))
+            .collect();
+        Cursor::new(frames.into_bytes())
+    }
+
+    #[test]
+    fn skips_oversized_messages() {
+        let stdout = SharedBuffer::default();
+        let server = Server::new(oversized_stdin(), stdout.clone())
+            .interleave(stream::iter_ok(Vec::new()))
+            .max_message_len(256)
+            .skip_oversized_messages()
+            .serve(MockService);
+        current_thread::block_on_all(server).expect("failed to decode/encode message");
+
+        let output = stdout.0.lock().unwrap().clone();
+        let mut output = output.as_slice();
+        let error = read_message(&mut output).unwrap();
+        assert_eq!(
+            error,
+            r#"{"jsonrpc":"2.0","error":{"code":-32803,"message":"message of 1091 bytes exceeds maximum length"},"id":1}"#
+        );
+        let echoed = read_message(&mut output).unwrap();
+        assert_eq!(
+            echoed,
+            r#"{"jsonrpc":"2.0","method":"initialized","params":null}"#
+        );
+    }
+
+    #[test]
+    fn stops_on_oversized_message_by_default() {
+        let server = Server::new(oversized_stdin(), Cursor::new(Vec::new()))
+            .interleave(stream::iter_ok(Vec::new()))
+            .max_message_len(256)
+            .serve(MockService);
+        assert_eq!(current_thread::block_on_all(server), Err(()));
+    }
 }
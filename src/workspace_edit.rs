@@ -0,0 +1,326 @@
+//! Construction of workspace edits matching the capabilities of the client.
+
+use std::collections::HashMap;
+use std::fmt::{self, Display, Formatter};
+
+use lsp_types::{
+    ClientCapabilities, CreateFile, CreateFileOptions, DeleteFile, DeleteFileOptions,
+    DocumentChangeOperation, DocumentChanges, OneOf, OptionalVersionedTextDocumentIdentifier,
+    RenameFile, RenameFileOptions, ResourceOp, ResourceOperationKind, TextDocumentEdit, TextEdit,
+    Url, WorkspaceEdit,
+};
+
+/// Builds a `WorkspaceEdit` in the representation supported by the client.
+///
+/// A `WorkspaceEdit` either holds a plain map of text edits per URI in its `changes` field, or an
+/// array of versioned text document edits and resource operations in its `documentChanges`
+/// field. The latter is only understood by clients announcing the `documentChanges` workspace
+/// edit capability, and resource operations such as creating, renaming or deleting files are
+/// only understood by clients announcing the corresponding `resourceOperations`.
+///
+/// The builder records edits and operations in order and picks the representation once the edit
+/// is [`finish`](#method.finish)ed. Document versions are dropped when falling back to the
+/// `changes` map, since it cannot express them.
+///
+/// # Example
+///
+/// ```rust
+/// # use tower_lsp::lsp_types::{ClientCapabilities, Position, Range, TextEdit, Url};
+/// # use tower_lsp::WorkspaceEditBuilder;
+/// let uri = Url::parse("file:///src/lib.rs").unwrap();
+/// let range = Range::new(Position::new(0, 0), Position::new(0, 3));
+/// let edit = WorkspaceEditBuilder::new(&ClientCapabilities::default())
+///     .edit(uri.clone(), Some(2), vec![TextEdit::new(range, "bar".into())])
+///     .finish()
+///     .unwrap();
+/// assert_eq!(edit.changes.unwrap()[&uri].len(), 1);
+/// ```
+#[derive(Clone, Debug, Default)]
+pub struct WorkspaceEditBuilder {
+    document_changes: bool,
+    resource_operations: Vec<ResourceOperationKind>,
+    operations: Vec<DocumentChangeOperation>,
+}
+
+impl WorkspaceEditBuilder {
+    /// Creates a new, empty builder for a client with the given capabilities.
+    ///
+    /// The capabilities announced in the `initialize` request are available from
+    /// [`Printer::client_capabilities`], or via [`Printer::workspace_edit`] directly.
+    ///
+    /// [`Printer::client_capabilities`]: ./struct.Printer.html#method.client_capabilities
+    /// [`Printer::workspace_edit`]: ./struct.Printer.html#method.workspace_edit
+    pub fn new(capabilities: &ClientCapabilities) -> Self {
+        let workspace_edit = capabilities
+            .workspace
+            .as_ref()
+            .and_then(|workspace| workspace.workspace_edit.as_ref());
+
+        WorkspaceEditBuilder {
+            document_changes: workspace_edit
+                .and_then(|edit| edit.document_changes)
+                .unwrap_or(false),
+            resource_operations: workspace_edit
+                .and_then(|edit| edit.resource_operations.clone())
+                .unwrap_or_default(),
+            operations: Vec::new(),
+        }
+    }
+
+    /// Appends text edits to the document with the given URI.
+    ///
+    /// The `version` is the version of the document the edits were computed against, if known,
+    /// so that the client may reject them should the document have changed in the meantime.
+    pub fn edit(mut self, uri: Url, version: Option<i32>, edits: Vec<TextEdit>) -> Self {
+        self.operations
+            .push(DocumentChangeOperation::Edit(TextDocumentEdit {
+                text_document: OptionalVersionedTextDocumentIdentifier { uri, version },
+                edits: edits.into_iter().map(OneOf::Left).collect(),
+            }));
+        self
+    }
+
+    /// Appends an operation creating a file with the given URI.
+    pub fn create_file(mut self, uri: Url, options: Option<CreateFileOptions>) -> Self {
+        self.operations
+            .push(DocumentChangeOperation::Op(ResourceOp::Create(
+                CreateFile {
+                    uri,
+                    options,
+                    annotation_id: None,
+                },
+            )));
+        self
+    }
+
+    /// Appends an operation renaming the file with URI `old_uri` to `new_uri`.
+    pub fn rename_file(
+        mut self,
+        old_uri: Url,
+        new_uri: Url,
+        options: Option<RenameFileOptions>,
+    ) -> Self {
+        self.operations
+            .push(DocumentChangeOperation::Op(ResourceOp::Rename(
+                RenameFile {
+                    old_uri,
+                    new_uri,
+                    options,
+                    annotation_id: None,
+                },
+            )));
+        self
+    }
+
+    /// Appends an operation deleting the file with the given URI.
+    pub fn delete_file(mut self, uri: Url, options: Option<DeleteFileOptions>) -> Self {
+        self.operations
+            .push(DocumentChangeOperation::Op(ResourceOp::Delete(
+                DeleteFile { uri, options },
+            )));
+        self
+    }
+
+    /// Returns whether the client supports the given kind of resource operation.
+    pub fn supports(&self, kind: ResourceOperationKind) -> bool {
+        self.document_changes && self.resource_operations.contains(&kind)
+    }
+
+    /// Builds the workspace edit.
+    ///
+    /// Returns an error if a resource operation was appended which the client does not support.
+    pub fn finish(self) -> Result<WorkspaceEdit, UnsupportedOperation> {
+        for operation in &self.operations {
+            if let DocumentChangeOperation::Op(ref op) = operation {
+                let kind = match op {
+                    ResourceOp::Create(_) => ResourceOperationKind::Create,
+                    ResourceOp::Rename(_) => ResourceOperationKind::Rename,
+                    ResourceOp::Delete(_) => ResourceOperationKind::Delete,
+                };
+                if !self.supports(kind) {
+                    return Err(UnsupportedOperation(kind));
+                }
+            }
+        }
+
+        if self.document_changes {
+            let has_operations = self
+                .operations
+                .iter()
+                .any(|operation| matches!(operation, DocumentChangeOperation::Op(_)));
+            let document_changes = if has_operations {
+                DocumentChanges::Operations(self.operations)
+            } else {
+                let edits = self
+                    .operations
+                    .into_iter()
+                    .filter_map(|operation| match operation {
+                        DocumentChangeOperation::Edit(edit) => Some(edit),
+                        DocumentChangeOperation::Op(_) => None,
+                    });
+                DocumentChanges::Edits(edits.collect())
+            };
+            return Ok(WorkspaceEdit {
+                document_changes: Some(document_changes),
+                ..WorkspaceEdit::default()
+            });
+        }
+
+        let mut changes: HashMap<Url, Vec<TextEdit>> = HashMap::new();
+        for operation in self.operations {
+            if let DocumentChangeOperation::Edit(edit) = operation {
+                let edits = edit.edits.into_iter().map(|edit| match edit {
+                    OneOf::Left(edit) => edit,
+                    OneOf::Right(annotated) => annotated.text_edit,
+                });
+                changes
+                    .entry(edit.text_document.uri)
+                    .or_default()
+                    .extend(edits);
+            }
+        }
+
+        Ok(WorkspaceEdit {
+            changes: Some(changes),
+            ..WorkspaceEdit::default()
+        })
+    }
+}
+
+/// Error returned when building a workspace edit with a resource operation the client does not
+/// support.
+#[derive(Clone, Copy, Debug, Eq, PartialEq)]
+pub struct UnsupportedOperation(pub ResourceOperationKind);
+
+impl Display for UnsupportedOperation {
+    fn fmt(&self, f: &mut Formatter) -> fmt::Result {
+        let kind = match self.0 {
+            ResourceOperationKind::Create => "create",
+            ResourceOperationKind::Rename => "rename",
+            ResourceOperationKind::Delete => "delete",
+        };
+        write!(f, "client does not support `{}` resource operations", kind)
+    }
+}
+
+impl std::error::Error for UnsupportedOperation {}
+
+#[cfg(test)]
+mod tests {
+    use lsp_types::{
+        Position, Range, WorkspaceClientCapabilities, WorkspaceEditClientCapabilities,
+    };
+    use serde_json::json;
+
+    use super::*;
+
+    fn capabilities(
+        document_changes: bool,
+        resource_operations: Vec<ResourceOperationKind>,
+    ) -> ClientCapabilities {
+        ClientCapabilities {
+            workspace: Some(WorkspaceClientCapabilities {
+                workspace_edit: Some(WorkspaceEditClientCapabilities {
+                    document_changes: Some(document_changes),
+                    resource_operations: Some(resource_operations),
+                    ..WorkspaceEditClientCapabilities::default()
+                }),
+                ..WorkspaceClientCapabilities::default()
+            }),
+            ..ClientCapabilities::default()
+        }
+    }
+
+    fn text_edit(text: &str) -> TextEdit {
+        TextEdit::new(
+            Range::new(Position::new(0, 0), Position::new(0, 1)),
+            text.into(),
+        )
+    }
+
+    #[test]
+    fn falls_back_to_changes() {
+        let uri: Url = "file:///foo.rs".parse().unwrap();
+        let edit = WorkspaceEditBuilder::new(&capabilities(false, Vec::new()))
+            .edit(uri.clone(), Some(1), vec![text_edit("a")])
+            .edit(uri.clone(), Some(1), vec![text_edit("b")])
+            .finish()
+            .unwrap();
+
+        assert_eq!(edit.document_changes, None);
+        assert_eq!(
+            edit.changes.unwrap()[&uri],
+            vec![text_edit("a"), text_edit("b")]
+        );
+    }
+
+    #[test]
+    fn uses_versioned_document_changes() {
+        let uri: Url = "file:///foo.rs".parse().unwrap();
+        let edit = WorkspaceEditBuilder::new(&capabilities(true, Vec::new()))
+            .edit(uri, Some(3), vec![text_edit("a")])
+            .finish()
+            .unwrap();
+
+        assert_eq!(
+            serde_json::to_value(&edit).unwrap(),
+            json!({
+                "documentChanges": [{
+                    "textDocument": { "uri": "file:///foo.rs", "version": 3 },
+                    "edits": [{
+                        "range": {
+                            "start": { "line": 0, "character": 0 },
+                            "end": { "line": 0, "character": 1 },
+                        },
+                        "newText": "a",
+                    }],
+                }],
+            })
+        );
+    }
+
+    #[test]
+    fn orders_resource_operations() {
+        let old: Url = "file:///old.rs".parse().unwrap();
+        let new: Url = "file:///new.rs".parse().unwrap();
+        let kinds = vec![ResourceOperationKind::Create, ResourceOperationKind::Rename];
+        let edit = WorkspaceEditBuilder::new(&capabilities(true, kinds))
+            .rename_file(old.clone(), new.clone(), None)
+            .edit(new.clone(), None, vec![text_edit("a")])
+            .create_file(old, None)
+            .finish()
+            .unwrap();
+
+        let operations = match edit.document_changes {
+            Some(DocumentChanges::Operations(operations)) => operations,
+            other => panic!("unexpected document changes: {:?}", other),
+        };
+        let kinds: Vec<_> = operations
+            .iter()
+            .map(|operation| serde_json::to_value(operation).unwrap()["kind"].clone())
+            .collect();
+        assert_eq!(kinds, vec![json!("rename"), json!(null), json!("create")]);
+    }
+
+    #[test]
+    fn rejects_unsupported_operations() {
+        let uri: Url = "file:///foo.rs".parse().unwrap();
+        let supported = capabilities(true, vec![ResourceOperationKind::Create]);
+        let result = WorkspaceEditBuilder::new(&supported)
+            .create_file(uri.clone(), None)
+            .delete_file(uri.clone(), None)
+            .finish();
+        assert_eq!(
+            result,
+            Err(UnsupportedOperation(ResourceOperationKind::Delete))
+        );
+
+        let unversioned = capabilities(false, vec![ResourceOperationKind::Create]);
+        let builder = WorkspaceEditBuilder::new(&unversioned);
+        assert!(!builder.supports(ResourceOperationKind::Create));
+        assert_eq!(
+            builder.create_file(uri, None).finish(),
+            Err(UnsupportedOperation(ResourceOperationKind::Create))
+        );
+    }
+}